@@ -13,11 +13,12 @@ extern crate hmac;
 use minifb::{Key, Window, WindowOptions, MouseMode, MouseButton, KeyRepeat};
 use std::net::{SocketAddr, TcpStream};
 use std::io::{Read, Write};
-use std::time::{Instant};
+use std::time::{Instant, Duration};
 use std::ptr;
 use std::mem;
 use std::mem::{size_of, forget};
-use rdp::core::client::{RdpClient, Connector};
+use rdp::core::client::{RdpClient, Connector, ShutdownResult};
+use rdp::model::link::ReadTimeout;
 #[cfg(target_os = "windows")]
 use winapi::um::winsock2::{select, fd_set};
 #[cfg(target_os = "linux")]
@@ -30,9 +31,8 @@ use std::os::windows::io::{AsRawSocket};
 use std::os::unix::io::{AsRawFd};
 #[cfg(target_os = "macos")]
 use std::os::unix::io::{AsRawFd};
-use rdp::core::event::{RdpEvent, BitmapEvent, PointerEvent, PointerButton, KeyboardEvent};
+use rdp::core::event::{RdpEvent, BitmapEvent, SurfaceEvent, PointerEvent, PointerButton, PointerWheel, KeyboardEvent};
 use std::ptr::copy_nonoverlapping;
-use std::convert::TryFrom;
 use std::thread;
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread::{JoinHandle};
@@ -40,6 +40,13 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use rdp::model::error::{Error, RdpErrorKind, RdpError, RdpResult};
 use clap::{Arg, App, ArgMatches};
 use rdp::core::gcc::KeyboardLayout;
+use rdp::core::keyboard::{
+    vk_to_keystroke, VK_F1, VK_NUMPAD0, VK_ESCAPE, VK_BACK, VK_TAB, VK_RETURN, VK_SPACE,
+    VK_LSHIFT, VK_RSHIFT, VK_LCONTROL, VK_RCONTROL, VK_LMENU, VK_RMENU, VK_LWIN, VK_RWIN,
+    VK_APPS, VK_CAPITAL, VK_NUMLOCK, VK_SCROLL, VK_HOME, VK_END, VK_PRIOR, VK_NEXT,
+    VK_INSERT, VK_DELETE, VK_LEFT, VK_UP, VK_RIGHT, VK_DOWN, VK_MULTIPLY, VK_ADD,
+    VK_SUBTRACT, VK_DECIMAL, VK_DIVIDE
+};
 use std::sync::mpsc::{Receiver, Sender};
 
 const APPLICATION_NAME: &str = "mstsc-rs";
@@ -120,129 +127,113 @@ fn fast_bitmap_transfer(buffer: &mut Vec<u32>, width: usize, bitmap: BitmapEvent
     Ok(())
 }
 
-/// Translate minifb mouse to rdp-rs
-fn get_rdp_pointer_down(window: &Window) -> PointerButton {
-    if window.get_mouse_down(MouseButton::Left) {
-        PointerButton::Left
-    } else if window.get_mouse_down(MouseButton::Middle) {
-        PointerButton::Middle
-    } else if window.get_mouse_down(MouseButton::Right) {
-        PointerButton::Right
-    } else {
-        PointerButton::None
+/// Copy an already decoded surface event into the buffer
+fn surface_transfer(buffer: &mut Vec<u32>, width: usize, surface: SurfaceEvent) -> RdpResult<()> {
+    let surface_width = surface.width as usize;
+    let dest_left = surface.dest_left as usize;
+    let dest_top = surface.dest_top as usize;
+    if surface_width == 0 {
+        return Ok(())
+    }
+
+    for (i, line) in surface.data.chunks(surface_width * 4).enumerate() {
+        let dest_i = (i + dest_top) * width + dest_left;
+        if dest_left + surface_width > width || dest_i + surface_width > buffer.len() {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "Surface have invalid size")))
+        }
+        for (j, pixel) in line.chunks(4).enumerate() {
+            buffer[dest_i + j] = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Translate minifb mouse buttons to rdp-rs
+fn rdp_pointer_buttons() -> [(MouseButton, PointerButton); 3] {
+    [
+        (MouseButton::Left, PointerButton::Left),
+        (MouseButton::Middle, PointerButton::Middle),
+        (MouseButton::Right, PointerButton::Right)
+    ]
+}
+
+/// Translate minifb key to Windows virtual key code
+fn to_virtual_key(key: Key) -> Option<u8> {
+    let key_code = key as u8;
+    match key {
+        Key::Key0 | Key::Key1 | Key::Key2 | Key::Key3 | Key::Key4 |
+        Key::Key5 | Key::Key6 | Key::Key7 | Key::Key8 | Key::Key9 => Some(b'0' + key_code - Key::Key0 as u8),
+        Key::F1 | Key::F2 | Key::F3 | Key::F4 | Key::F5 | Key::F6 |
+        Key::F7 | Key::F8 | Key::F9 | Key::F10 | Key::F11 | Key::F12 => Some(VK_F1 + key_code - Key::F1 as u8),
+        Key::NumPad0 => Some(VK_NUMPAD0),
+        Key::NumPad1 => Some(VK_NUMPAD0 + 1),
+        Key::NumPad2 => Some(VK_NUMPAD0 + 2),
+        Key::NumPad3 => Some(VK_NUMPAD0 + 3),
+        Key::NumPad4 => Some(VK_NUMPAD0 + 4),
+        Key::NumPad5 => Some(VK_NUMPAD0 + 5),
+        Key::NumPad6 => Some(VK_NUMPAD0 + 6),
+        Key::NumPad7 => Some(VK_NUMPAD0 + 7),
+        Key::NumPad8 => Some(VK_NUMPAD0 + 8),
+        Key::NumPad9 => Some(VK_NUMPAD0 + 9),
+        Key::Escape => Some(VK_ESCAPE),
+        Key::Backspace => Some(VK_BACK),
+        Key::Tab => Some(VK_TAB),
+        Key::Enter => Some(VK_RETURN),
+        Key::Space => Some(VK_SPACE),
+        Key::LeftShift => Some(VK_LSHIFT),
+        Key::RightShift => Some(VK_RSHIFT),
+        Key::LeftCtrl => Some(VK_LCONTROL),
+        Key::RightCtrl => Some(VK_RCONTROL),
+        Key::LeftAlt => Some(VK_LMENU),
+        Key::RightAlt => Some(VK_RMENU),
+        Key::LeftSuper => Some(VK_LWIN),
+        Key::RightSuper => Some(VK_RWIN),
+        Key::Menu => Some(VK_APPS),
+        Key::CapsLock => Some(VK_CAPITAL),
+        Key::NumLock => Some(VK_NUMLOCK),
+        Key::ScrollLock => Some(VK_SCROLL),
+        Key::Home => Some(VK_HOME),
+        Key::End => Some(VK_END),
+        Key::PageUp => Some(VK_PRIOR),
+        Key::PageDown => Some(VK_NEXT),
+        Key::Insert => Some(VK_INSERT),
+        Key::Delete => Some(VK_DELETE),
+        Key::Left => Some(VK_LEFT),
+        Key::Up => Some(VK_UP),
+        Key::Right => Some(VK_RIGHT),
+        Key::Down => Some(VK_DOWN),
+        Key::NumPadAsterisk => Some(VK_MULTIPLY),
+        Key::NumPadPlus => Some(VK_ADD),
+        Key::NumPadMinus => Some(VK_SUBTRACT),
+        Key::NumPadDot => Some(VK_DECIMAL),
+        Key::NumPadSlash => Some(VK_DIVIDE),
+        // Letters are contiguous in minifb
+        _ if (Key::A as u8..=Key::Z as u8).contains(&key_code) => Some(b'A' + key_code - Key::A as u8),
+        _ => None
     }
 }
 
 /// Translate minifb key to scancode
-fn to_scancode(key: Key) -> u16 {
+/// Letters and digits follow the keyboard layout
+/// OEM keys keep their US position
+fn to_scancode(layout: KeyboardLayout, key: Key) -> Option<u16> {
     match key {
-        Key::Escape => 0x0001,
-        Key::Key1 => 0x0002,
-        Key::Key2 => 0x0003,
-        Key::Key3 => 0x0004,
-        Key::Key4 => 0x0005,
-        Key::Key5 => 0x0006,
-        Key::Key6 => 0x0007,
-        Key::Key7 => 0x0008,
-        Key::Key8 => 0x0009,
-        Key::Key9 => 0x000A,
-        Key::Key0 => 0x000B,
-        Key::Minus => 0x000C,
-        Key::Equal => 0x000D,
-        Key::Backspace => 0x000E,
-        Key::Tab => 0x000F,
-        Key::Q => 0x0010,
-        Key::W => 0x0011,
-        Key::E => 0x0012,
-        Key::R => 0x0013,
-        Key::T => 0x0014,
-        Key::Y => 0x0015,
-        Key::U => 0x0016,
-        Key::I => 0x0017,
-        Key::O => 0x0018,
-        Key::P => 0x0019,
-        Key::LeftBracket => 0x001A,
-        Key::RightBracket => 0x001B,
-        Key::Enter => 0x001C,
-        Key::LeftCtrl => 0x001D,
-        Key::A => 0x001E,
-        Key::S => 0x001F,
-        Key::D => 0x0020,
-        Key::F => 0x0021,
-        Key::G => 0x0022,
-        Key::H => 0x0023,
-        Key::J => 0x0024,
-        Key::K => 0x0025,
-        Key::L => 0x0026,
-        Key::Semicolon => 0x0027,
-        Key::Apostrophe => 0x0028,
-        Key::Backquote => 0x0029,
-        Key::LeftShift => 0x002A,
-        Key::Backslash => 0x002B,
-        Key::Z => 0x002C,
-        Key::X => 0x002D,
-        Key::C => 0x002E,
-        Key::V => 0x002F,
-        Key::B => 0x0030,
-        Key::N => 0x0031,
-        Key::M => 0x0032,
-        Key::Comma => 0x0033,
-        Key::Period => 0x0034,
-        Key::Slash => 0x0035,
-        Key::RightShift => 0x0036,
-        Key::NumPadAsterisk => 0x0037,
-        Key::LeftAlt => 0x0038,
-        Key::Space => 0x0039,
-        Key::CapsLock => 0x003A,
-        Key::F1 => 0x003B,
-        Key::F2 => 0x003C,
-        Key::F3 => 0x003D,
-        Key::F4 => 0x003E,
-        Key::F5 => 0x003F,
-        Key::F6 => 0x0040,
-        Key::F7 => 0x0041,
-        Key::F8 => 0x0042,
-        Key::F9 => 0x0043,
-        Key::F10 => 0x0044,
-        Key::Pause => 0x0045,
-        Key::ScrollLock => 0x0046,
-        Key::NumPad7 => 0x0047,
-        Key::NumPad8 => 0x0048,
-        Key::NumPad9 => 0x0049,
-        Key::NumPadMinus => 0x004A,
-        Key::NumPad4 => 0x004B,
-        Key::NumPad5 => 0x004C,
-        Key::NumPad6 => 0x004D,
-        Key::NumPadPlus => 0x004E,
-        Key::NumPad1 => 0x004F,
-        Key::NumPad2 => 0x0050,
-        Key::NumPad3 => 0x0051,
-        Key::NumPad0 => 0x0052,
-        Key::NumPadDot => 0x0053,
-        Key::F11 => 0x0057,
-        Key::F12 => 0x0058,
-        Key::F13 => 0x0064,
-        Key::F14 => 0x0065,
-        Key::F15 => 0x0066,
-        Key::NumPadEnter => 0xE01C,
-        Key::RightCtrl => 0xE01D,
-        Key::NumPadSlash => 0xE035,
-        Key::RightAlt => 0xE038,
-        Key::NumLock => 0xE045,
-        Key::Home => 0xE047,
-        Key::Up => 0xE048,
-        Key::PageUp => 0xE049,
-        Key::Left => 0xE04B,
-        Key::Right => 0xE04D,
-        Key::End => 0xE04F,
-        Key::Down => 0xE050,
-        Key::PageDown => 0xE051,
-        Key::Insert => 0xE052,
-        Key::Delete => 0xE053,
-        Key::LeftSuper => 0xE05B,
-        Key::RightSuper => 0xE05C,
-        Key::Menu => 0xE05D,
-        _ => panic!("foo")
+        Key::Minus => Some(0x000C),
+        Key::Equal => Some(0x000D),
+        Key::LeftBracket => Some(0x001A),
+        Key::RightBracket => Some(0x001B),
+        Key::Semicolon => Some(0x0027),
+        Key::Apostrophe => Some(0x0028),
+        Key::Backquote => Some(0x0029),
+        Key::Backslash => Some(0x002B),
+        Key::Comma => Some(0x0033),
+        Key::Period => Some(0x0034),
+        Key::Slash => Some(0x0035),
+        Key::NumPadEnter => Some(0xE01C),
+        _ => to_virtual_key(key)
+            .and_then(|vk| vk_to_keystroke(layout, vk))
+            .map(|stroke| stroke.code())
     }
 }
 
@@ -340,22 +331,27 @@ fn launch_rdp_thread<S: 'static + Read + Write + Send>(
     handle: usize,
     rdp_client: Arc<Mutex<RdpClient<S>>>,
     sync: Arc<AtomicBool>,
-    bitmap_channel: Sender<BitmapEvent>) -> RdpResult<JoinHandle<()>> {
+    bitmap_channel: Sender<RdpEvent>) -> RdpResult<JoinHandle<()>> {
     // Create the rdp thread
     Ok(thread::spawn(move || {
         while wait_for_fd(handle as usize) && sync.load(Ordering::Relaxed) {
             let mut guard = rdp_client.lock().unwrap();
             if let Err(Error::RdpError(e)) = guard.read(|event| {
                 match event {
-                    RdpEvent::Bitmap(bitmap) => {
-                        bitmap_channel.send(bitmap).unwrap();
+                    RdpEvent::Bitmap(_) | RdpEvent::Surface(_) | RdpEvent::Resize(_) => {
+                        bitmap_channel.send(event).unwrap();
                     },
+                    // surface events are drawn as soon as received
+                    RdpEvent::FrameMarker(_) => (),
                     _ => println!("{}: ignore event", APPLICATION_NAME)
                 }
             }) {
                 match e.kind() {
                     RdpErrorKind::Disconnect => {
-                        println!("{}: Server ask for disconnect", APPLICATION_NAME);
+                        match e.error_info() {
+                            Some(reason) => println!("{}: Server ask for disconnect {:?}", APPLICATION_NAME, reason),
+                            None => println!("{}: Server ask for disconnect", APPLICATION_NAME)
+                        }
                     },
                     _ => println!("{}: {:?}", APPLICATION_NAME, e)
                 }
@@ -368,13 +364,14 @@ fn launch_rdp_thread<S: 'static + Read + Write + Send>(
 /// This is the main loop
 /// Print Window and handle all input (mous + keyboard)
 /// to RDP
-fn main_gui_loop<S: Read + Write>(
+fn main_gui_loop<S: Read + Write + ReadTimeout>(
     mut window: Window,
+    layout: KeyboardLayout,
     rdp_client: Arc<Mutex<RdpClient<S>>>,
     sync: Arc<AtomicBool>,
-    bitmap_receiver: Receiver<BitmapEvent>) -> RdpResult<()> {
+    bitmap_receiver: Receiver<RdpEvent>) -> RdpResult<()> {
 
-    let (width, height) = window.get_size();
+    let (mut width, mut height) = window.get_size();
     // Now we continue with the graphical main thread
     // Limit to max ~60 fps update rate
     window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
@@ -382,8 +379,8 @@ fn main_gui_loop<S: Read + Write>(
     // The window buffer
     let mut buffer: Vec<u32> = vec![0; width * height];
 
-    // State for mouse button
-    let mut last_button = PointerButton::None;
+    // State of each mouse button
+    let mut last_buttons = [false; 3];
 
     // state for keyboard keys
     let mut last_keys = vec![];
@@ -395,7 +392,15 @@ fn main_gui_loop<S: Read + Write>(
         // Refresh loop must faster than 30 Hz
         while now.elapsed().as_micros() < 16600 * 2 {
             match bitmap_receiver.try_recv() {
-                Ok(bitmap) => fast_bitmap_transfer(&mut buffer, width, bitmap)?,
+                Ok(RdpEvent::Bitmap(bitmap)) => fast_bitmap_transfer(&mut buffer, width, bitmap)?,
+                Ok(RdpEvent::Surface(surface)) => surface_transfer(&mut buffer, width, surface)?,
+                // Server changed the desktop size
+                Ok(RdpEvent::Resize(resize)) => {
+                    width = resize.width as usize;
+                    height = resize.height as usize;
+                    buffer = vec![0; width * height];
+                },
+                Ok(_) => (),
                 Err(mpsc::TryRecvError::Empty) => break,
                 Err(mpsc::TryRecvError::Disconnected) => {
                     sync.store(false, Ordering::Relaxed);
@@ -410,18 +415,41 @@ fn main_gui_loop<S: Read + Write>(
                 Error::RdpError(RdpError::new(RdpErrorKind::Unknown, &format!("Thread error during access to mutex [{}]", e)))
             })?;
 
-            // Button is down if not 0
-            let current_button = get_rdp_pointer_down(&window);
-            rdp_client_guard.write(RdpEvent::Pointer(
-                PointerEvent{
-                    x: x as u16,
-                    y: y as u16,
-                    button: if last_button == current_button { PointerButton::None } else { PointerButton::try_from(last_button as u8 | current_button as u8).unwrap() },
-                    down: (last_button != current_button) && last_button == PointerButton::None
-                })
-            )?;
-
-            last_button = current_button;
+            // One event by button that changed state
+            // or a simple move
+            let mut events = vec![];
+            for (last_down, (mouse_button, button)) in last_buttons.iter_mut().zip(rdp_pointer_buttons().iter()) {
+                let down = window.get_mouse_down(*mouse_button);
+                if down != *last_down {
+                    events.push((*button, down, PointerWheel::None));
+                    *last_down = down;
+                }
+            }
+            if events.is_empty() {
+                events.push((PointerButton::None, false, PointerWheel::None));
+            }
+
+            // Mouse wheel, one notch is 120 units
+            if let Some((horizontal, vertical)) = window.get_scroll_wheel() {
+                if vertical != 0.0 {
+                    events.push((PointerButton::None, false, PointerWheel::Vertical((vertical * 120.0) as i16)));
+                }
+                if horizontal != 0.0 {
+                    events.push((PointerButton::None, false, PointerWheel::Horizontal((horizontal * 120.0) as i16)));
+                }
+            }
+
+            for (button, down, wheel) in events {
+                rdp_client_guard.write(RdpEvent::Pointer(
+                    PointerEvent {
+                        x: x as u16,
+                        y: y as u16,
+                        button,
+                        down,
+                        wheel
+                    })
+                )?;
+            }
         }
 
         // Keyboard inputs
@@ -430,23 +458,27 @@ fn main_gui_loop<S: Read + Write>(
 
             for key in last_keys.iter() {
                 if !keys.contains(key) {
-                    rdp_client_guard.write(RdpEvent::Key(
-                        KeyboardEvent {
-                            code: to_scancode(*key),
-                            down: false
-                        })
-                    )?
+                    if let Some(code) = to_scancode(layout, *key) {
+                        rdp_client_guard.write(RdpEvent::Key(
+                            KeyboardEvent {
+                                code,
+                                down: false
+                            })
+                        )?
+                    }
                 }
             }
 
             for key in keys.iter() {
                 if window.is_key_pressed(*key, KeyRepeat::Yes){
-                    rdp_client_guard.write(RdpEvent::Key(
-                        KeyboardEvent {
-                            code: to_scancode(*key),
-                            down: true
-                        })
-                    )?
+                    if let Some(code) = to_scancode(layout, *key) {
+                        rdp_client_guard.write(RdpEvent::Key(
+                            KeyboardEvent {
+                                code,
+                                down: true
+                            })
+                        )?
+                    }
                 }
             }
 
@@ -459,8 +491,17 @@ fn main_gui_loop<S: Read + Write>(
         })?;
     }
 
+    // Window closed by the user, ask the server first
+    let window_closed = !window.is_open();
     sync.store(false, Ordering::Relaxed);
-    rdp_client.lock().unwrap().shutdown()?;
+    let mut rdp_client_guard = rdp_client.lock().unwrap();
+    if window_closed {
+        match rdp_client_guard.request_shutdown(Duration::from_secs(5), |_| ())? {
+            ShutdownResult::Logoff | ShutdownResult::Disconnect => return Ok(()),
+            ShutdownResult::Denied | ShutdownResult::Timeout => ()
+        }
+    }
+    rdp_client_guard.shutdown()?;
     Ok(())
 }
 
@@ -515,7 +556,7 @@ fn main() {
                  .long("layout")
                  .takes_value(true)
                  .default_value("us")
-                 .help("Keyboard layout: us or fr"))
+                 .help("Keyboard layout: ar, bg, zh, cs, da, de, el, us, es, fi, fr, he, hu, is, it, ja, ko, nl or no"))
         .arg(Arg::with_name("auto_logon")
                  .long("auto")
                  .help("AutoLogon mode in case of SSL nego"))
@@ -573,6 +614,7 @@ fn main() {
     // Launch the GUI
     main_gui_loop(
         window,
+        KeyboardLayout::from(matches.value_of("layout").unwrap_or_default()),
         rdp_client_mutex,
         sync,
         bitmap_receiver
@@ -0,0 +1,65 @@
+use codec::mppc::{MppcDecompressor, CompressionType, CompressionFlag};
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+use std::convert::TryFrom;
+
+/// Bulk decompressor shared by fast path
+/// and slow path updates
+///
+/// The compression type is read from flags of each packet
+///
+/// # Example
+/// ```
+/// use rdp::codec::bulk::BulkDecompressor;
+/// use rdp::codec::mppc::{CompressionType, CompressionFlag};
+/// let mut bulk = BulkDecompressor::new();
+/// let flags = CompressionFlag::PacketCompressed as u8 | CompressionFlag::PacketFlushed as u8 | CompressionType::PacketComprType64k as u8;
+/// assert_eq!(bulk.decompress(b"abc", flags, 3).unwrap(), b"abc");
+/// assert!(bulk.decompress(b"abc", flags, 2).is_err());
+/// ```
+pub struct BulkDecompressor {
+    /// MPPC context, created on first use
+    mppc: Option<MppcDecompressor>
+}
+
+impl BulkDecompressor {
+    pub fn new() -> Self {
+        BulkDecompressor {
+            mppc: None
+        }
+    }
+
+    /// Decompress a payload using flags sent by the server
+    /// max_size is the uncompressed length expected by the caller
+    pub fn decompress(&mut self, input: &[u8], flags: u8, max_size: usize) -> RdpResult<Vec<u8>> {
+        let output = self.decompress_packet(input, flags)?;
+        if output.len() > max_size {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "BULK: Decompressed data exceed the uncompressed length")))
+        }
+        Ok(output)
+    }
+
+    /// Dispatch a payload to the decompressor of its compression type
+    fn decompress_packet(&mut self, input: &[u8], flags: u8) -> RdpResult<Vec<u8>> {
+        // Nothing to do with history
+        if flags & (CompressionFlag::PacketCompressed as u8 | CompressionFlag::PacketAtFront as u8 | CompressionFlag::PacketFlushed as u8) == 0 {
+            return Ok(input.to_vec())
+        }
+
+        let compression_type = CompressionType::try_from(flags & CompressionFlag::CompressionTypeMask as u8)?;
+        match compression_type {
+            CompressionType::PacketComprType8k | CompressionType::PacketComprType64k => {
+                if self.mppc.as_ref().map(|mppc| mppc.get_compression_type()) != Some(compression_type) {
+                    self.mppc = Some(MppcDecompressor::new(compression_type));
+                }
+                self.mppc.as_mut().unwrap().decompress(input, flags)
+            },
+            _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, &format!("BULK: Compression type not implemented {:?}", compression_type))))
+        }
+    }
+}
+
+impl Default for BulkDecompressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
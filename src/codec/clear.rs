@@ -0,0 +1,463 @@
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+use codec::nsc::nsc_decompress;
+use codec::surface::{SurfaceDecoder, SurfaceRect};
+use std::io::{Cursor, Read};
+use byteorder::{ReadBytesExt, LittleEndian};
+use num_enum::TryFromPrimitive;
+use std::convert::TryFrom;
+
+/// Number of entries of the glyph cache
+const CLEARCODEC_GLYPH_CACHE_SIZE: usize = 4000;
+
+/// Glyphs are only cached for small bitmaps
+const CLEARCODEC_GLYPH_MAX_PIXELS: usize = 1024;
+
+/// Number of entries of the vertical bar cache
+const CLEARCODEC_VBAR_CACHE_SIZE: usize = 32768;
+
+/// Number of entries of the short vertical bar cache
+const CLEARCODEC_SHORT_VBAR_CACHE_SIZE: usize = 16384;
+
+/// Maximum height of a band
+const CLEARCODEC_VBAR_MAX_HEIGHT: usize = 52;
+
+/// Flags of the bitmap stream header
+///
+/// See MS-RDPEGFX 2.2.4.1 CLEARCODEC_BITMAP_STREAM
+#[repr(u8)]
+#[derive(Copy, Clone)]
+pub enum GlyphFlag {
+    GlyphIndex = 0x01,
+    GlyphHit = 0x02,
+    CacheReset = 0x04
+}
+
+/// Codec used by a subcodec rectangle
+///
+/// See MS-RDPEGFX 2.2.4.1.1.3.1 CLEARCODEC_SUBCODEC
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, TryFromPrimitive)]
+pub enum SubcodecId {
+    Uncompressed = 0x00,
+    Nscodec = 0x01,
+    Rlex = 0x02
+}
+
+/// Read a BGR triplet as an opaque BGRA pixel
+fn read_pixel(stream: &mut dyn Read) -> RdpResult<[u8; 4]> {
+    let mut pixel = [0xff_u8; 4];
+    stream.read_exact(&mut pixel[..3])?;
+    Ok(pixel)
+}
+
+/// Run length encoded on 1, 3 or 7 bytes
+fn read_run_length(stream: &mut dyn Read) -> RdpResult<usize> {
+    let factor = stream.read_u8()?;
+    if factor != 0xFF {
+        return Ok(factor as usize)
+    }
+    let factor = stream.read_u16::<LittleEndian>()?;
+    if factor != 0xFFFF {
+        return Ok(factor as usize)
+    }
+    Ok(stream.read_u32::<LittleEndian>()? as usize)
+}
+
+/// Residual layer, run length encoded colors
+/// covering the whole bitmap
+///
+/// See MS-RDPEGFX 2.2.4.1.1.1 CLEARCODEC_RESIDUAL_DATA
+fn read_residual(data: &[u8], output: &mut [u8]) -> RdpResult<()> {
+    let mut stream = Cursor::new(data);
+    let mut position = 0;
+    while (stream.position() as usize) < data.len() {
+        let pixel = read_pixel(&mut stream)?;
+        let run_length = read_run_length(&mut stream)?;
+        if run_length > output.len() / 4 - position {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Residual run overflow bitmap")))
+        }
+        for chunk in output[position * 4..(position + run_length) * 4].chunks_mut(4) {
+            chunk.copy_from_slice(&pixel);
+        }
+        position += run_length;
+    }
+
+    if position * 4 != output.len() {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Residual doesn't cover bitmap")))
+    }
+    Ok(())
+}
+
+/// RLEX subcodec, palette colors
+/// encoded as runs followed by suites of consecutive indexes
+///
+/// See MS-RDPEGFX 2.2.4.1.1.3.1.1 CLEARCODEC_SUBCODEC_RLEX
+fn read_rlex(data: &[u8], output: &mut [u8]) -> RdpResult<()> {
+    let mut stream = Cursor::new(data);
+    let palette_count = stream.read_u8()? as usize;
+    if palette_count == 0 || palette_count > 127 {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Invalid RLEX palette count")))
+    }
+    let mut palette = Vec::with_capacity(palette_count);
+    for _ in 0..palette_count {
+        palette.push(read_pixel(&mut stream)?);
+    }
+
+    // bits needed to store the last palette index
+    let num_bits = (8 - ((palette_count - 1) as u8).leading_zeros()).max(1);
+    let pixel_count = output.len() / 4;
+    let mut position = 0;
+    while (stream.position() as usize) < data.len() {
+        let indexes = stream.read_u8()?;
+        let run_length = read_run_length(&mut stream)?;
+        let stop_index = (indexes & ((1 << num_bits) - 1) as u8) as usize;
+        let suite_depth = (indexes >> num_bits) as usize;
+        if stop_index >= palette_count || suite_depth > stop_index {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Invalid RLEX palette index")))
+        }
+        if run_length + suite_depth + 1 > pixel_count - position {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: RLEX segment overflow bitmap")))
+        }
+
+        let start_index = stop_index - suite_depth;
+        // repeat_n needs Rust 1.82
+        #[allow(clippy::manual_repeat_n)]
+        let colors = std::iter::repeat(start_index).take(run_length).chain(start_index..=stop_index);
+        for (chunk, index) in output[position * 4..].chunks_mut(4).zip(colors) {
+            chunk.copy_from_slice(&palette[index]);
+            position += 1;
+        }
+    }
+
+    if position != pixel_count {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: RLEX doesn't cover rectangle")))
+    }
+    Ok(())
+}
+
+/// Subcodec layer, rectangles encoded with other codecs
+///
+/// See MS-RDPEGFX 2.2.4.1.1.3 CLEARCODEC_SUBCODECS
+fn read_subcodecs(data: &[u8], width: usize, height: usize, output: &mut [u8]) -> RdpResult<()> {
+    let mut stream = Cursor::new(data);
+    while (stream.position() as usize) < data.len() {
+        let x = stream.read_u16::<LittleEndian>()? as usize;
+        let y = stream.read_u16::<LittleEndian>()? as usize;
+        let subcodec_width = stream.read_u16::<LittleEndian>()? as usize;
+        let subcodec_height = stream.read_u16::<LittleEndian>()? as usize;
+        let byte_count = stream.read_u32::<LittleEndian>()? as usize;
+        let subcodec_id = SubcodecId::try_from(stream.read_u8()?).map_err(|_| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Unknown subcodec")))?;
+
+        let start = stream.position() as usize;
+        if byte_count > data.len() - start {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "CLEAR: Subcodec overflow stream")))
+        }
+        let bitmap_data = &data[start..start + byte_count];
+        stream.set_position((start + byte_count) as u64);
+
+        if x + subcodec_width > width || y + subcodec_height > height {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Subcodec overflow bitmap")))
+        }
+        if subcodec_width == 0 || subcodec_height == 0 {
+            continue;
+        }
+
+        let mut rect = vec![0_u8; subcodec_width * subcodec_height * 4];
+        match subcodec_id {
+            SubcodecId::Uncompressed => {
+                if byte_count != subcodec_width * subcodec_height * 3 {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "CLEAR: Invalid uncompressed subcodec size")))
+                }
+                for (chunk, pixel) in rect.chunks_mut(4).zip(bitmap_data.chunks(3)) {
+                    chunk.copy_from_slice(&[pixel[0], pixel[1], pixel[2], 0xff]);
+                }
+            },
+            SubcodecId::Nscodec => nsc_decompress(bitmap_data, subcodec_width, subcodec_height, &mut rect)?,
+            SubcodecId::Rlex => read_rlex(bitmap_data, &mut rect)?
+        }
+
+        for (row, pixels) in rect.chunks(subcodec_width * 4).enumerate() {
+            let destination = ((y + row) * width + x) * 4;
+            output[destination..destination + pixels.len()].copy_from_slice(pixels);
+        }
+    }
+    Ok(())
+}
+
+/// ClearCodec decoder
+/// Keep the glyph and vertical bar caches between bitmaps
+/// Output is 32 bpp BGRA, top-down
+///
+/// See MS-RDPEGFX 3.3.8.1 ClearCodec
+///
+/// # Example
+/// ```
+/// use rdp::codec::clear::ClearDecoder;
+/// let mut decoder = ClearDecoder::new();
+/// // only a residual layer with one blue run
+/// let input = [0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0, 0, 2];
+/// assert_eq!(decoder.decode(&input, 2, 1).unwrap(), [0xff, 0, 0, 0xff, 0xff, 0, 0, 0xff]);
+/// ```
+pub struct ClearDecoder {
+    /// Pixels of cached glyphs
+    glyphs: Vec<Option<Vec<u8>>>,
+    /// Cached vertical bars of a band
+    vbars: Vec<Vec<u8>>,
+    /// Next vertical bar entry to fill
+    vbar_cursor: usize,
+    /// Cached short vertical bars (first line, pixels)
+    short_vbars: Vec<(usize, Vec<u8>)>,
+    /// Next short vertical bar entry to fill
+    short_vbar_cursor: usize
+}
+
+impl ClearDecoder {
+    pub fn new() -> Self {
+        ClearDecoder {
+            glyphs: vec![None; CLEARCODEC_GLYPH_CACHE_SIZE],
+            vbars: vec![Vec::new(); CLEARCODEC_VBAR_CACHE_SIZE],
+            vbar_cursor: 0,
+            short_vbars: vec![(0, Vec::new()); CLEARCODEC_SHORT_VBAR_CACHE_SIZE],
+            short_vbar_cursor: 0
+        }
+    }
+
+    /// Decode a CLEARCODEC_BITMAP_STREAM of width x height pixels
+    pub fn decode(&mut self, input: &[u8], width: u16, height: u16) -> RdpResult<Vec<u8>> {
+        let (width, height) = (width as usize, height as usize);
+        let mut stream = Cursor::new(input);
+        let glyph_flags = stream.read_u8()?;
+        // sequence number
+        stream.read_u8()?;
+
+        if glyph_flags & GlyphFlag::CacheReset as u8 != 0 {
+            self.vbar_cursor = 0;
+            self.short_vbar_cursor = 0;
+        }
+
+        let glyph_index = if glyph_flags & GlyphFlag::GlyphIndex as u8 != 0 {
+            let glyph_index = stream.read_u16::<LittleEndian>()? as usize;
+            if glyph_index >= CLEARCODEC_GLYPH_CACHE_SIZE || width * height > CLEARCODEC_GLYPH_MAX_PIXELS {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Invalid glyph")))
+            }
+            Some(glyph_index)
+        } else {
+            None
+        };
+
+        if glyph_flags & GlyphFlag::GlyphHit as u8 != 0 {
+            return match glyph_index.and_then(|index| self.glyphs[index].as_ref()) {
+                Some(glyph) if glyph.len() >= width * height * 4 => Ok(glyph[..width * height * 4].to_vec()),
+                _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Glyph not in cache")))
+            }
+        }
+
+        let residual_byte_count = stream.read_u32::<LittleEndian>()? as usize;
+        let bands_byte_count = stream.read_u32::<LittleEndian>()? as usize;
+        let subcodec_byte_count = stream.read_u32::<LittleEndian>()? as usize;
+
+        let start = stream.position() as usize;
+        if residual_byte_count + bands_byte_count + subcodec_byte_count > input.len() - start {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "CLEAR: Layers overflow stream")))
+        }
+        let (residual, remaining) = input[start..].split_at(residual_byte_count);
+        let (bands, remaining) = remaining.split_at(bands_byte_count);
+        let subcodecs = &remaining[..subcodec_byte_count];
+
+        let mut output = [0, 0, 0, 0xff].repeat(width * height);
+        if !residual.is_empty() {
+            read_residual(residual, &mut output)?;
+        }
+        self.read_bands(bands, width, height, &mut output)?;
+        read_subcodecs(subcodecs, width, height, &mut output)?;
+
+        if let Some(index) = glyph_index {
+            self.glyphs[index] = Some(output.clone());
+        }
+        Ok(output)
+    }
+
+    /// Bands layer, columns of vertical bars
+    /// drawn over a background color
+    ///
+    /// See MS-RDPEGFX 2.2.4.1.1.2 CLEARCODEC_BANDS_DATA
+    fn read_bands(&mut self, data: &[u8], width: usize, height: usize, output: &mut [u8]) -> RdpResult<()> {
+        let mut stream = Cursor::new(data);
+        while (stream.position() as usize) < data.len() {
+            let x_start = stream.read_u16::<LittleEndian>()? as usize;
+            let x_end = stream.read_u16::<LittleEndian>()? as usize;
+            let y_start = stream.read_u16::<LittleEndian>()? as usize;
+            let y_end = stream.read_u16::<LittleEndian>()? as usize;
+            let background = read_pixel(&mut stream)?;
+
+            if x_end < x_start || y_end < y_start || y_end - y_start + 1 > CLEARCODEC_VBAR_MAX_HEIGHT {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Invalid band")))
+            }
+            let vbar_height = y_end - y_start + 1;
+
+            for x in x_start..=x_end {
+                let vbar = self.read_vbar(&mut stream, vbar_height, &background)?;
+                if vbar.len() != vbar_height * 4 {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Vertical bar height mismatch")))
+                }
+                if x >= width {
+                    continue;
+                }
+                for (y, pixel) in (y_start..height.min(y_end + 1)).zip(vbar.chunks(4)) {
+                    output[(y * width + x) * 4..(y * width + x + 1) * 4].copy_from_slice(pixel);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a vertical bar from stream or caches
+    /// Vertical bars built from short vertical bars are cached
+    fn read_vbar(&mut self, stream: &mut dyn Read, vbar_height: usize, background: &[u8; 4]) -> RdpResult<Vec<u8>> {
+        let header = stream.read_u16::<LittleEndian>()?;
+        let (y_on, pixels) = match header & 0xC000 {
+            // vertical bar cache hit
+            0x8000 | 0xC000 => {
+                return Ok(self.vbars[(header & 0x7FFF) as usize].clone())
+            },
+            // short vertical bar cache hit
+            0x4000 => {
+                let (_, pixels) = &self.short_vbars[(header & 0x3FFF) as usize];
+                (stream.read_u8()? as usize, pixels.clone())
+            },
+            // short vertical bar cache miss
+            _ => {
+                let y_on = (header & 0xFF) as usize;
+                let y_off = ((header >> 8) & 0x3F) as usize;
+                if y_off < y_on {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Invalid short vertical bar")))
+                }
+                let mut pixels = Vec::with_capacity((y_off - y_on) * 4);
+                for _ in y_on..y_off {
+                    pixels.extend_from_slice(&read_pixel(stream)?);
+                }
+                self.short_vbars[self.short_vbar_cursor] = (y_on, pixels.clone());
+                self.short_vbar_cursor = (self.short_vbar_cursor + 1) % CLEARCODEC_SHORT_VBAR_CACHE_SIZE;
+                (y_on, pixels)
+            }
+        };
+
+        if y_on * 4 + pixels.len() > vbar_height * 4 {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "CLEAR: Short vertical bar overflow band")))
+        }
+        let mut vbar = background.repeat(vbar_height);
+        vbar[y_on * 4..y_on * 4 + pixels.len()].copy_from_slice(&pixels);
+
+        self.vbars[self.vbar_cursor] = vbar.clone();
+        self.vbar_cursor = (self.vbar_cursor + 1) % CLEARCODEC_VBAR_CACHE_SIZE;
+        Ok(vbar)
+    }
+}
+
+impl Default for ClearDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SurfaceDecoder for ClearDecoder {
+    fn decode(&mut self, width: u16, height: u16, input: &[u8]) -> RdpResult<Vec<SurfaceRect>> {
+        Ok(vec![SurfaceRect {
+            left: 0,
+            top: 0,
+            width,
+            height,
+            data: ClearDecoder::decode(self, input, width, height)?
+        }])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Stream header followed by layers
+    fn bitmap_stream(glyph_flags: u8, residual: &[u8], bands: &[u8], subcodecs: &[u8]) -> Vec<u8> {
+        let mut result = vec![glyph_flags, 0];
+        if glyph_flags & GlyphFlag::GlyphIndex as u8 != 0 {
+            result.extend_from_slice(&[5, 0]);
+        }
+        for layer in [residual, bands, subcodecs].iter() {
+            result.extend_from_slice(&(layer.len() as u32).to_le_bytes());
+        }
+        for layer in [residual, bands, subcodecs].iter() {
+            result.extend_from_slice(layer);
+        }
+        result
+    }
+
+    /// Vertical bars are cached and reused by later bitmaps
+    #[test]
+    fn test_clear_bands() {
+        let mut decoder = ClearDecoder::new();
+        // short vbar cache miss (yOn 0, yOff 1) then short vbar cache hit (yOn 1)
+        let bands = [0, 0, 1, 0, 0, 0, 1, 0, 9, 9, 9, 0x00, 0x01, 1, 2, 3, 0x00, 0x40, 1];
+        let output = decoder.decode(&bitmap_stream(0, &[], &bands, &[]), 2, 2).unwrap();
+        assert_eq!(output, [1, 2, 3, 0xff, 9, 9, 9, 0xff, 9, 9, 9, 0xff, 1, 2, 3, 0xff]);
+
+        // vbar cache hits in reverse order
+        let bands = [0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0x01, 0x80, 0x00, 0x80];
+        let output = decoder.decode(&bitmap_stream(0, &[], &bands, &[]), 2, 2).unwrap();
+        assert_eq!(output, [9, 9, 9, 0xff, 1, 2, 3, 0xff, 1, 2, 3, 0xff, 9, 9, 9, 0xff]);
+    }
+
+    /// RLEX subcodec over a residual layer
+    #[test]
+    fn test_clear_rlex() {
+        let mut decoder = ClearDecoder::new();
+        // one run of index 0, then suite from index 0 to 1
+        let subcodecs = [1, 0, 0, 0, 3, 0, 1, 0, 9, 0, 0, 0, 2, 2, 1, 1, 1, 2, 2, 2, 3, 1];
+        let output = decoder.decode(&bitmap_stream(0, &[7, 7, 7, 4], &[], &subcodecs), 4, 1).unwrap();
+        assert_eq!(output, [7, 7, 7, 0xff, 1, 1, 1, 0xff, 1, 1, 1, 0xff, 2, 2, 2, 0xff]);
+    }
+
+    /// Glyph hit returns the cached bitmap
+    #[test]
+    fn test_clear_glyph() {
+        let mut decoder = ClearDecoder::new();
+        let subcodecs = [0, 0, 0, 0, 1, 0, 1, 0, 3, 0, 0, 0, 0, 4, 5, 6];
+        let glyph = decoder.decode(&bitmap_stream(GlyphFlag::GlyphIndex as u8, &[], &[], &subcodecs), 1, 1).unwrap();
+        assert_eq!(glyph, [4, 5, 6, 0xff]);
+        assert_eq!(decoder.decode(&[GlyphFlag::GlyphIndex as u8 | GlyphFlag::GlyphHit as u8, 1, 5, 0], 1, 1).unwrap(), glyph);
+    }
+
+    /// Residual layer must cover the whole bitmap
+    #[test]
+    fn test_clear_short_residual() {
+        let mut decoder = ClearDecoder::new();
+        assert!(decoder.decode(&bitmap_stream(0, &[1, 1, 1, 1], &[], &[]), 2, 1).is_err());
+    }
+
+    /// Glyph hit on an index never cached
+    #[test]
+    fn test_clear_glyph_miss() {
+        let mut decoder = ClearDecoder::new();
+        assert!(decoder.decode(&[GlyphFlag::GlyphIndex as u8 | GlyphFlag::GlyphHit as u8, 0, 6, 0], 1, 1).is_err());
+    }
+
+    /// RLEX stop index out of the palette
+    #[test]
+    fn test_clear_rlex_invalid_stop_index() {
+        let mut decoder = ClearDecoder::new();
+        assert!(decoder.decode(&bitmap_stream(0, &[], &[], &[0, 0, 0, 0, 1, 0, 1, 0, 6, 0, 0, 0, 2, 1, 1, 1, 1, 3, 0]), 1, 1).is_err());
+    }
+
+    /// Subcodec rectangle outside of the bitmap
+    #[test]
+    fn test_clear_subcodec_outside_bitmap() {
+        let mut decoder = ClearDecoder::new();
+        assert!(decoder.decode(&bitmap_stream(0, &[], &[], &[1, 0, 0, 0, 1, 0, 1, 0, 3, 0, 0, 0, 0, 4, 5, 6]), 1, 1).is_err());
+    }
+
+    /// Layer byte counts larger than the stream
+    #[test]
+    fn test_clear_layers_overflow_stream() {
+        let mut decoder = ClearDecoder::new();
+        assert!(decoder.decode(&[0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 1, 1).is_err());
+    }
+}
@@ -1 +1,9 @@
-pub mod rle;
\ No newline at end of file
+pub mod rle;
+pub mod planar;
+pub mod surface;
+pub mod rfx;
+pub mod nsc;
+pub mod clear;
+pub mod progressive;
+pub mod mppc;
+pub mod bulk;
@@ -0,0 +1,279 @@
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+use num_enum::TryFromPrimitive;
+
+/// Bulk compression type
+/// advertised in the info packet and
+/// set in the low bits of compression flags
+///
+/// See MS-RDPBCGR 3.1.8 Bulk Data Compression
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, TryFromPrimitive)]
+pub enum CompressionType {
+    /// MPPC with 8K history (RDP 4.0)
+    PacketComprType8k = 0x0,
+    /// MPPC with 64K history (RDP 5.0)
+    PacketComprType64k = 0x1,
+    /// NCRUSH (RDP 6.0)
+    PacketComprTypeRdp6 = 0x2,
+    /// XCRUSH (RDP 6.1)
+    PacketComprTypeRdp61 = 0x3
+}
+
+/// Flags set in compressionFlags (fast path)
+/// or compressedType (slow path)
+#[repr(u8)]
+#[derive(Copy, Clone)]
+pub enum CompressionFlag {
+    CompressionTypeMask = 0x0F,
+    PacketCompressed = 0x20,
+    PacketAtFront = 0x40,
+    PacketFlushed = 0x80
+}
+
+/// Read a compressed stream bit by bit
+/// Most significant bit first
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            position: 0
+        }
+    }
+
+    /// Number of bits not yet consumed
+    pub fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+
+    /// Look at the next bits without consuming them
+    /// Missing bits are read as 0
+    pub fn peek(&self, count: usize) -> u32 {
+        let mut result = 0;
+        for i in self.position..self.position + count {
+            let bit = match self.data.get(i / 8) {
+                Some(byte) => (byte >> (7 - (i % 8))) & 1,
+                None => 0
+            };
+            result = (result << 1) | bit as u32;
+        }
+        result
+    }
+
+    /// Consume bits
+    pub fn read(&mut self, count: usize) -> RdpResult<u32> {
+        if count > self.remaining() {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "MPPC: Unexpected end of compressed stream")))
+        }
+        let result = self.peek(count);
+        self.position += count;
+        Ok(result)
+    }
+}
+
+/// MPPC decompressor
+/// Keep the history buffer shared by all packets
+///
+/// See MS-RDPBCGR 3.1.8.4 Compression Types
+///
+/// # Example
+/// ```
+/// use rdp::codec::mppc::{MppcDecompressor, CompressionType, CompressionFlag};
+/// let mut mppc = MppcDecompressor::new(CompressionType::PacketComprType64k);
+/// let flags = CompressionFlag::PacketCompressed as u8 | CompressionFlag::PacketFlushed as u8 | CompressionType::PacketComprType64k as u8;
+/// assert_eq!(mppc.decompress(b"abc", flags).unwrap(), b"abc");
+/// ```
+pub struct MppcDecompressor {
+    /// History of decompressed data
+    history: Vec<u8>,
+    /// Current position in history
+    offset: usize,
+    /// 8K or 64K
+    compression_type: CompressionType
+}
+
+impl MppcDecompressor {
+    /// Create a new decompressor
+    /// Only 8K and 64K type are relevant
+    pub fn new(compression_type: CompressionType) -> Self {
+        let size = if compression_type == CompressionType::PacketComprType8k { 8192 } else { 65536 };
+        MppcDecompressor {
+            history: vec![0; size],
+            offset: 0,
+            compression_type
+        }
+    }
+
+    /// Compression type handled by this decompressor
+    pub fn get_compression_type(&self) -> CompressionType {
+        self.compression_type
+    }
+
+    /// Decompress a packet using flags sent by server
+    /// Uncompressed packet are returned as is
+    pub fn decompress(&mut self, input: &[u8], flags: u8) -> RdpResult<Vec<u8>> {
+        if flags & CompressionFlag::PacketAtFront as u8 != 0 {
+            self.offset = 0;
+        }
+
+        if flags & CompressionFlag::PacketFlushed as u8 != 0 {
+            self.offset = 0;
+            self.history.iter_mut().for_each(|x| *x = 0);
+        }
+
+        if flags & CompressionFlag::PacketCompressed as u8 == 0 {
+            return Ok(input.to_vec())
+        }
+
+        let start = self.offset;
+        let mut bits = BitReader::new(input);
+
+        // remaining bits are padding
+        while bits.remaining() >= 8 {
+            // Literal
+            if bits.peek(1) == 0 {
+                let literal = bits.read(8)?;
+                self.push(literal as u8)?;
+                continue;
+            }
+            if bits.peek(2) == 0b10 {
+                bits.read(2)?;
+                let literal = bits.read(7)? | 0x80;
+                self.push(literal as u8)?;
+                continue;
+            }
+
+            // Copy tuple
+            let copy_offset = self.read_copy_offset(&mut bits)?;
+            let length = self.read_length_of_match(&mut bits)?;
+            self.copy(copy_offset, length)?;
+        }
+
+        Ok(self.history[start..self.offset].to_vec())
+    }
+
+    /// Read the copy offset part of a copy tuple
+    fn read_copy_offset(&self, bits: &mut BitReader) -> RdpResult<usize> {
+        let copy_offset = if self.compression_type == CompressionType::PacketComprType8k {
+            if bits.peek(4) == 0b1111 {
+                bits.read(4)?;
+                bits.read(6)?
+            } else if bits.peek(4) == 0b1110 {
+                bits.read(4)?;
+                bits.read(8)? + 64
+            } else {
+                bits.read(3)?;
+                bits.read(13)? + 320
+            }
+        } else if bits.peek(5) == 0b11111 {
+            bits.read(5)?;
+            bits.read(6)?
+        } else if bits.peek(5) == 0b11110 {
+            bits.read(5)?;
+            bits.read(8)? + 64
+        } else if bits.peek(4) == 0b1110 {
+            bits.read(4)?;
+            bits.read(11)? + 320
+        } else {
+            bits.read(3)?;
+            bits.read(16)? + 2368
+        };
+        Ok(copy_offset as usize)
+    }
+
+    /// Read the length of match part of a copy tuple
+    /// Encoded as a prefix of 1 followed by a value
+    fn read_length_of_match(&self, bits: &mut BitReader) -> RdpResult<usize> {
+        let max_prefix = if self.compression_type == CompressionType::PacketComprType8k { 11 } else { 14 };
+        let mut prefix = 0;
+        while bits.read(1)? == 1 {
+            prefix += 1;
+            if prefix > max_prefix {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "MPPC: Invalid length of match")))
+            }
+        }
+
+        if prefix == 0 {
+            Ok(3)
+        } else {
+            Ok((1 << (prefix + 1)) + bits.read(prefix + 1)? as usize)
+        }
+    }
+
+    /// Append a byte into history
+    fn push(&mut self, value: u8) -> RdpResult<()> {
+        if self.offset >= self.history.len() {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "MPPC: History buffer overflow")))
+        }
+        self.history[self.offset] = value;
+        self.offset += 1;
+        Ok(())
+    }
+
+    /// Copy from history
+    /// Source and destination can overlap
+    fn copy(&mut self, copy_offset: usize, length: usize) -> RdpResult<()> {
+        if copy_offset == 0 || self.offset + length > self.history.len() {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "MPPC: Invalid copy tuple")))
+        }
+        let mask = self.history.len() - 1;
+        let mut source = self.offset.wrapping_sub(copy_offset) & mask;
+        for _ in 0..length {
+            self.history[self.offset] = self.history[source];
+            self.offset += 1;
+            source = (source + 1) & mask;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn compressed(compression_type: CompressionType) -> u8 {
+        CompressionFlag::PacketCompressed as u8 | compression_type as u8
+    }
+
+    /// Literals and copy tuple with 64K history
+    #[test]
+    fn test_mppc_64k() {
+        let mut mppc = MppcDecompressor::new(CompressionType::PacketComprType64k);
+        // abc + copy offset 3 length 3 + literal 0xE9
+        let flags = compressed(CompressionType::PacketComprType64k) | CompressionFlag::PacketFlushed as u8;
+        assert_eq!(mppc.decompress(&[0x61, 0x62, 0x63, 0xF8, 0x6B, 0x48], flags).unwrap(), [0x61, 0x62, 0x63, 0x61, 0x62, 0x63, 0xE9]);
+    }
+
+    /// History is kept between packets until flushed
+    #[test]
+    fn test_mppc_history() {
+        let mut mppc = MppcDecompressor::new(CompressionType::PacketComprType64k);
+        let flags = compressed(CompressionType::PacketComprType64k);
+        assert_eq!(mppc.decompress(b"abcd", flags | CompressionFlag::PacketFlushed as u8).unwrap(), b"abcd");
+        // copy offset 4 length 4
+        assert_eq!(mppc.decompress(&[0xF8, 0x90], flags).unwrap(), b"abcd");
+        assert_eq!(mppc.decompress(b"xyz", CompressionFlag::PacketAtFront as u8).unwrap(), b"xyz");
+        assert!(mppc.decompress(&[0xF8, 0x90], flags | CompressionFlag::PacketFlushed as u8).is_ok());
+    }
+
+    /// Copy offset encoding of 8K history
+    #[test]
+    fn test_mppc_8k() {
+        let mut mppc = MppcDecompressor::new(CompressionType::PacketComprType8k);
+        // ab + copy offset 2 length 3
+        let flags = compressed(CompressionType::PacketComprType8k) | CompressionFlag::PacketFlushed as u8;
+        assert_eq!(mppc.decompress(&[0x61, 0x62, 0xF0, 0x80], flags).unwrap(), b"ababa");
+    }
+
+    /// Truncated stream must not panic
+    #[test]
+    fn test_mppc_invalid() {
+        let mut mppc = MppcDecompressor::new(CompressionType::PacketComprType64k);
+        let flags = compressed(CompressionType::PacketComprType64k) | CompressionFlag::PacketFlushed as u8;
+        assert!(mppc.decompress(&[0xFF, 0xFF], flags).is_err());
+    }
+}
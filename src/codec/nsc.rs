@@ -0,0 +1,222 @@
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+use codec::planar::ycocg_to_rgb;
+use codec::surface::{SurfaceDecoder, SurfaceRect};
+use std::io::{Cursor, Read};
+use byteorder::{ReadBytesExt, LittleEndian};
+
+/// Size of a NSCodec stream header
+const NSC_HEADER_SIZE: usize = 20;
+
+/// Decode a RLE plane
+/// Runs are encoded as the value repeated twice followed by the run length
+/// Last four bytes are always raw
+///
+/// See MS-RDPNSC 2.2.2 NSCODEC_RLE_SEGMENTS
+fn nsc_rle_decode(input: &[u8], output: &mut [u8]) -> RdpResult<()> {
+    let mut stream = Cursor::new(input);
+    let mut position = 0;
+
+    while output.len() - position > 4 {
+        let value = stream.read_u8()?;
+        let next = input.get(stream.position() as usize);
+
+        if output.len() - position == 5 || next != Some(&value) {
+            output[position] = value;
+            position += 1;
+            continue;
+        }
+
+        stream.read_u8()?;
+        let run_length = match stream.read_u8()? {
+            0xFF => stream.read_u32::<LittleEndian>()? as usize,
+            length => length as usize + 2
+        };
+        if run_length > output.len() - position {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "NSC: RLE run overflow plane")))
+        }
+        output[position..position + run_length].iter_mut().for_each(|x| *x = value);
+        position += run_length;
+    }
+
+    stream.read_exact(&mut output[position..])?;
+    Ok(())
+}
+
+/// NSCodec decoder
+/// Output is 32 bpp BGRA, top-down
+///
+/// See MS-RDPNSC 3.1.8 Decoding
+///
+/// # Example
+/// ```
+/// use rdp::codec::nsc::nsc_decompress;
+/// let input = [
+///     1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
+///     1, 0, 0, 0,
+///     0x80, 0x00, 0x00
+/// ];
+/// let mut output = [0_u8; 4];
+/// nsc_decompress(&input, 1, 1, &mut output).unwrap();
+/// assert_eq!(output, [0x80, 0x80, 0x80, 0xff]);
+/// ```
+pub fn nsc_decompress(input: &[u8], width: usize, height: usize, output: &mut [u8]) -> RdpResult<()> {
+    if output.len() < width * height * 4 {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "NSC: Output buffer too small")))
+    }
+    if input.len() < NSC_HEADER_SIZE {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "NSC: Invalid stream size")))
+    }
+
+    let mut stream = Cursor::new(input);
+    let mut plane_byte_count = [0_usize; 4];
+    for count in plane_byte_count.iter_mut() {
+        *count = stream.read_u32::<LittleEndian>()? as usize;
+    }
+    let color_loss_level = stream.read_u8()?;
+    let chroma_subsampling = stream.read_u8()? != 0;
+    stream.read_u16::<LittleEndian>()?;
+
+    if !(1..=7).contains(&color_loss_level) {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "NSC: Invalid color loss level")))
+    }
+
+    // subsampled luma lines are padded on 8 pixels
+    // and chroma planes on 2 lines
+    let luma_width = if chroma_subsampling { (width + 7) & !7 } else { width };
+    let (chroma_width, chroma_height) = if chroma_subsampling { (luma_width / 2, (height + 1) / 2) } else { (width, height) };
+    let original_size = [luma_width * height, chroma_width * chroma_height, chroma_width * chroma_height, width * height];
+
+    let mut planes = Vec::with_capacity(4);
+    let mut position = NSC_HEADER_SIZE;
+    for i in 0..4 {
+        let mut plane = vec![0xFF_u8; original_size[i]];
+        if position + plane_byte_count[i] > input.len() {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "NSC: Plane overflow stream")))
+        }
+        let data = &input[position..position + plane_byte_count[i]];
+
+        // empty plane is filled with 0xFF
+        if plane_byte_count[i] >= original_size[i] {
+            plane.copy_from_slice(&data[..original_size[i]]);
+        } else if plane_byte_count[i] > 0 {
+            nsc_rle_decode(data, &mut plane)?;
+        }
+        planes.push(plane);
+        position += plane_byte_count[i];
+    }
+
+    let shift = chroma_subsampling as usize;
+    for y in 0..height {
+        for x in 0..width {
+            let chroma_index = (y >> shift) * chroma_width + (x >> shift);
+            let (red, green, blue) = ycocg_to_rgb(planes[0][y * luma_width + x], planes[1][chroma_index], planes[2][chroma_index], color_loss_level);
+            let pixel = (y * width + x) * 4;
+            output[pixel] = blue;
+            output[pixel + 1] = green;
+            output[pixel + 2] = red;
+            output[pixel + 3] = planes[3][y * width + x];
+        }
+    }
+    Ok(())
+}
+
+/// NSCodec surface decoder
+/// Each surface bits command is a whole image
+#[derive(Default)]
+pub struct NscDecoder;
+
+impl SurfaceDecoder for NscDecoder {
+    fn decode(&mut self, width: u16, height: u16, input: &[u8]) -> RdpResult<Vec<SurfaceRect>> {
+        let mut data = vec![0; width as usize * height as usize * 4];
+        nsc_decompress(input, width as usize, height as usize, &mut data)?;
+        Ok(vec![SurfaceRect {
+            left: 0,
+            top: 0,
+            width,
+            height,
+            data
+        }])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Header with plane sizes, color loss level and subsampling
+    fn header(plane_byte_count: [u32; 4], color_loss_level: u8, chroma_subsampling: bool) -> Vec<u8> {
+        let mut result = vec![];
+        for count in plane_byte_count.iter() {
+            result.extend_from_slice(&count.to_le_bytes());
+        }
+        result.extend_from_slice(&[color_loss_level, chroma_subsampling as u8, 0, 0]);
+        result
+    }
+
+    #[test]
+    fn test_nsc_rle_decode() {
+        let mut output = [0_u8; 10];
+        nsc_rle_decode(&[7, 7, 3, 1, 2, 3, 4, 5], &mut output).unwrap();
+        assert_eq!(output, [7, 7, 7, 7, 7, 1, 2, 3, 4, 5]);
+
+        let mut output = [0_u8; 24];
+        nsc_rle_decode(&[9, 9, 0xFF, 20, 0, 0, 0, 1, 2, 3, 4], &mut output).unwrap();
+        assert!(output[..20].iter().all(|x| *x == 9));
+        assert_eq!(output[20..], [1, 2, 3, 4]);
+    }
+
+    /// Chroma planes are shared by 2x2 pixels
+    #[test]
+    fn test_nsc_chroma_subsampling() {
+        let mut input = header([16, 4, 4, 0], 1, true);
+        input.extend_from_slice(&[100; 16]);
+        input.extend_from_slice(&[0x10, 0, 0, 0]);
+        input.extend_from_slice(&[0, 0, 0, 0]);
+
+        let mut output = [0_u8; 16];
+        nsc_decompress(&input, 2, 2, &mut output).unwrap();
+        assert!(output.chunks(4).all(|pixel| pixel == [84, 100, 116, 0xff]));
+    }
+
+    /// RLE planes and alpha plane
+    #[test]
+    fn test_nsc_rle_planes() {
+        let mut input = header([7, 7, 7, 7], 3, false);
+        input.extend_from_slice(&[50, 50, 2, 50, 50, 50, 50]);
+        input.extend_from_slice(&[0, 0, 2, 0, 0, 0, 0]);
+        input.extend_from_slice(&[0, 0, 2, 0, 0, 0, 0]);
+        input.extend_from_slice(&[0x80, 0x80, 2, 0x80, 0x80, 0x80, 0x80]);
+
+        let mut output = [0_u8; 32];
+        nsc_decompress(&input, 4, 2, &mut output).unwrap();
+        assert!(output.chunks(4).all(|pixel| pixel == [50, 50, 50, 0x80]));
+    }
+
+    /// Color loss level must be between 1 and 7
+    #[test]
+    fn test_nsc_invalid_color_loss_level() {
+        let mut output = [0_u8; 16];
+        assert!(nsc_decompress(&header([1, 1, 1, 0], 0, false), 1, 1, &mut output).is_err());
+    }
+
+    /// Plane byte count larger than the stream
+    #[test]
+    fn test_nsc_plane_overflow_stream() {
+        let mut output = [0_u8; 16];
+        assert!(nsc_decompress(&header([100, 1, 1, 0], 1, false), 1, 1, &mut output).is_err());
+    }
+
+    /// Stream shorter than the plane header
+    #[test]
+    fn test_nsc_truncated_header() {
+        let mut output = [0_u8; 16];
+        assert!(nsc_decompress(&[0, 0], 1, 1, &mut output).is_err());
+    }
+
+    /// RLE run longer than the plane
+    #[test]
+    fn test_nsc_rle_run_overflow() {
+        let mut output = [0_u8; 8];
+        assert!(nsc_rle_decode(&[7, 7, 10, 1, 2, 3, 4], &mut output).is_err());
+    }
+}
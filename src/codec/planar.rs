@@ -0,0 +1,525 @@
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+use std::io::{Cursor, Read};
+use byteorder::ReadBytesExt;
+
+/// Format header flags of a planar bitmap
+///
+/// See MS-RDPEGDI 2.2.2.5.1 RDP6_BITMAP_STREAM
+#[repr(u8)]
+#[derive(Copy, Clone)]
+pub enum PlanarFlag {
+    ColorLossLevelMask = 0x07,
+    ChromaSubsampling = 0x08,
+    RunLengthEncoding = 0x10,
+    NoAlpha = 0x20
+}
+
+/// Maximum number of raw bytes in a RLE segment
+const MAX_RAW_BYTES: usize = 15;
+
+/// Control byte of a RLE segment
+/// Run length of 1 and 2 are used to encode long runs
+fn read_control_byte(control: u8) -> (usize, usize) {
+    let run_length = (control & 0x0F) as usize;
+    let raw_bytes = (control >> 4) as usize;
+    match run_length {
+        1 => (0, raw_bytes + 16),
+        2 => (0, raw_bytes + 32),
+        _ => (raw_bytes, run_length)
+    }
+}
+
+/// Delta between two scanlines are encoded
+/// using the lowest bit as sign
+fn decode_delta(value: u8) -> u8 {
+    if value & 1 != 0 {
+        (-(((value >> 1) as i16) + 1)) as u8
+    } else {
+        value >> 1
+    }
+}
+
+/// Inverse of decode_delta
+fn encode_delta(delta: u8) -> u8 {
+    let delta = delta as i8 as i16;
+    if delta >= 0 {
+        (delta * 2) as u8
+    } else {
+        (-delta * 2 - 1) as u8
+    }
+}
+
+/// Decode a RLE plane
+/// First scanline is made of absolute values
+/// next scanlines are made of deltas with the previous one
+///
+/// See MS-RDPEGDI 2.2.2.5.1.1 RDP6_RLE_PLANE
+fn read_rle_plane(input: &mut dyn Read, width: usize, height: usize) -> RdpResult<Vec<u8>> {
+    let mut plane = vec![0_u8; width * height];
+
+    for y in 0..height {
+        let mut x = 0;
+        // value repeated by runs
+        let mut last = 0_u8;
+        while x < width {
+            let (raw_bytes, run_length) = read_control_byte(input.read_u8()?);
+            if raw_bytes + run_length == 0 || x + raw_bytes + run_length > width {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PLANAR: RLE segment overflow scanline")))
+            }
+
+            for _ in 0..raw_bytes {
+                last = input.read_u8()?;
+                plane[y * width + x] = if y == 0 { last } else { plane[(y - 1) * width + x].wrapping_add(decode_delta(last)) };
+                x += 1;
+            }
+            for _ in 0..run_length {
+                plane[y * width + x] = if y == 0 { last } else { plane[(y - 1) * width + x].wrapping_add(decode_delta(last)) };
+                x += 1;
+            }
+        }
+    }
+    Ok(plane)
+}
+
+/// Write a RLE segment
+/// Run length must be 0 or at least 3
+fn write_segment(raw: &[u8], run_length: usize, output: &mut Vec<u8>) {
+    if raw.is_empty() && run_length >= 32 {
+        output.push(((run_length - 32) << 4) as u8 | 2);
+    } else if raw.is_empty() && run_length >= 16 {
+        output.push(((run_length - 16) << 4) as u8 | 1);
+    } else {
+        output.push((raw.len() << 4) as u8 | run_length as u8);
+        output.extend_from_slice(raw);
+    }
+}
+
+/// Encode a scanline of bytes already transformed into deltas
+fn write_rle_scanline(line: &[u8], output: &mut Vec<u8>) {
+    let mut raw = Vec::with_capacity(MAX_RAW_BYTES);
+    let mut last = 0_u8;
+    let mut x = 0;
+
+    while x < line.len() {
+        let mut run_length = 0;
+        while x + run_length < line.len() && line[x + run_length] == last {
+            run_length += 1;
+        }
+
+        if run_length >= 3 {
+            // long runs are only allowed without raw bytes
+            let run_length = if raw.is_empty() { run_length.min(47) } else { run_length.min(15) };
+            write_segment(&raw, run_length, output);
+            raw.clear();
+            x += run_length;
+            continue;
+        }
+
+        last = line[x];
+        raw.push(last);
+        x += 1;
+        if raw.len() == MAX_RAW_BYTES {
+            write_segment(&raw, 0, output);
+            raw.clear();
+        }
+    }
+
+    if !raw.is_empty() {
+        write_segment(&raw, 0, output);
+    }
+}
+
+/// Encode a plane using RLE
+fn write_rle_plane(plane: &[u8], width: usize, height: usize, output: &mut Vec<u8>) {
+    let mut line = vec![0_u8; width];
+    for y in 0..height {
+        for x in 0..width {
+            line[x] = if y == 0 {
+                plane[x]
+            } else {
+                encode_delta(plane[y * width + x].wrapping_sub(plane[(y - 1) * width + x]))
+            };
+        }
+        write_rle_scanline(&line, output);
+    }
+}
+
+/// Read a raw or RLE plane
+fn read_plane(input: &mut Cursor<&[u8]>, width: usize, height: usize, is_rle: bool) -> RdpResult<Vec<u8>> {
+    if is_rle {
+        read_rle_plane(input, width, height)
+    } else {
+        let mut plane = vec![0_u8; width * height];
+        input.read_exact(&mut plane)?;
+        Ok(plane)
+    }
+}
+
+/// Size of chroma planes
+fn chroma_size(width: usize, height: usize, chroma_subsampling: bool) -> (usize, usize) {
+    if chroma_subsampling {
+        ((width + 1) / 2, (height + 1) / 2)
+    } else {
+        (width, height)
+    }
+}
+
+/// Chroma values are reduced by the color loss level
+/// and stored as signed bytes
+fn decode_chroma(value: u8, color_loss_level: u8) -> i16 {
+    (value << (color_loss_level - 1)) as i8 as i16
+}
+
+/// Convert a YCoCg pixel into RGB
+/// Color loss level must be between 1 and 7
+/// Shared with NSCodec which use the same transform
+pub fn ycocg_to_rgb(luma: u8, orange_chroma: u8, green_chroma: u8, color_loss_level: u8) -> (u8, u8, u8) {
+    let luma = luma as i16;
+    let orange_chroma = decode_chroma(orange_chroma, color_loss_level);
+    let green_chroma = decode_chroma(green_chroma, color_loss_level);
+    (
+        (luma + orange_chroma - green_chroma).max(0).min(255) as u8,
+        (luma + green_chroma).max(0).min(255) as u8,
+        (luma - orange_chroma - green_chroma).max(0).min(255) as u8
+    )
+}
+
+/// Planar codec decoder (RDP 6.0 bitmap compression)
+/// Output is 32 bpp BGRA, written top-down
+///
+/// See MS-RDPEGDI 3.1.9 RDP 6.0 Bitmap Compression
+///
+/// # Example
+/// ```
+/// use rdp::codec::planar::planar_decompress;
+/// let mut output = [0_u8; 4];
+/// // raw planes without alpha (red, green, blue)
+/// planar_decompress(&[0x20, 0x10, 0x20, 0x30, 0x00], 1, 1, &mut output).unwrap();
+/// assert_eq!(output, [0x30, 0x20, 0x10, 0xff]);
+/// ```
+pub fn planar_decompress(input: &[u8], width: usize, height: usize, output: &mut [u8]) -> RdpResult<()> {
+    if output.len() < width * height * 4 {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "PLANAR: Output buffer too small")))
+    }
+
+    let mut stream = Cursor::new(input);
+    let header = stream.read_u8()?;
+    let color_loss_level = header & PlanarFlag::ColorLossLevelMask as u8;
+    let chroma_subsampling = header & PlanarFlag::ChromaSubsampling as u8 != 0;
+    let is_rle = header & PlanarFlag::RunLengthEncoding as u8 != 0;
+
+    if chroma_subsampling && color_loss_level == 0 {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PLANAR: Chroma subsampling without color loss reduction")))
+    }
+
+    let alpha_plane = if header & PlanarFlag::NoAlpha as u8 == 0 {
+        read_plane(&mut stream, width, height, is_rle)?
+    } else {
+        vec![0xff; width * height]
+    };
+
+    let (chroma_width, chroma_height) = chroma_size(width, height, chroma_subsampling);
+    let first_plane = read_plane(&mut stream, width, height, is_rle)?;
+    let second_plane = read_plane(&mut stream, chroma_width, chroma_height, is_rle)?;
+    let third_plane = read_plane(&mut stream, chroma_width, chroma_height, is_rle)?;
+
+    let shift = chroma_subsampling as usize;
+    for y in 0..height {
+        // scanlines are sent bottom-up
+        let line = (height - y - 1) * width * 4;
+        for x in 0..width {
+            let index = y * width + x;
+            let (red, green, blue) = if color_loss_level == 0 {
+                (first_plane[index], second_plane[index], third_plane[index])
+            } else {
+                let chroma_index = (y >> shift) * chroma_width + (x >> shift);
+                ycocg_to_rgb(first_plane[index], second_plane[chroma_index], third_plane[chroma_index], color_loss_level)
+            };
+            let pixel = line + x * 4;
+            output[pixel] = blue;
+            output[pixel + 1] = green;
+            output[pixel + 2] = red;
+            output[pixel + 3] = alpha_plane[index];
+        }
+    }
+    Ok(())
+}
+
+/// Planar codec encoder
+/// Input is 32 bpp BGRA, top-down
+///
+/// # Example
+/// ```
+/// use rdp::codec::planar::{PlanarEncoder, planar_decompress};
+/// let bitmap = [0x30, 0x20, 0x10, 0xff, 0x30, 0x20, 0x10, 0xff];
+/// let encoded = PlanarEncoder::new().rle(true).alpha(false).encode(&bitmap, 2, 1).unwrap();
+/// let mut output = [0_u8; 8];
+/// planar_decompress(&encoded, 2, 1, &mut output).unwrap();
+/// assert_eq!(output, bitmap);
+/// ```
+#[derive(Clone)]
+pub struct PlanarEncoder {
+    color_loss_level: u8,
+    chroma_subsampling: bool,
+    rle: bool,
+    alpha: bool
+}
+
+impl PlanarEncoder {
+    /// Lossless encoder with raw planes and alpha
+    pub fn new() -> Self {
+        PlanarEncoder {
+            color_loss_level: 0,
+            chroma_subsampling: false,
+            rle: false,
+            alpha: true
+        }
+    }
+
+    /// Color loss level from 0 (lossless RGB) to 7
+    /// A non zero value switch to YCoCg color space
+    pub fn color_loss_level(mut self, color_loss_level: u8) -> Self {
+        self.color_loss_level = color_loss_level;
+        self
+    }
+
+    /// Halve chroma planes in both direction
+    /// Need a non zero color loss level
+    pub fn chroma_subsampling(mut self, chroma_subsampling: bool) -> Self {
+        self.chroma_subsampling = chroma_subsampling;
+        self
+    }
+
+    /// Use RLE planes instead of raw planes
+    pub fn rle(mut self, rle: bool) -> Self {
+        self.rle = rle;
+        self
+    }
+
+    /// Send the alpha plane
+    pub fn alpha(mut self, alpha: bool) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Encode a bitmap into a RDP6_BITMAP_STREAM
+    pub fn encode(&self, input: &[u8], width: usize, height: usize) -> RdpResult<Vec<u8>> {
+        if self.color_loss_level > PlanarFlag::ColorLossLevelMask as u8 {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PLANAR: Invalid color loss level")))
+        }
+        if self.chroma_subsampling && self.color_loss_level == 0 {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PLANAR: Chroma subsampling without color loss reduction")))
+        }
+        if input.len() < width * height * 4 {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "PLANAR: Input buffer too small")))
+        }
+
+        let mut header = self.color_loss_level;
+        if self.chroma_subsampling {
+            header |= PlanarFlag::ChromaSubsampling as u8;
+        }
+        if self.rle {
+            header |= PlanarFlag::RunLengthEncoding as u8;
+        }
+        if !self.alpha {
+            header |= PlanarFlag::NoAlpha as u8;
+        }
+
+        // split planes in stream order (bottom-up)
+        let mut planes = [vec![0_u8; width * height], vec![0_u8; width * height], vec![0_u8; width * height], vec![0_u8; width * height]];
+        let mut orange_chroma = vec![0_i16; width * height];
+        let mut green_chroma = vec![0_i16; width * height];
+        for y in 0..height {
+            let line = (height - y - 1) * width * 4;
+            for x in 0..width {
+                let index = y * width + x;
+                let pixel = line + x * 4;
+                let (blue, green, red) = (input[pixel] as i16, input[pixel + 1] as i16, input[pixel + 2] as i16);
+                planes[0][index] = input[pixel + 3];
+                if self.color_loss_level == 0 {
+                    planes[1][index] = red as u8;
+                    planes[2][index] = green as u8;
+                    planes[3][index] = blue as u8;
+                } else {
+                    planes[1][index] = ((red + 2 * green + blue) >> 2) as u8;
+                    orange_chroma[index] = red - blue;
+                    green_chroma[index] = (2 * green - red - blue) >> 1;
+                }
+            }
+        }
+
+        if self.color_loss_level != 0 {
+            let (chroma_width, chroma_height) = chroma_size(width, height, self.chroma_subsampling);
+            planes[2] = self.reduce_chroma(&orange_chroma, width, height, chroma_width, chroma_height);
+            planes[3] = self.reduce_chroma(&green_chroma, width, height, chroma_width, chroma_height);
+        }
+
+        let mut result = vec![header];
+        let (chroma_width, chroma_height) = chroma_size(width, height, self.chroma_subsampling);
+        for (i, plane) in planes.iter().enumerate() {
+            let (plane_width, plane_height) = match i {
+                0 if !self.alpha => continue,
+                0 | 1 => (width, height),
+                _ => (chroma_width, chroma_height)
+            };
+            if self.rle {
+                write_rle_plane(plane, plane_width, plane_height, &mut result);
+            } else {
+                result.extend_from_slice(plane);
+            }
+        }
+
+        // raw planes are followed by a padding byte
+        if !self.rle {
+            result.push(0);
+        }
+        Ok(result)
+    }
+
+    /// Apply color loss level and chroma subsampling
+    /// Subsampled values are the average of each 2x2 block
+    fn reduce_chroma(&self, chroma: &[i16], width: usize, height: usize, chroma_width: usize, chroma_height: usize) -> Vec<u8> {
+        let mut result = vec![0_u8; chroma_width * chroma_height];
+        let shift = self.chroma_subsampling as usize;
+        for y in 0..chroma_height {
+            for x in 0..chroma_width {
+                let mut sum = 0;
+                let mut count = 0;
+                for source_y in (y << shift)..((y + 1) << shift).min(height) {
+                    for source_x in (x << shift)..((x + 1) << shift).min(width) {
+                        sum += chroma[source_y * width + source_x] as i32;
+                        count += 1;
+                    }
+                }
+                result[y * chroma_width + x] = (sum.div_euclid(count) >> self.color_loss_level) as u8;
+            }
+        }
+        result
+    }
+}
+
+impl Default for PlanarEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn random_bitmap(rng: &mut StdRng, width: usize, height: usize) -> Vec<u8> {
+        (0..width * height * 4).map(|i| {
+            // mix noise and flat areas to exercise runs
+            if (i / 4) % 7 < 3 { rng.gen::<u8>() } else { (i % 4) as u8 * 0x40 }
+        }).collect()
+    }
+
+    /// Same as rdesktop header of 32 bpp bitmap
+    #[test]
+    fn test_planar_rle_alpha() {
+        // 2x1, raw segments for each plane
+        let input = [0x10, 0x20, 0xff, 0xff, 0x20, 0x01, 0x02, 0x20, 0x00, 0x00, 0x20, 0x03, 0x04];
+        let mut output = [0_u8; 8];
+        planar_decompress(&input, 2, 1, &mut output).unwrap();
+        assert_eq!(output, [0x03, 0x00, 0x01, 0xff, 0x04, 0x00, 0x02, 0xff]);
+    }
+
+    /// Second scanline is made of deltas
+    #[test]
+    fn test_planar_rle_delta() {
+        // 1x2 without alpha, red 0x10 then +2, green 0x10 then -1, blue 0
+        let input = [0x30, 0x10, 0x10, 0x10, 0x04, 0x10, 0x10, 0x10, 0x01, 0x10, 0x00, 0x10, 0x00];
+        let mut output = [0_u8; 8];
+        planar_decompress(&input, 1, 2, &mut output).unwrap();
+        assert_eq!(output, [0x00, 0x0f, 0x12, 0xff, 0x00, 0x10, 0x10, 0xff]);
+    }
+
+    /// Long runs use run length 1 and 2
+    #[test]
+    fn test_planar_long_run() {
+        let mut output = vec![0_u8; 40 * 4];
+        planar_decompress(&[0x30, 0x10, 0x01, 0x72, 0x82, 0x82], 40, 1, &mut output).unwrap();
+        assert!(output.chunks(4).all(|pixel| pixel == [0, 0, 1, 0xff]));
+
+        let mut encoded = vec![];
+        write_rle_scanline(&[7; 47], &mut encoded);
+        assert_eq!(encoded, [0x1F, 0x07, 0xF1]);
+    }
+
+    /// RLE segment longer than the scanline
+    #[test]
+    fn test_planar_rle_segment_overflow() {
+        let mut output = [0_u8; 8];
+        // run of 3 on a 2 pixels scanline
+        assert!(planar_decompress(&[0x30, 0x03], 2, 1, &mut output).is_err());
+    }
+
+    /// RLE control byte without raw bytes nor run
+    #[test]
+    fn test_planar_rle_empty_segment() {
+        let mut output = [0_u8; 8];
+        assert!(planar_decompress(&[0x30, 0x00], 2, 1, &mut output).is_err());
+    }
+
+    /// Chroma subsampling is only defined with a color loss level
+    #[test]
+    fn test_planar_chroma_subsampling_without_color_loss() {
+        let mut output = [0_u8; 8];
+        assert!(planar_decompress(&[0x28, 0, 0, 0, 0, 0, 0], 2, 1, &mut output).is_err());
+        assert!(PlanarEncoder::new().chroma_subsampling(true).encode(&output, 2, 1).is_err());
+    }
+
+    /// Raw planes shorter than the bitmap
+    #[test]
+    fn test_planar_truncated_raw_plane() {
+        let mut output = [0_u8; 8];
+        assert!(planar_decompress(&[0x20, 0, 0], 2, 1, &mut output).is_err());
+    }
+
+    /// Lossless encoding must round trip
+    #[test]
+    fn test_planar_round_trip_lossless() {
+        // fixed seed so a failure can be reproduced
+        let mut rng = StdRng::seed_from_u64(0x5EED);
+        for _ in 0..50 {
+            let (width, height) = (rng.gen_range(1, 70), rng.gen_range(1, 20));
+            let bitmap = random_bitmap(&mut rng, width, height);
+            let alpha = rng.gen::<bool>();
+            let encoder = PlanarEncoder::new().rle(rng.gen()).alpha(alpha);
+            let mut output = vec![0_u8; width * height * 4];
+            planar_decompress(&encoder.encode(&bitmap, width, height).unwrap(), width, height, &mut output).unwrap();
+
+            for (source, decoded) in bitmap.chunks(4).zip(output.chunks(4)) {
+                assert_eq!(source[0..3], decoded[0..3]);
+                assert_eq!(decoded[3], if alpha { source[3] } else { 0xff });
+            }
+        }
+    }
+
+    /// Lossy encoding must stay close to the source
+    #[test]
+    fn test_planar_round_trip_color_loss() {
+        let mut rng = StdRng::seed_from_u64(0x5EED);
+        for _ in 0..50 {
+            let (width, height) = (rng.gen_range(1, 70), rng.gen_range(1, 20));
+            let color_loss_level = rng.gen_range(1, 8);
+            // solid color so chroma subsampling does not blur
+            let pixel = [rng.gen::<u8>(), rng.gen::<u8>(), rng.gen::<u8>(), rng.gen::<u8>()];
+            let bitmap = pixel.repeat(width * height);
+            let encoder = PlanarEncoder::new().rle(rng.gen()).color_loss_level(color_loss_level).chroma_subsampling(rng.gen());
+            let mut output = vec![0_u8; width * height * 4];
+            planar_decompress(&encoder.encode(&bitmap, width, height).unwrap(), width, height, &mut output).unwrap();
+
+            let tolerance = 2 + (1 << color_loss_level);
+            for decoded in output.chunks(4) {
+                for i in 0..3 {
+                    assert!((decoded[i] as i16 - pixel[i] as i16).abs() <= tolerance);
+                }
+                assert_eq!(decoded[3], pixel[3]);
+            }
+        }
+    }
+}
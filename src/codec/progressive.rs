@@ -0,0 +1,757 @@
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+use codec::mppc::BitReader;
+use codec::rfx::{rlgr_decode, idwt_2d, ycbcr_to_bgra, read_quant, EntropyAlgorithm, RFX_TILE_SIZE};
+use codec::surface::SurfaceRect;
+use std::io::{Cursor, Read};
+use std::collections::HashMap;
+use byteorder::{ReadBytesExt, LittleEndian};
+use num_enum::TryFromPrimitive;
+use std::convert::TryFrom;
+
+/// Number of coefficients of a tile component
+const PROGRESSIVE_TILE_COEFFICIENTS: usize = RFX_TILE_SIZE * RFX_TILE_SIZE;
+
+/// Magic number of the sync block
+const PROGRESSIVE_WF_MAGIC: u32 = 0xCACCACCA;
+
+/// Quality index of the full quality pass
+const PROGRESSIVE_QUALITY_FULL: u8 = 0xFF;
+
+/// Maximum value of the SRL adaptive parameter
+const PROGRESSIVE_SRL_KPMAX: u32 = 80;
+
+/// Progressive message block types
+///
+/// See MS-RDPEGFX 2.2.4.2.1 RFX_PROGRESSIVE_DATABLOCK
+#[repr(u16)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, TryFromPrimitive)]
+pub enum ProgressiveBlockType {
+    WbtSync = 0xCCC0,
+    WbtFrameBegin = 0xCCC1,
+    WbtFrameEnd = 0xCCC2,
+    WbtContext = 0xCCC3,
+    WbtRegion = 0xCCC4,
+    WbtTileSimple = 0xCCC5,
+    WbtTileFirst = 0xCCC6,
+    WbtTileUpgrade = 0xCCC7
+}
+
+/// Flags of the region block
+#[repr(u8)]
+#[derive(Copy, Clone)]
+pub enum RegionFlag {
+    RfxDwtReduceExtrapolate = 0x01
+}
+
+/// Flags of the tile blocks
+#[repr(u8)]
+#[derive(Copy, Clone)]
+pub enum TileFlag {
+    RfxTileDifference = 0x01
+}
+
+/// Sub bands offset and size in the coefficient buffer
+/// with the index of their quantization value
+///
+/// Quantization values are ordered as
+/// LL3, HL3, LH3, HH3, HL2, LH2, HH2, HL1, LH1, HH1
+/// LL3 is always the last band
+const SUB_BANDS: [(usize, usize, usize); 10] = [
+    (0, 1024, 7),       // HL1
+    (1024, 1024, 8),    // LH1
+    (2048, 1024, 9),    // HH1
+    (3072, 256, 4),     // HL2
+    (3328, 256, 5),     // LH2
+    (3584, 256, 6),     // HH2
+    (3840, 64, 1),      // HL3
+    (3904, 64, 2),      // LH3
+    (3968, 64, 3),      // HH3
+    (4032, 64, 0)       // LL3
+];
+
+/// Sub bands of the reduce extrapolate layout
+/// Low bands are one coefficient larger than high bands
+const EXTRAPOLATE_SUB_BANDS: [(usize, usize, usize); 10] = [
+    (0, 1023, 7),       // HL1
+    (1023, 1023, 8),    // LH1
+    (2046, 961, 9),     // HH1
+    (3007, 272, 4),     // HL2
+    (3279, 272, 5),     // LH2
+    (3551, 256, 6),     // HH2
+    (3807, 72, 1),      // HL3
+    (3879, 72, 2),      // LH3
+    (3951, 64, 3),      // HH3
+    (4015, 81, 0)       // LL3
+];
+
+/// Offset, low and high band sizes of each reduce extrapolate level
+/// from the deepest one
+const EXTRAPOLATE_LEVELS: [(usize, usize, usize); 3] = [
+    (3807, 9, 8),
+    (3007, 17, 16),
+    (0, 33, 31)
+];
+
+/// One dimension inverse lifting of the reduce extrapolate DWT
+/// output is the size of low and high together
+fn idwt_1d_extrapolate(low: &[i16], high: &[i16], output: &mut [i16]) {
+    let (low_count, high_count) = (low.len(), high.len());
+    let mut h0 = high[0] as i32;
+    let mut x0 = (low[0] as i32 - h0) as i16 as i32;
+    let mut x2 = x0;
+    let mut position = 0;
+
+    for j in 1..high_count {
+        let h1 = high[j] as i32;
+        x2 = (low[j] as i32 - (h0 + h1) / 2) as i16 as i32;
+        output[position] = x0 as i16;
+        output[position + 1] = ((x0 + x2) / 2 + 2 * h0) as i16;
+        position += 2;
+        x0 = x2;
+        h0 = h1;
+    }
+
+    if low_count <= high_count {
+        output[position] = x2 as i16;
+        output[position + 1] = (x2 + 2 * h0) as i16;
+    } else if low_count == high_count + 1 {
+        let x0 = (low[high_count] as i32 - h0) as i16 as i32;
+        output[position] = x2 as i16;
+        output[position + 1] = ((x0 + x2) / 2 + 2 * h0) as i16;
+        output[position + 2] = x0 as i16;
+    } else {
+        let x0 = (low[high_count] as i32 - h0 / 2) as i16 as i32;
+        output[position] = x2 as i16;
+        output[position + 1] = ((x0 + x2) / 2 + 2 * h0) as i16;
+        output[position + 2] = x0 as i16;
+        output[position + 3] = ((x0 + low[high_count + 1] as i32) / 2) as i16;
+    }
+}
+
+/// Inverse reduce extrapolate DWT of one level
+/// Sub bands are stored in HL, LH, HH, LL order
+/// Result is written back in place
+fn idwt_2d_extrapolate_block(buffer: &mut [i16], low_count: usize, high_count: usize) {
+    let size = low_count + high_count;
+    let hl = 0;
+    let lh = hl + high_count * low_count;
+    let hh = lh + low_count * high_count;
+    let ll = hh + high_count * high_count;
+    let mut rows = vec![0_i16; size * size];
+
+    // horizontal pass, L rows on top of H rows
+    for y in 0..low_count {
+        idwt_1d_extrapolate(&buffer[ll + y * low_count..ll + (y + 1) * low_count], &buffer[hl + y * high_count..hl + (y + 1) * high_count], &mut rows[y * size..(y + 1) * size]);
+    }
+    for y in 0..high_count {
+        idwt_1d_extrapolate(&buffer[lh + y * low_count..lh + (y + 1) * low_count], &buffer[hh + y * high_count..hh + (y + 1) * high_count], &mut rows[(low_count + y) * size..(low_count + y + 1) * size]);
+    }
+
+    // vertical pass
+    let mut low = vec![0_i16; low_count];
+    let mut high = vec![0_i16; high_count];
+    let mut column = vec![0_i16; size];
+    for x in 0..size {
+        for (n, value) in low.iter_mut().enumerate() {
+            *value = rows[n * size + x];
+        }
+        for (n, value) in high.iter_mut().enumerate() {
+            *value = rows[(low_count + n) * size + x];
+        }
+        idwt_1d_extrapolate(&low, &high, &mut column);
+        for (n, value) in column.iter().enumerate() {
+            buffer[n * size + x] = *value;
+        }
+    }
+}
+
+/// Inverse reduce extrapolate DWT of the three levels
+///
+/// See MS-RDPEGFX 3.2.8.1.2.1 Reduce-Extrapolate Method
+fn idwt_2d_extrapolate(coefficients: &mut [i16]) {
+    for (offset, low_count, high_count) in EXTRAPOLATE_LEVELS.iter() {
+        idwt_2d_extrapolate_block(&mut coefficients[*offset..], *low_count, *high_count);
+    }
+}
+
+/// Band wise sum of quantization values
+fn quant_add(left: &[u8; 10], right: &[u8; 10]) -> [u8; 10] {
+    let mut result = [0_u8; 10];
+    for i in 0..10 {
+        result[i] = left[i].saturating_add(right[i]);
+    }
+    result
+}
+
+/// Coefficients of a band are shifted by its bit position minus one
+fn band_shift(bit_pos: &[u8; 10]) -> [u8; 10] {
+    let mut result = [0_u8; 10];
+    for i in 0..10 {
+        result[i] = bit_pos[i].saturating_sub(1);
+    }
+    result
+}
+
+/// Adaptive state of the Simplified Run-Length coder
+/// shared by all bands of a component
+///
+/// See MS-RDPEGFX 3.2.8.1.4.1 SRL Decoding
+struct SrlDecoder {
+    /// Adaptive parameter
+    kp: u32,
+    /// Zeros not yet returned
+    zeros: u32,
+    /// Next value is encoded in unary
+    unary: bool
+}
+
+impl SrlDecoder {
+    fn new() -> Self {
+        SrlDecoder {
+            kp: 8,
+            zeros: 0,
+            unary: false
+        }
+    }
+
+    /// Read the next value
+    /// Magnitude is between 1 and 2^num_bits - 1
+    fn read(&mut self, bits: &mut BitReader, num_bits: u8) -> RdpResult<i32> {
+        if self.zeros > 0 {
+            self.zeros -= 1;
+            return Ok(0)
+        }
+
+        let k = self.kp / 8;
+        if !self.unary {
+            if bits.read(1)? == 0 {
+                // a full run of 2^k zeros
+                self.zeros = (1 << k) - 1;
+                self.kp = (self.kp + 4).min(PROGRESSIVE_SRL_KPMAX);
+                return Ok(0)
+            }
+
+            // a shorter run followed by a value
+            self.unary = true;
+            self.zeros = bits.read(k as usize)?;
+            if self.zeros > 0 {
+                self.zeros -= 1;
+                return Ok(0)
+            }
+        }
+
+        self.unary = false;
+        let sign = bits.read(1)?;
+        self.kp = self.kp.saturating_sub(6);
+
+        let max = (1 << num_bits) - 1;
+        let mut magnitude = 1;
+        while magnitude < max && bits.read(1)? == 0 {
+            magnitude += 1;
+        }
+        Ok(if sign != 0 { -magnitude } else { magnitude })
+    }
+}
+
+/// Decoding state of a tile
+/// kept between passes
+struct ProgressiveTile {
+    /// Quality of the last pass
+    quality: u8,
+    /// Bit position of the last pass by component
+    bit_pos: [[u8; 10]; 3],
+    /// Dequantized coefficients by component
+    current: [Vec<i16>; 3],
+    /// Sign of coefficients, zero while not significant
+    sign: [Vec<i16>; 3],
+    /// 64x64 pixels in BGRA
+    data: Vec<u8>
+}
+
+impl ProgressiveTile {
+    fn new() -> Self {
+        ProgressiveTile {
+            quality: 0,
+            bit_pos: [[0; 10]; 3],
+            current: [vec![0; PROGRESSIVE_TILE_COEFFICIENTS], vec![0; PROGRESSIVE_TILE_COEFFICIENTS], vec![0; PROGRESSIVE_TILE_COEFFICIENTS]],
+            sign: [vec![0; PROGRESSIVE_TILE_COEFFICIENTS], vec![0; PROGRESSIVE_TILE_COEFFICIENTS], vec![0; PROGRESSIVE_TILE_COEFFICIENTS]],
+            data: vec![0; PROGRESSIVE_TILE_COEFFICIENTS * 4]
+        }
+    }
+
+    /// Rebuild pixels from coefficients
+    fn render(&mut self, extrapolate: bool) {
+        let mut components = self.current.clone();
+        for component in components.iter_mut() {
+            if extrapolate {
+                idwt_2d_extrapolate(component);
+            } else {
+                idwt_2d(component);
+            }
+        }
+        for (i, pixel) in self.data.chunks_mut(4).enumerate() {
+            ycbcr_to_bgra(components[0][i], components[1][i], components[2][i], pixel);
+        }
+    }
+}
+
+/// Tiles of a surface
+struct ProgressiveSurface {
+    width: u16,
+    height: u16,
+    tiles: HashMap<(u16, u16), ProgressiveTile>
+}
+
+/// Tables shared by the tiles of a region
+struct Region {
+    rects: Vec<(u16, u16, u16, u16)>,
+    quants: Vec<[u8; 10]>,
+    /// Progressive quantization by quality, one per component
+    prog_quants: Vec<[[u8; 10]; 3]>,
+    extrapolate: bool
+}
+
+impl Region {
+    /// Quantization values of a component
+    fn get_quant(&self, index: u8) -> RdpResult<&[u8; 10]> {
+        self.quants.get(index as usize).ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PROGRESSIVE: Invalid quantization index")))
+    }
+
+    /// Progressive quantization values of a quality
+    fn get_prog_quant(&self, quality: u8) -> RdpResult<[[u8; 10]; 3]> {
+        if quality == PROGRESSIVE_QUALITY_FULL {
+            return Ok([[0; 10]; 3])
+        }
+        self.prog_quants.get(quality as usize).copied().ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PROGRESSIVE: Invalid quality")))
+    }
+}
+
+/// Read a component data of a tile
+fn read_data(block: &mut dyn Read, length: u16) -> RdpResult<Vec<u8>> {
+    let mut data = vec![0; length as usize];
+    block.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Decode the first pass of a tile component
+/// Coefficients are added to the previous ones if difference is set
+fn decode_component(data: &[u8], shift: &[u8; 10], extrapolate: bool, difference: bool, current: &mut [i16], sign: &mut [i16]) -> RdpResult<()> {
+    let mut coefficients = vec![0_i16; PROGRESSIVE_TILE_COEFFICIENTS];
+    rlgr_decode(data, EntropyAlgorithm::ClwEntropyRlgr1, &mut coefficients)?;
+    sign.copy_from_slice(&coefficients);
+
+    let sub_bands = if extrapolate { &EXTRAPOLATE_SUB_BANDS } else { &SUB_BANDS };
+
+    // LL3 band is differential encoded
+    let (ll3_offset, _, _) = sub_bands[9];
+    for i in ll3_offset + 1..PROGRESSIVE_TILE_COEFFICIENTS {
+        coefficients[i] = coefficients[i].wrapping_add(coefficients[i - 1]);
+    }
+
+    for (offset, size, quant_index) in sub_bands.iter() {
+        for coefficient in coefficients[*offset..*offset + *size].iter_mut() {
+            *coefficient = ((*coefficient as i32) << shift[*quant_index]) as i16;
+        }
+    }
+
+    for (value, coefficient) in current.iter_mut().zip(coefficients) {
+        *value = if difference { value.wrapping_add(coefficient) } else { coefficient };
+    }
+    Ok(())
+}
+
+/// Refine a tile component with an upgrade pass
+/// Significant coefficients read their bits from the raw stream
+/// others from the SRL stream
+fn upgrade_component(srl_data: &[u8], raw_data: &[u8], shift: &[u8; 10], num_bits: &[u8; 10], extrapolate: bool, current: &mut [i16], sign: &mut [i16]) -> RdpResult<()> {
+    let mut srl = BitReader::new(srl_data);
+    let mut raw = BitReader::new(raw_data);
+    let mut srl_decoder = SrlDecoder::new();
+    let sub_bands = if extrapolate { &EXTRAPOLATE_SUB_BANDS } else { &SUB_BANDS };
+
+    for (band, (offset, size, quant_index)) in sub_bands.iter().enumerate() {
+        let band_bits = num_bits[*quant_index];
+        if band_bits == 0 {
+            continue;
+        }
+
+        for i in *offset..*offset + *size {
+            let input = if band == 9 {
+                // LL3 is always read raw
+                raw.read(band_bits as usize)? as i32
+            } else if sign[i] != 0 {
+                let magnitude = raw.read(band_bits as usize)? as i32;
+                if sign[i] > 0 { magnitude } else { magnitude.wrapping_neg() }
+            } else {
+                let value = srl_decoder.read(&mut srl, band_bits)?;
+                sign[i] = value as i16;
+                value
+            };
+            current[i] = (current[i] as i32).wrapping_add(input.wrapping_shl(shift[*quant_index] as u32)) as i16;
+        }
+    }
+    Ok(())
+}
+
+/// RemoteFX progressive decoder
+/// Tiles are kept by surface to be refined by upgrade passes
+/// Output is 32 bpp BGRA, top-down
+///
+/// See MS-RDPEGFX 3.2.8.1 RemoteFX Progressive Codec
+///
+/// # Example
+/// ```
+/// use rdp::codec::progressive::ProgressiveDecoder;
+/// let mut decoder = ProgressiveDecoder::new();
+/// decoder.create_surface(1, 64, 64);
+/// // only a sync block
+/// let rects = decoder.decode(1, &[0xC0, 0xCC, 0x0C, 0, 0, 0, 0xCA, 0xAC, 0xCC, 0xCA, 0, 1]).unwrap();
+/// assert!(rects.is_empty());
+/// ```
+pub struct ProgressiveDecoder {
+    /// Tile state by surface id
+    surfaces: HashMap<u16, ProgressiveSurface>
+}
+
+impl ProgressiveDecoder {
+    pub fn new() -> Self {
+        ProgressiveDecoder {
+            surfaces: HashMap::new()
+        }
+    }
+
+    /// Create or reset the tile state of a surface
+    pub fn create_surface(&mut self, surface_id: u16, width: u16, height: u16) {
+        self.surfaces.insert(surface_id, ProgressiveSurface {
+            width,
+            height,
+            tiles: HashMap::new()
+        });
+    }
+
+    /// Forget the tile state of a surface
+    pub fn delete_surface(&mut self, surface_id: u16) {
+        self.surfaces.remove(&surface_id);
+    }
+
+    /// Decode a progressive bitmap stream for a surface
+    /// Return updated rectangles in surface coordinates
+    pub fn decode(&mut self, surface_id: u16, input: &[u8]) -> RdpResult<Vec<SurfaceRect>> {
+        let surface = self.surfaces.get_mut(&surface_id).ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PROGRESSIVE: Unknown surface")))?;
+        let mut rects = Vec::new();
+        let mut stream = Cursor::new(input);
+
+        while (stream.position() as usize) < input.len() {
+            let start = stream.position() as usize;
+            let block_type = stream.read_u16::<LittleEndian>()?;
+            let block_length = stream.read_u32::<LittleEndian>()? as usize;
+            if block_length < 6 || start + block_length > input.len() {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "PROGRESSIVE: Invalid block length")))
+            }
+            let mut block = Cursor::new(&input[start + 6..start + block_length]);
+            stream.set_position((start + block_length) as u64);
+
+            match ProgressiveBlockType::try_from(block_type) {
+                Ok(ProgressiveBlockType::WbtSync) => {
+                    if block.read_u32::<LittleEndian>()? != PROGRESSIVE_WF_MAGIC {
+                        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PROGRESSIVE: Invalid sync block")))
+                    }
+                },
+                Ok(ProgressiveBlockType::WbtContext) => {
+                    block.read_u8()?;
+                    if block.read_u16::<LittleEndian>()? as usize != RFX_TILE_SIZE {
+                        return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "PROGRESSIVE: Only 64 pixels tiles are supported")))
+                    }
+                },
+                Ok(ProgressiveBlockType::WbtRegion) => rects.append(&mut Self::read_region(surface, &mut block)?),
+                Ok(ProgressiveBlockType::WbtFrameBegin) | Ok(ProgressiveBlockType::WbtFrameEnd) => (),
+                _ => return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, &format!("PROGRESSIVE: Unexpected block type {:x}", block_type))))
+            }
+        }
+        Ok(rects)
+    }
+
+    /// Region block, quantization tables followed by tiles
+    /// Updated tiles are clipped into region rectangles
+    ///
+    /// See MS-RDPEGFX 2.2.4.2.1.5 RFX_PROGRESSIVE_REGION
+    fn read_region(surface: &mut ProgressiveSurface, block: &mut Cursor<&[u8]>) -> RdpResult<Vec<SurfaceRect>> {
+        if block.read_u8()? as usize != RFX_TILE_SIZE {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "PROGRESSIVE: Only 64 pixels tiles are supported")))
+        }
+        let number_rects = block.read_u16::<LittleEndian>()?;
+        let number_quant = block.read_u8()?;
+        let number_prog_quant = block.read_u8()?;
+        let flags = block.read_u8()?;
+        block.read_u16::<LittleEndian>()?;
+        let tile_data_size = block.read_u32::<LittleEndian>()? as usize;
+
+        let mut region = Region {
+            rects: Vec::with_capacity(number_rects as usize),
+            quants: Vec::with_capacity(number_quant as usize),
+            prog_quants: Vec::with_capacity(number_prog_quant as usize),
+            extrapolate: flags & RegionFlag::RfxDwtReduceExtrapolate as u8 != 0
+        };
+        for _ in 0..number_rects {
+            region.rects.push((
+                block.read_u16::<LittleEndian>()?,
+                block.read_u16::<LittleEndian>()?,
+                block.read_u16::<LittleEndian>()?,
+                block.read_u16::<LittleEndian>()?
+            ));
+        }
+        for _ in 0..number_quant {
+            region.quants.push(read_quant(block)?);
+        }
+        for _ in 0..number_prog_quant {
+            // quality
+            block.read_u8()?;
+            region.prog_quants.push([read_quant(block)?, read_quant(block)?, read_quant(block)?]);
+        }
+
+        let data = *block.get_ref();
+        let tiles_start = block.position() as usize;
+        if tile_data_size > data.len() - tiles_start {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "PROGRESSIVE: Tiles overflow region")))
+        }
+
+        let mut updated = Vec::new();
+        let mut tiles = Cursor::new(&data[tiles_start..tiles_start + tile_data_size]);
+        while (tiles.position() as usize) < tile_data_size {
+            let start = tiles.position() as usize;
+            let block_type = tiles.read_u16::<LittleEndian>()?;
+            let block_length = tiles.read_u32::<LittleEndian>()? as usize;
+            if block_length < 6 || start + block_length > tile_data_size {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "PROGRESSIVE: Invalid tile length")))
+            }
+            let mut tile = Cursor::new(&tiles.get_ref()[start + 6..start + block_length]);
+            tiles.set_position((start + block_length) as u64);
+
+            let position = match ProgressiveBlockType::try_from(block_type) {
+                Ok(ProgressiveBlockType::WbtTileSimple) => Self::read_tile_first(surface, &region, &mut tile, false)?,
+                Ok(ProgressiveBlockType::WbtTileFirst) => Self::read_tile_first(surface, &region, &mut tile, true)?,
+                Ok(ProgressiveBlockType::WbtTileUpgrade) => Self::read_tile_upgrade(surface, &region, &mut tile)?,
+                _ => return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PROGRESSIVE: Invalid tile block")))
+            };
+            if !updated.contains(&position) {
+                updated.push(position);
+            }
+        }
+
+        Ok(Self::compose(surface, &region, &updated))
+    }
+
+    /// Tile position in the grid
+    /// Must be inside the surface
+    fn read_tile_position(surface: &ProgressiveSurface, block: &mut dyn Read) -> RdpResult<(u16, u16)> {
+        let x = block.read_u16::<LittleEndian>()?;
+        let y = block.read_u16::<LittleEndian>()?;
+        if x as usize * RFX_TILE_SIZE >= surface.width as usize || y as usize * RFX_TILE_SIZE >= surface.height as usize {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PROGRESSIVE: Tile outside of surface")))
+        }
+        Ok((x, y))
+    }
+
+    /// Simple and first tiles replace the tile
+    /// or add to it when the difference flag is set
+    ///
+    /// See MS-RDPEGFX 2.2.4.2.1.5.3 RFX_PROGRESSIVE_TILE_FIRST
+    fn read_tile_first(surface: &mut ProgressiveSurface, region: &Region, block: &mut dyn Read, has_quality: bool) -> RdpResult<(u16, u16)> {
+        let quant_index = [block.read_u8()?, block.read_u8()?, block.read_u8()?];
+        let position = Self::read_tile_position(surface, block)?;
+        let flags = block.read_u8()?;
+        let quality = if has_quality { block.read_u8()? } else { PROGRESSIVE_QUALITY_FULL };
+        let lengths = [block.read_u16::<LittleEndian>()?, block.read_u16::<LittleEndian>()?, block.read_u16::<LittleEndian>()?];
+        // tail length
+        block.read_u16::<LittleEndian>()?;
+
+        let prog_quant = region.get_prog_quant(quality)?;
+        let tile = surface.tiles.entry(position).or_insert_with(ProgressiveTile::new);
+        for i in 0..3 {
+            let bit_pos = quant_add(region.get_quant(quant_index[i])?, &prog_quant[i]);
+            let shift = band_shift(&bit_pos);
+            let data = read_data(block, lengths[i])?;
+            decode_component(&data, &shift, region.extrapolate, flags & TileFlag::RfxTileDifference as u8 != 0, &mut tile.current[i], &mut tile.sign[i])?;
+            tile.bit_pos[i] = bit_pos;
+        }
+        tile.quality = quality;
+        tile.render(region.extrapolate);
+        Ok(position)
+    }
+
+    /// Upgrade tiles refine a tile up to a better quality
+    ///
+    /// See MS-RDPEGFX 2.2.4.2.1.5.4 RFX_PROGRESSIVE_TILE_UPGRADE
+    fn read_tile_upgrade(surface: &mut ProgressiveSurface, region: &Region, block: &mut dyn Read) -> RdpResult<(u16, u16)> {
+        let quant_index = [block.read_u8()?, block.read_u8()?, block.read_u8()?];
+        let position = Self::read_tile_position(surface, block)?;
+        let quality = block.read_u8()?;
+        let mut lengths = [0_u16; 6];
+        for length in lengths.iter_mut() {
+            *length = block.read_u16::<LittleEndian>()?;
+        }
+
+        let prog_quant = region.get_prog_quant(quality)?;
+        let tile = surface.tiles.get_mut(&position).ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PROGRESSIVE: Upgrade of an unknown tile")))?;
+        for i in 0..3 {
+            let bit_pos = quant_add(region.get_quant(quant_index[i])?, &prog_quant[i]);
+            let mut num_bits = [0_u8; 10];
+            for band in 0..10 {
+                num_bits[band] = tile.bit_pos[i][band].checked_sub(bit_pos[band]).ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "PROGRESSIVE: Upgrade to a lower quality")))?;
+            }
+            let shift = band_shift(&bit_pos);
+            let srl_data = read_data(block, lengths[i * 2])?;
+            let raw_data = read_data(block, lengths[i * 2 + 1])?;
+            upgrade_component(&srl_data, &raw_data, &shift, &num_bits, region.extrapolate, &mut tile.current[i], &mut tile.sign[i])?;
+            tile.bit_pos[i] = bit_pos;
+        }
+        tile.quality = quality;
+        tile.render(region.extrapolate);
+        Ok(position)
+    }
+
+    /// Clip updated tiles into region rectangles
+    fn compose(surface: &ProgressiveSurface, region: &Region, updated: &[(u16, u16)]) -> Vec<SurfaceRect> {
+        let mut result = Vec::new();
+        for position in updated {
+            let tile = &surface.tiles[position];
+            let (tile_x, tile_y) = (position.0 as usize * RFX_TILE_SIZE, position.1 as usize * RFX_TILE_SIZE);
+            for (left, top, width, height) in region.rects.iter() {
+                let (left, top) = (*left as usize, *top as usize);
+                let right = (left + *width as usize).min(surface.width as usize).min(tile_x + RFX_TILE_SIZE);
+                let bottom = (top + *height as usize).min(surface.height as usize).min(tile_y + RFX_TILE_SIZE);
+                let (left, top) = (left.max(tile_x), top.max(tile_y));
+                if left >= right || top >= bottom {
+                    continue;
+                }
+
+                let mut data = Vec::with_capacity((right - left) * (bottom - top) * 4);
+                for y in top..bottom {
+                    let source = ((y - tile_y) * RFX_TILE_SIZE + left - tile_x) * 4;
+                    data.extend_from_slice(&tile.data[source..source + (right - left) * 4]);
+                }
+                result.push(SurfaceRect {
+                    left: left as u16,
+                    top: top as u16,
+                    width: (right - left) as u16,
+                    height: (bottom - top) as u16,
+                    data
+                });
+            }
+        }
+        result
+    }
+}
+
+impl Default for ProgressiveDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Region with one quantization table, one progressive quality
+    /// and a single rectangle covering the first tile
+    fn region(tile: &[u8]) -> Vec<u8> {
+        let mut result = vec![0xC4, 0xCC];
+        result.extend_from_slice(&(18 + 8 + 5 + 16 + tile.len() as u32).to_le_bytes());
+        result.extend_from_slice(&[64, 1, 0, 1, 1, RegionFlag::RfxDwtReduceExtrapolate as u8, 1, 0]);
+        result.extend_from_slice(&(tile.len() as u32).to_le_bytes());
+        result.extend_from_slice(&[0, 0, 0, 0, 64, 0, 64, 0]);
+        result.extend_from_slice(&[0x66; 5]);
+        result.push(0);
+        result.extend_from_slice(&[0x11; 15]);
+        result.extend_from_slice(tile);
+        result
+    }
+
+    /// Constant LL3 band gives a constant tile
+    #[test]
+    fn test_idwt_2d_extrapolate() {
+        let mut coefficients = vec![0_i16; PROGRESSIVE_TILE_COEFFICIENTS];
+        coefficients[4015..].iter_mut().for_each(|x| *x = 100);
+        idwt_2d_extrapolate(&mut coefficients);
+        assert!(coefficients.iter().all(|x| *x == 100));
+    }
+
+    /// Runs of zeros then a unary coded value
+    #[test]
+    fn test_srl_decoder() {
+        let data = [0x54];
+        let mut bits = BitReader::new(&data);
+        let mut srl = SrlDecoder::new();
+        let values = (0..3).map(|_| srl.read(&mut bits, 2).unwrap()).collect::<Vec<i32>>();
+        assert_eq!(values, [0, 0, -2]);
+    }
+
+    /// First pass then full quality upgrade of the same tile
+    #[test]
+    fn test_progressive_upgrade() {
+        let mut decoder = ProgressiveDecoder::new();
+        decoder.create_surface(1, 64, 32);
+
+        let mut input = vec![0xC3, 0xCC, 10, 0, 0, 0, 0, 64, 0, 0];
+        input.extend(region(&[0xC6, 0xCC, 23, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+        let rects = decoder.decode(1, &input).unwrap();
+        assert_eq!(rects.len(), 1);
+        assert_eq!((rects[0].left, rects[0].top, rects[0].width, rects[0].height), (0, 0, 64, 32));
+        assert!(rects[0].data.chunks(4).all(|pixel| pixel == [128, 128, 128, 0xff]));
+
+        // srl only encodes zeros, LL3 of luma gets one more bit
+        let mut upgrade = vec![0xC7, 0xCC, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, PROGRESSIVE_QUALITY_FULL, 3, 0, 11, 0, 3, 0, 11, 0, 3, 0, 11, 0];
+        upgrade.extend_from_slice(&[0; 3]);
+        upgrade.extend_from_slice(&[0xff; 10]);
+        upgrade.push(0x80);
+        upgrade.extend_from_slice(&[0; 28]);
+        let length = upgrade.len() as u32;
+        upgrade[2..6].copy_from_slice(&length.to_le_bytes());
+
+        let rects = decoder.decode(1, &region(&upgrade)).unwrap();
+        assert!(rects[0].data.chunks(4).all(|pixel| pixel == [129, 129, 129, 0xff]));
+    }
+
+    /// Hostile bit counts and shifts must wrap instead of overflowing
+    #[test]
+    fn test_upgrade_component_overflow() {
+        let mut num_bits = [0_u8; 10];
+        num_bits[0] = 17;
+        let mut current = vec![0x7fff_i16; PROGRESSIVE_TILE_COEFFICIENTS];
+        let mut sign = vec![0_i16; PROGRESSIVE_TILE_COEFFICIENTS];
+        upgrade_component(&[], &[0xff; 136], &[14; 10], &num_bits, false, &mut current, &mut sign).unwrap();
+    }
+
+    /// Decoding needs a surface created by the caller
+    #[test]
+    fn test_progressive_unknown_surface() {
+        let mut decoder = ProgressiveDecoder::new();
+        assert!(decoder.decode(1, &[]).is_err());
+    }
+
+    /// Upgrade of a tile never received
+    #[test]
+    fn test_progressive_upgrade_without_first_pass() {
+        let mut decoder = ProgressiveDecoder::new();
+        decoder.create_surface(1, 64, 64);
+        let upgrade = [0xC7, 0xCC, 26, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, PROGRESSIVE_QUALITY_FULL, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(decoder.decode(1, &region(&upgrade)).is_err());
+    }
+
+    /// Tile outside of the surface
+    #[test]
+    fn test_progressive_tile_outside_surface() {
+        let mut decoder = ProgressiveDecoder::new();
+        decoder.create_surface(1, 64, 64);
+        assert!(decoder.decode(1, &region(&[0xC5, 0xCC, 22, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])).is_err());
+    }
+
+    /// Block length larger than the stream
+    #[test]
+    fn test_progressive_truncated_block() {
+        let mut decoder = ProgressiveDecoder::new();
+        decoder.create_surface(1, 64, 64);
+        assert!(decoder.decode(1, &[0xC0, 0xCC, 0xff, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,750 @@
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+use codec::mppc::BitReader;
+use codec::surface::{SurfaceDecoder, SurfaceRect};
+use std::io::{Cursor, Read};
+use byteorder::{ReadBytesExt, LittleEndian};
+use num_enum::TryFromPrimitive;
+use std::convert::TryFrom;
+
+/// Size in pixels of a RemoteFX tile side
+pub const RFX_TILE_SIZE: usize = 64;
+
+/// Number of coefficients in a tile component
+const RFX_TILE_COEFFICIENTS: usize = RFX_TILE_SIZE * RFX_TILE_SIZE;
+
+/// Magic number of the sync message
+const WF_MAGIC: u32 = 0xCACCACCA;
+
+/// Version of the RemoteFX protocol
+const WF_VERSION_1_0: u16 = 0x0100;
+
+/// RemoteFX codec id used in codec channel headers
+const RFX_CODEC_ID: u8 = 0x01;
+
+/// RemoteFX message block types
+///
+/// See MS-RDPRFX 2.2.2.1.1 TS_RFX_BLOCKT
+#[repr(u16)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, TryFromPrimitive)]
+pub enum RfxBlockType {
+    WbtSync = 0xCCC0,
+    WbtCodecVersions = 0xCCC1,
+    WbtChannels = 0xCCC2,
+    WbtContext = 0xCCC3,
+    WbtFrameBegin = 0xCCC4,
+    WbtFrameEnd = 0xCCC5,
+    WbtRegion = 0xCCC6,
+    WbtExtension = 0xCCC7,
+    CbtRegion = 0xCAC1,
+    CbtTileset = 0xCAC2,
+    CbtTile = 0xCAC3
+}
+
+/// Entropy algorithm used for tiles
+/// set in the context message properties
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, TryFromPrimitive)]
+pub enum EntropyAlgorithm {
+    ClwEntropyRlgr1 = 0x01,
+    ClwEntropyRlgr3 = 0x04
+}
+
+/// Adaptive parameters of the RLGR entropy coder
+///
+/// See MS-RDPRFX 3.1.8.1.7.3 RLGR1/RLGR3 Pseudocode
+const KPMAX: u32 = 80;
+const LSGR: u32 = 3;
+const UP_GR: u32 = 4;
+const DN_GR: u32 = 6;
+const UQ_GR: u32 = 3;
+const DQ_GR: u32 = 3;
+
+/// Write a run of zeros in the coefficient buffer
+fn write_zeros(output: &mut [i16], index: &mut usize, count: u32) {
+    let end = (*index + count as usize).min(output.len());
+    output[*index..end].iter_mut().for_each(|x| *x = 0);
+    *index = end;
+}
+
+/// Write a single coefficient in the buffer
+fn write_value(output: &mut [i16], index: &mut usize, value: i32) {
+    if *index < output.len() {
+        output[*index] = value as i16;
+        *index += 1;
+    }
+}
+
+/// Lowest bit of a magnitude is its sign
+fn from_2_mag_sign(mag: u32) -> i32 {
+    if mag & 1 != 0 {
+        -(((mag + 1) >> 1) as i32)
+    } else {
+        (mag >> 1) as i32
+    }
+}
+
+/// Read a Golomb-Rice code and update its adaptive parameter
+fn read_gr_code(bits: &mut BitReader, krp: &mut u32, kr: &mut u32) -> RdpResult<u32> {
+    // unary prefix of 1
+    let mut vk = 0;
+    while bits.read(1)? == 1 {
+        vk += 1;
+        if vk > 32 {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "RFX: Invalid Golomb-Rice code")))
+        }
+    }
+
+    let mag = (vk << *kr) | bits.read(*kr as usize)?;
+
+    if vk == 0 {
+        *krp = krp.saturating_sub(2);
+    } else if vk != 1 {
+        *krp = (*krp + vk).min(KPMAX);
+    }
+    *kr = *krp >> LSGR;
+    Ok(mag)
+}
+
+/// Decode RLGR1 or RLGR3 coefficients
+/// Missing coefficients at the end of the stream are zeros
+///
+/// See MS-RDPRFX 3.1.8.1.7.3 RLGR1/RLGR3 Pseudocode
+pub fn rlgr_decode(input: &[u8], mode: EntropyAlgorithm, output: &mut [i16]) -> RdpResult<()> {
+    let mut bits = BitReader::new(input);
+    let mut kp = 1 << LSGR;
+    let mut k = kp >> LSGR;
+    let mut krp = 1 << LSGR;
+    let mut kr = krp >> LSGR;
+    let mut index = 0;
+
+    while index < output.len() && bits.remaining() > 0 {
+        if k != 0 {
+            // Run length mode, each 0 is a full run of 2^k zeros
+            while bits.remaining() > 0 && bits.peek(1) == 0 {
+                bits.read(1)?;
+                write_zeros(output, &mut index, 1 << k);
+                kp = (kp + UP_GR).min(KPMAX);
+                k = kp >> LSGR;
+            }
+
+            if bits.remaining() == 0 {
+                break;
+            }
+            bits.read(1)?;
+
+            // remaining of the run
+            let run = bits.read(k as usize)?;
+            write_zeros(output, &mut index, run);
+
+            let sign = bits.read(1)?;
+            let mag = read_gr_code(&mut bits, &mut krp, &mut kr)? as i32 + 1;
+            write_value(output, &mut index, if sign != 0 { -mag } else { mag });
+
+            kp = kp.saturating_sub(DN_GR);
+            k = kp >> LSGR;
+        } else {
+            // Golomb-Rice mode
+            let mag = read_gr_code(&mut bits, &mut krp, &mut kr)?;
+            match mode {
+                EntropyAlgorithm::ClwEntropyRlgr1 => {
+                    if mag == 0 {
+                        write_value(output, &mut index, 0);
+                        kp = (kp + UQ_GR).min(KPMAX);
+                    } else {
+                        write_value(output, &mut index, from_2_mag_sign(mag));
+                        kp = kp.saturating_sub(DQ_GR);
+                    }
+                },
+                EntropyAlgorithm::ClwEntropyRlgr3 => {
+                    // mag is the sum of two values
+                    let first_size = 32 - mag.leading_zeros();
+                    let first = bits.read(first_size as usize)?;
+                    if first > mag {
+                        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "RFX: Invalid RLGR3 code")))
+                    }
+                    let second = mag - first;
+
+                    if first != 0 && second != 0 {
+                        kp = kp.saturating_sub(2 * DQ_GR);
+                    } else if first == 0 && second == 0 {
+                        kp = (kp + 2 * UQ_GR).min(KPMAX);
+                    }
+
+                    write_value(output, &mut index, from_2_mag_sign(first));
+                    write_value(output, &mut index, from_2_mag_sign(second));
+                }
+            }
+            k = kp >> LSGR;
+        }
+    }
+
+    write_zeros(output, &mut index, output.len() as u32);
+    Ok(())
+}
+
+/// Sub bands offset and size in the coefficient buffer
+/// with the index of their quantization value
+///
+/// Quantization values are ordered as
+/// LL3, LH3, HL3, HH3, LH2, HL2, HH2, LH1, HL1, HH1
+const SUB_BANDS: [(usize, usize, usize); 10] = [
+    (0, 1024, 8),       // HL1
+    (1024, 1024, 7),    // LH1
+    (2048, 1024, 9),    // HH1
+    (3072, 256, 5),     // HL2
+    (3328, 256, 4),     // LH2
+    (3584, 256, 6),     // HH2
+    (3840, 64, 2),      // HL3
+    (3904, 64, 1),      // LH3
+    (3968, 64, 3),      // HH3
+    (4032, 64, 0)       // LL3
+];
+
+/// Scale coefficients by their quantization factor
+///
+/// See MS-RDPRFX 3.1.8.1.5 Quantization
+fn dequantize(coefficients: &mut [i16], quant: &[u8; 10]) {
+    for (offset, size, quant_index) in SUB_BANDS.iter() {
+        let factor = quant[*quant_index].saturating_sub(1);
+        for coefficient in coefficients[*offset..*offset + *size].iter_mut() {
+            *coefficient = ((*coefficient as i32) << factor) as i16;
+        }
+    }
+}
+
+/// One dimension inverse lifting
+/// output is twice the size of low and high
+fn idwt_1d(low: &[i16], high: &[i16], output: &mut [i16]) {
+    let n = low.len();
+
+    // even coefficients
+    output[0] = (low[0] as i32 - ((high[0] as i32 * 2 + 1) >> 1)) as i16;
+    for i in 1..n {
+        output[2 * i] = (low[i] as i32 - ((high[i - 1] as i32 + high[i] as i32 + 1) >> 1)) as i16;
+    }
+
+    // odd coefficients
+    for i in 0..n - 1 {
+        output[2 * i + 1] = (((high[i] as i32) << 1) + ((output[2 * i] as i32 + output[2 * i + 2] as i32) >> 1)) as i16;
+    }
+    output[2 * n - 1] = (((high[n - 1] as i32) << 1) + output[2 * n - 2] as i32) as i16;
+}
+
+/// Inverse DWT of one level
+/// Sub bands are stored in HL, LH, HH, LL order
+/// Result is written back in place
+fn idwt_2d_block(buffer: &mut [i16], subband_width: usize) {
+    let total_width = subband_width * 2;
+    let subband_size = subband_width * subband_width;
+    let mut rows = vec![0_i16; total_width * total_width];
+
+    // horizontal pass, L rows on top of H rows
+    for y in 0..subband_width {
+        let line = y * subband_width..(y + 1) * subband_width;
+        let (hl, lh, hh, ll) = (
+            &buffer[line.start..line.end],
+            &buffer[subband_size + line.start..subband_size + line.end],
+            &buffer[2 * subband_size + line.start..2 * subband_size + line.end],
+            &buffer[3 * subband_size + line.start..3 * subband_size + line.end]
+        );
+        idwt_1d(ll, hl, &mut rows[y * total_width..(y + 1) * total_width]);
+        idwt_1d(lh, hh, &mut rows[(subband_width + y) * total_width..(subband_width + y + 1) * total_width]);
+    }
+
+    // vertical pass
+    let mut low = vec![0_i16; subband_width];
+    let mut high = vec![0_i16; subband_width];
+    let mut column = vec![0_i16; total_width];
+    for x in 0..total_width {
+        for n in 0..subband_width {
+            low[n] = rows[n * total_width + x];
+            high[n] = rows[(subband_width + n) * total_width + x];
+        }
+        idwt_1d(&low, &high, &mut column);
+        for (n, value) in column.iter().enumerate() {
+            buffer[n * total_width + x] = *value;
+        }
+    }
+}
+
+/// Inverse DWT of the three levels
+///
+/// See MS-RDPRFX 3.1.8.1.4 Discrete Wavelet Transform
+pub fn idwt_2d(coefficients: &mut [i16]) {
+    idwt_2d_block(&mut coefficients[3840..], 8);
+    idwt_2d_block(&mut coefficients[3072..], 16);
+    idwt_2d_block(&mut coefficients[0..], 32);
+}
+
+/// Convert YCbCr coefficients into a 32 bpp BGRA pixel
+/// Values are fixed point with 5 fractional bits
+///
+/// See MS-RDPRFX 3.1.8.1.3 Color Conversion
+pub fn ycbcr_to_bgra(y: i16, cb: i16, cr: i16, output: &mut [u8]) {
+    let y = ((y as i64) + 4096) << 16;
+    let (cb, cr) = (cb as i64, cr as i64);
+    let red = ((((cr * 91916) + y) >> 16) as i16) >> 5;
+    let green = (((y - cb * 22526 - cr * 46819) >> 16) as i16) >> 5;
+    let blue = ((((cb * 115992) + y) >> 16) as i16) >> 5;
+
+    output[0] = blue.max(0).min(255) as u8;
+    output[1] = green.max(0).min(255) as u8;
+    output[2] = red.max(0).min(255) as u8;
+    output[3] = 0xff;
+}
+
+/// Decode one color component of a tile
+fn decode_component(data: &[u8], quant: &[u8; 10], entropy: EntropyAlgorithm) -> RdpResult<Vec<i16>> {
+    let mut coefficients = vec![0_i16; RFX_TILE_COEFFICIENTS];
+    rlgr_decode(data, entropy, &mut coefficients)?;
+
+    // LL3 band is differential encoded
+    for i in 4033..RFX_TILE_COEFFICIENTS {
+        coefficients[i] = coefficients[i].wrapping_add(coefficients[i - 1]);
+    }
+
+    dequantize(&mut coefficients, quant);
+    idwt_2d(&mut coefficients);
+    Ok(coefficients)
+}
+
+/// Unpack quantization values
+/// Each value is 4 bits wide
+pub fn read_quant(input: &mut dyn Read) -> RdpResult<[u8; 10]> {
+    let mut quant = [0_u8; 10];
+    for i in 0..5 {
+        let value = input.read_u8()?;
+        quant[i * 2] = value & 0x0F;
+        quant[i * 2 + 1] = value >> 4;
+    }
+    Ok(quant)
+}
+
+/// A whole decoded frame
+pub struct RfxFrame {
+    /// Index sent in the frame begin message
+    pub frame_index: u32,
+    /// Region of the frame updated
+    pub rects: Vec<SurfaceRect>
+}
+
+/// A decoded tile
+struct RfxTile {
+    x: usize,
+    y: usize,
+    /// 64x64 pixels in BGRA
+    data: Vec<u8>
+}
+
+/// Frame being decoded
+#[derive(Default)]
+struct PendingFrame {
+    frame_index: u32,
+    rects: Vec<(u16, u16, u16, u16)>,
+    tiles: Vec<RfxTile>
+}
+
+/// RemoteFX decoder
+/// Keep the context sent by the server between messages
+///
+/// See MS-RDPRFX 3.1.8 Decoding
+///
+/// # Example
+/// ```
+/// use rdp::codec::rfx::RfxDecoder;
+/// let mut decoder = RfxDecoder::new();
+/// // only a sync message
+/// let frames = decoder.decode(&[0xC0, 0xCC, 0x0C, 0, 0, 0, 0xCA, 0xAC, 0xCC, 0xCA, 0, 1]).unwrap();
+/// assert!(frames.is_empty());
+/// ```
+pub struct RfxDecoder {
+    /// Entropy algorithm set by the context
+    entropy: EntropyAlgorithm,
+    /// Channel size (width, height)
+    channel: Option<(u16, u16)>,
+    /// Frame being decoded
+    frame: Option<PendingFrame>
+}
+
+impl RfxDecoder {
+    pub fn new() -> Self {
+        RfxDecoder {
+            entropy: EntropyAlgorithm::ClwEntropyRlgr3,
+            channel: None,
+            frame: None
+        }
+    }
+
+    /// Size of the channel sent by the server
+    pub fn get_channel_size(&self) -> Option<(u16, u16)> {
+        self.channel
+    }
+
+    /// Decode a RemoteFX message stream
+    /// Return all frames ended in this stream
+    pub fn decode(&mut self, input: &[u8]) -> RdpResult<Vec<RfxFrame>> {
+        let mut frames = Vec::new();
+        let mut stream = Cursor::new(input);
+
+        while (stream.position() as usize) < input.len() {
+            let start = stream.position() as usize;
+            let block_type = stream.read_u16::<LittleEndian>()?;
+            let block_length = stream.read_u32::<LittleEndian>()? as usize;
+            if block_length < 6 || start + block_length > input.len() {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "RFX: Invalid block length")))
+            }
+            let mut block = Cursor::new(&input[start + 6..start + block_length]);
+            stream.set_position((start + block_length) as u64);
+
+            let block_type = RfxBlockType::try_from(block_type).map_err(|_| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, &format!("RFX: Unknown block type {:x}", block_type))))?;
+
+            // codec channel header
+            match block_type {
+                RfxBlockType::WbtContext | RfxBlockType::WbtFrameBegin | RfxBlockType::WbtFrameEnd | RfxBlockType::WbtRegion | RfxBlockType::WbtExtension => {
+                    if block.read_u8()? != RFX_CODEC_ID {
+                        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "RFX: Invalid codec id")))
+                    }
+                    block.read_u8()?;
+                },
+                _ => ()
+            }
+
+            match block_type {
+                RfxBlockType::WbtSync => {
+                    if block.read_u32::<LittleEndian>()? != WF_MAGIC || block.read_u16::<LittleEndian>()? != WF_VERSION_1_0 {
+                        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "RFX: Invalid sync message")))
+                    }
+                },
+                RfxBlockType::WbtCodecVersions => (),
+                RfxBlockType::WbtChannels => self.read_channels(&mut block)?,
+                RfxBlockType::WbtContext => self.read_context(&mut block)?,
+                RfxBlockType::WbtFrameBegin => {
+                    self.frame = Some(PendingFrame {
+                        frame_index: block.read_u32::<LittleEndian>()?,
+                        ..Default::default()
+                    });
+                },
+                RfxBlockType::WbtRegion => self.read_region(&mut block)?,
+                RfxBlockType::WbtExtension => self.read_tileset(&mut block)?,
+                RfxBlockType::WbtFrameEnd => {
+                    if let Some(frame) = self.frame.take() {
+                        frames.push(self.compose(frame)?);
+                    }
+                },
+                _ => return Err(Error::RdpError(RdpError::new(RdpErrorKind::UnexpectedType, &format!("RFX: Unexpected block {:?}", block_type))))
+            }
+        }
+
+        Ok(frames)
+    }
+
+    /// Channels message, only the first channel is used
+    fn read_channels(&mut self, block: &mut dyn Read) -> RdpResult<()> {
+        let number_channels = block.read_u8()?;
+        if number_channels > 0 {
+            block.read_u8()?;
+            let width = block.read_u16::<LittleEndian>()?;
+            let height = block.read_u16::<LittleEndian>()?;
+            self.channel = Some((width, height));
+        }
+        Ok(())
+    }
+
+    /// Context message set tile size and entropy algorithm
+    fn read_context(&mut self, block: &mut dyn Read) -> RdpResult<()> {
+        block.read_u8()?;
+        if block.read_u16::<LittleEndian>()? as usize != RFX_TILE_SIZE {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "RFX: Only 64 pixels tiles are supported")))
+        }
+        let properties = block.read_u16::<LittleEndian>()?;
+        self.entropy = EntropyAlgorithm::try_from(((properties >> 9) & 0x0F) as u8).map_err(|_| Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "RFX: Unknown entropy algorithm")))?;
+        Ok(())
+    }
+
+    /// Region message, rectangles updated by the frame
+    fn read_region(&mut self, block: &mut dyn Read) -> RdpResult<()> {
+        block.read_u8()?;
+        let number_rects = block.read_u16::<LittleEndian>()?;
+        let mut rects = Vec::with_capacity(number_rects as usize);
+        for _ in 0..number_rects {
+            rects.push((
+                block.read_u16::<LittleEndian>()?,
+                block.read_u16::<LittleEndian>()?,
+                block.read_u16::<LittleEndian>()?,
+                block.read_u16::<LittleEndian>()?
+            ));
+        }
+        self.frame.get_or_insert_with(PendingFrame::default).rects = rects;
+        Ok(())
+    }
+
+    /// Tileset message, all tiles of the frame
+    fn read_tileset(&mut self, block: &mut Cursor<&[u8]>) -> RdpResult<()> {
+        if block.read_u16::<LittleEndian>()? != RfxBlockType::CbtTileset as u16 {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "RFX: Invalid tileset subtype")))
+        }
+        // index and properties
+        block.read_u16::<LittleEndian>()?;
+        block.read_u16::<LittleEndian>()?;
+
+        let number_quant = block.read_u8()?;
+        if block.read_u8()? as usize != RFX_TILE_SIZE {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "RFX: Only 64 pixels tiles are supported")))
+        }
+        let number_tiles = block.read_u16::<LittleEndian>()?;
+        block.read_u32::<LittleEndian>()?;
+
+        let mut quants = Vec::with_capacity(number_quant as usize);
+        for _ in 0..number_quant {
+            quants.push(read_quant(block)?);
+        }
+
+        let mut tiles = Vec::with_capacity(number_tiles as usize);
+        for _ in 0..number_tiles {
+            let start = block.position() as usize;
+            let block_type = block.read_u16::<LittleEndian>()?;
+            let block_length = block.read_u32::<LittleEndian>()? as usize;
+            if block_type != RfxBlockType::CbtTile as u16 {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "RFX: Invalid tile block")))
+            }
+
+            let quant_index = [block.read_u8()? as usize, block.read_u8()? as usize, block.read_u8()? as usize];
+            let x = block.read_u16::<LittleEndian>()? as usize * RFX_TILE_SIZE;
+            let y = block.read_u16::<LittleEndian>()? as usize * RFX_TILE_SIZE;
+            let lengths = [
+                block.read_u16::<LittleEndian>()? as usize,
+                block.read_u16::<LittleEndian>()? as usize,
+                block.read_u16::<LittleEndian>()? as usize
+            ];
+
+            let mut components = Vec::with_capacity(3);
+            for i in 0..3 {
+                let quant = quants.get(quant_index[i]).ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "RFX: Invalid quantization index")))?;
+                let mut data = vec![0; lengths[i]];
+                block.read_exact(&mut data)?;
+                components.push(decode_component(&data, quant, self.entropy)?);
+            }
+            block.set_position((start + block_length) as u64);
+
+            let mut data = vec![0_u8; RFX_TILE_COEFFICIENTS * 4];
+            for (i, pixel) in data.chunks_mut(4).enumerate() {
+                ycbcr_to_bgra(components[0][i], components[1][i], components[2][i], pixel);
+            }
+            tiles.push(RfxTile { x, y, data });
+        }
+
+        self.frame.get_or_insert_with(PendingFrame::default).tiles.append(&mut tiles);
+        Ok(())
+    }
+
+    /// Clip tiles into region rectangles
+    /// An empty region means the whole channel
+    /// Rectangles are clipped to the channel
+    fn compose(&self, frame: PendingFrame) -> RdpResult<RfxFrame> {
+        let PendingFrame { frame_index, mut rects, tiles } = frame;
+        let (channel_width, channel_height) = self.channel.ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "RFX: Frame received before channels message")))?;
+        if rects.is_empty() {
+            rects.push((0, 0, channel_width, channel_height));
+        }
+
+        let mut clipped_rects = Vec::with_capacity(rects.len());
+        for (left, top, width, height) in rects {
+            if left >= channel_width || top >= channel_height {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "RFX: Region rectangle outside of the channel")))
+            }
+            clipped_rects.push((
+                left as usize,
+                top as usize,
+                width.min(channel_width - left) as usize,
+                height.min(channel_height - top) as usize
+            ));
+        }
+
+        Ok(RfxFrame {
+            frame_index,
+            rects: clipped_rects.into_iter().map(|(left, top, width, height)| {
+                let mut data = vec![0_u8; width * height * 4];
+                for tile in tiles.iter() {
+                    let (x_start, x_end) = (left.max(tile.x), (left + width).min(tile.x + RFX_TILE_SIZE));
+                    let (y_start, y_end) = (top.max(tile.y), (top + height).min(tile.y + RFX_TILE_SIZE));
+                    if x_start >= x_end || y_start >= y_end {
+                        continue;
+                    }
+                    for y in y_start..y_end {
+                        let source = ((y - tile.y) * RFX_TILE_SIZE + x_start - tile.x) * 4;
+                        let destination = ((y - top) * width + x_start - left) * 4;
+                        let length = (x_end - x_start) * 4;
+                        data[destination..destination + length].copy_from_slice(&tile.data[source..source + length]);
+                    }
+                }
+                SurfaceRect {
+                    left: left as u16,
+                    top: top as u16,
+                    width: width as u16,
+                    height: height as u16,
+                    data
+                }
+            }).collect()
+        })
+    }
+}
+
+impl Default for RfxDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SurfaceDecoder for RfxDecoder {
+    /// Size is carried by the RemoteFX messages
+    fn decode(&mut self, _width: u16, _height: u16, input: &[u8]) -> RdpResult<Vec<SurfaceRect>> {
+        Ok(RfxDecoder::decode(self, input)?.into_iter().flat_map(|frame| frame.rects).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Pack a string of 0 and 1 MSB first
+    fn pack_bits(bits: &str) -> Vec<u8> {
+        let bits = bits.replace(' ', "");
+        bits.as_bytes().chunks(8).map(|chunk| {
+            chunk.iter().enumerate().fold(0_u8, |acc, (i, bit)| acc | ((*bit - b'0') << (7 - i)))
+        }).collect()
+    }
+
+    /// Build a RemoteFX block
+    fn block(block_type: RfxBlockType, channel: Option<u8>, body: &[u8]) -> Vec<u8> {
+        let mut result = vec![];
+        let header_length = if channel.is_some() { 8 } else { 6 };
+        result.extend_from_slice(&(block_type as u16).to_le_bytes());
+        result.extend_from_slice(&((header_length + body.len()) as u32).to_le_bytes());
+        if let Some(channel) = channel {
+            result.extend_from_slice(&[RFX_CODEC_ID, channel]);
+        }
+        result.extend_from_slice(body);
+        result
+    }
+
+    /// LL3 coefficient of 10 after 4032 zeros
+    fn dc_only_rlgr1() -> Vec<u8> {
+        pack_bits(&format!("{} 1 1111000100 0 11110 1", "0".repeat(19)))
+    }
+
+    #[test]
+    fn test_rlgr1_decode() {
+        let mut coefficients = vec![0x7f_i16; RFX_TILE_COEFFICIENTS];
+        rlgr_decode(&dc_only_rlgr1(), EntropyAlgorithm::ClwEntropyRlgr1, &mut coefficients).unwrap();
+        assert!(coefficients[..4032].iter().all(|x| *x == 0));
+        assert_eq!(coefficients[4032], 10);
+        assert!(coefficients[4033..].iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    fn test_rlgr3_decode() {
+        let mut coefficients = vec![0_i16; 8];
+        rlgr_decode(&pack_bits("100001 1001"), EntropyAlgorithm::ClwEntropyRlgr3, &mut coefficients).unwrap();
+        assert_eq!(coefficients[0..3], [1, -1, -1]);
+    }
+
+    /// A constant LL3 band give a constant tile
+    #[test]
+    fn test_decode_component_dc() {
+        let coefficients = decode_component(&dc_only_rlgr1(), &[6; 10], EntropyAlgorithm::ClwEntropyRlgr1).unwrap();
+        assert!(coefficients.iter().all(|x| *x == 320));
+
+        let mut pixel = [0_u8; 4];
+        ycbcr_to_bgra(320, 0, 0, &mut pixel);
+        assert_eq!(pixel, [138, 138, 138, 0xff]);
+    }
+
+    /// Whole message stream with one tile clipped by the region
+    #[test]
+    fn test_decode_message() {
+        let mut input = vec![];
+        input.extend(block(RfxBlockType::WbtSync, None, &[0xCA, 0xAC, 0xCC, 0xCA, 0x00, 0x01]));
+        input.extend(block(RfxBlockType::WbtCodecVersions, None, &[1, 1, 0x00, 0x01]));
+        input.extend(block(RfxBlockType::WbtChannels, None, &[1, 0, 64, 0, 64, 0]));
+        input.extend(block(RfxBlockType::WbtContext, Some(0xFF), &[0, 64, 0, 0x28, 0x02]));
+        input.extend(block(RfxBlockType::WbtFrameBegin, Some(0), &[7, 0, 0, 0, 1, 0]));
+        input.extend(block(RfxBlockType::WbtRegion, Some(0), &[1, 1, 0, 2, 0, 0, 0, 4, 0, 2, 0, 0xC1, 0xCA, 1, 0]));
+
+        let y_data = dc_only_rlgr1();
+        let mut tile = vec![0xC3, 0xCA];
+        tile.extend_from_slice(&((19 + y_data.len()) as u32).to_le_bytes());
+        tile.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0]);
+        tile.extend_from_slice(&(y_data.len() as u16).to_le_bytes());
+        tile.extend_from_slice(&[0, 0, 0, 0]);
+        tile.extend(y_data);
+
+        let mut tileset = vec![0xC2, 0xCA, 0, 0, 0, 0, 1, 64, 1, 0];
+        tileset.extend_from_slice(&(tile.len() as u32).to_le_bytes());
+        tileset.extend_from_slice(&[0x66, 0x66, 0x66, 0x66, 0x66]);
+        tileset.extend(tile);
+        input.extend(block(RfxBlockType::WbtExtension, Some(0), &tileset));
+        input.extend(block(RfxBlockType::WbtFrameEnd, Some(0), &[]));
+
+        let mut decoder = RfxDecoder::new();
+        let frames = decoder.decode(&input).unwrap();
+        assert_eq!(decoder.get_channel_size(), Some((64, 64)));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].frame_index, 7);
+        assert_eq!(frames[0].rects.len(), 1);
+        let rect = &frames[0].rects[0];
+        assert_eq!((rect.left, rect.top, rect.width, rect.height), (2, 0, 4, 2));
+        assert!(rect.data.chunks(4).all(|pixel| pixel == [138, 138, 138, 0xff]));
+    }
+
+    /// Frame without tiles on a 64x64 channel
+    fn region_frame(rect: [u8; 8]) -> Vec<u8> {
+        let mut region = vec![1, 1, 0];
+        region.extend_from_slice(&rect);
+        region.extend_from_slice(&[0xC1, 0xCA, 0, 0]);
+
+        let mut input = vec![];
+        input.extend(block(RfxBlockType::WbtChannels, None, &[1, 0, 64, 0, 64, 0]));
+        input.extend(block(RfxBlockType::WbtFrameBegin, Some(0), &[0, 0, 0, 0, 1, 0]));
+        input.extend(block(RfxBlockType::WbtRegion, Some(0), &region));
+        input.extend(block(RfxBlockType::WbtFrameEnd, Some(0), &[]));
+        input
+    }
+
+    /// Region rectangles are clipped to the channel
+    #[test]
+    fn test_decode_region_clipped() {
+        let mut decoder = RfxDecoder::new();
+        let frames = decoder.decode(&region_frame([60, 0, 62, 0, 0xFF, 0xFF, 0xFF, 0xFF])).unwrap();
+        let rect = &frames[0].rects[0];
+        assert_eq!((rect.left, rect.top, rect.width, rect.height), (60, 62, 4, 2));
+        assert_eq!(rect.data.len(), 4 * 2 * 4);
+    }
+
+    /// Region rectangle starting outside of the channel
+    #[test]
+    fn test_decode_region_outside_channel() {
+        let mut decoder = RfxDecoder::new();
+        assert!(decoder.decode(&region_frame([64, 0, 0, 0, 1, 0, 1, 0])).is_err());
+    }
+
+    /// Sync block with a wrong magic number
+    #[test]
+    fn test_decode_invalid_sync_magic() {
+        let mut decoder = RfxDecoder::new();
+        assert!(decoder.decode(&block(RfxBlockType::WbtSync, None, &[0, 0, 0, 0, 0, 1])).is_err());
+    }
+
+    /// Block length larger than the stream
+    #[test]
+    fn test_decode_truncated_block() {
+        let mut decoder = RfxDecoder::new();
+        assert!(decoder.decode(&[0xC0, 0xCC, 0xFF, 0, 0, 0]).is_err());
+    }
+
+    /// Tiles are always 64x64
+    #[test]
+    fn test_decode_invalid_tile_size() {
+        let mut decoder = RfxDecoder::new();
+        assert!(decoder.decode(&block(RfxBlockType::WbtContext, Some(0xFF), &[0, 32, 0, 0x28, 0x02])).is_err());
+    }
+}
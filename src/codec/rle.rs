@@ -1,115 +1,20 @@
 use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
 use std::io::{Cursor, Read};
+use codec::planar::planar_decompress;
+use std::ops::BitXor;
 use byteorder::{ReadBytesExt, LittleEndian};
 
+/// Run length encoding decoding function for 32 bpp
+/// 32 bpp bitmap are compressed using the planar codec
+pub fn rle_32_decompress(input: &[u8], width: u32, height: u32, output: &mut [u8]) -> RdpResult<()> {
+	planar_decompress(input, width as usize, height as usize, output)
+}
+
 /// All this uncompress code
 /// Are directly inspired from the source code
 /// of rdesktop and diretly port to rust
 /// Need a little bit of refactoring for rust
 
-fn process_plane(input: &mut dyn Read, width: u32, height: u32, output: &mut [u8]) -> RdpResult<()> {
-    let mut indexw;
-	let mut indexh= 0;
-	let mut code ;
-	let mut collen;
-	let mut replen;
-	let mut color:i8;
-	let mut x;
-	let mut revcode;
-
-    let mut this_line: u32;
-    let mut last_line: u32 = 0;
-
-	while indexh < height {
-		let mut out = (width * height * 4) - ((indexh + 1) * width * 4);
-		color = 0;
-		this_line = out;
-		indexw = 0;
-		if last_line == 0 {
-			while indexw < width {
-				code = input.read_u8()?;
-				replen = code & 0xf;
-				collen = (code >> 4) & 0xf;
-				revcode = (replen << 4) | collen;
-				if (revcode <= 47) && (revcode >= 16) {
-					replen = revcode;
-					collen = 0;
-				}
-				while collen > 0 {
-					color = input.read_u8()? as i8;
-					output[out as usize] = color as u8;
-					out += 4;
-					indexw += 1;
-					collen -= 1;
-				}
-				while replen > 0 {
-					output[out as usize] = color as u8;
-					out += 4;
-					indexw += 1;
-					replen -= 1;
-				}
-			}
-		}
-		else
-		{
-			while indexw < width {
-				code = input.read_u8()?;
-				replen = code & 0xf;
-				collen = (code >> 4) & 0xf;
-				revcode = (replen << 4) | collen;
-				if (revcode <= 47) && (revcode >= 16) {
-					replen = revcode;
-					collen = 0;
-				}
-				while collen > 0 {
-					x = input.read_u8()?;
-					if x & 1 != 0{
-						x = x >> 1;
-						x = x + 1;
-						color = -(x as i32) as i8;
-					}
-					else
-					{
-						x = x >> 1;
-						color = x as i8;
-					}
-					x = (output[(last_line + (indexw * 4)) as usize] as i32 + color as i32) as u8;
-					output[out as usize] = x;
-					out += 4;
-					indexw += 1;
-					collen -= 1;
-				}
-				while replen > 0 {
-					x = (output[(last_line + (indexw * 4)) as usize] as i32 + color as i32) as u8;
-					output[out as usize] = x;
-					out += 4;
-					indexw += 1;
-					replen -= 1;
-				}
-			}
-		}
-		indexh += 1;
-		last_line = this_line;
-	}
-    Ok(())
-}
-
-/// Run length encoding decoding function for 32 bpp
-pub fn rle_32_decompress(input: &[u8], width: u32, height: u32, output: &mut [u8]) -> RdpResult<()> {
-    let mut input_cursor = Cursor::new(input);
-
-	if input_cursor.read_u8()? != 0x10 {
-		return Err(Error::RdpError(RdpError::new(RdpErrorKind::UnexpectedType, "Bad header")))
-	}
-
-	process_plane(&mut input_cursor, width, height, &mut output[3..])?;
-	process_plane(&mut input_cursor, width, height, &mut output[2..])?;
-	process_plane(&mut input_cursor, width, height, &mut output[1..])?;
-	process_plane(&mut input_cursor, width, height, &mut output[0..])?;
-
-	Ok(())
-}
-
 macro_rules! repeat {
     ($expr:expr, $count:expr, $x:expr, $width:expr) => {
     	while (($count & !0x7) != 0) && ($x + 8) < $width {
@@ -130,7 +35,46 @@ macro_rules! repeat {
     };
 }
 
-pub fn rle_16_decompress(input: &[u8], width: usize, mut height: usize, output: &mut [u16]) -> RdpResult<()> {
+/// Pixel handled by the interleaved RLE decoder
+pub trait RlePixel: Copy + BitXor<Output=Self> {
+	/// Value of a white pixel
+	const WHITE: Self;
+	/// Value of a black pixel
+	const BLACK: Self;
+	/// Read a pixel from the compressed stream
+	fn read_pixel(input: &mut dyn Read) -> RdpResult<Self>;
+}
+
+/// 8 bpp palettized pixel
+impl RlePixel for u8 {
+	const WHITE: Self = 0xff;
+	const BLACK: Self = 0;
+	fn read_pixel(input: &mut dyn Read) -> RdpResult<Self> {
+		Ok(input.read_u8()?)
+	}
+}
+
+/// 15 and 16 bpp pixel
+impl RlePixel for u16 {
+	const WHITE: Self = 0xffff;
+	const BLACK: Self = 0;
+	fn read_pixel(input: &mut dyn Read) -> RdpResult<Self> {
+		Ok(input.read_u16::<LittleEndian>()?)
+	}
+}
+
+/// 24 bpp pixel, encoded on 3 bytes
+impl RlePixel for u32 {
+	const WHITE: Self = 0xffffff;
+	const BLACK: Self = 0;
+	fn read_pixel(input: &mut dyn Read) -> RdpResult<Self> {
+		Ok(input.read_u24::<LittleEndian>()?)
+	}
+}
+
+/// Interleaved RLE decoding for 8, 15, 16 and 24 bpp
+/// Output is written top-down
+pub fn rle_decompress<T: RlePixel>(input: &[u8], width: usize, mut height: usize, output: &mut [T]) -> RdpResult<()> {
 	let mut input_cursor = Cursor::new(input);
 
 	let mut code: u8;
@@ -143,9 +87,9 @@ pub fn rle_16_decompress(input: &[u8], width: usize, mut height: usize, output:
 	let mut x: usize = width;
 	let mut prevline : Option<usize> = None;
 	let mut line : Option<usize> = None;
-	let mut colour1= 0;
-	let mut colour2 = 0;
-	let mut mix = 0xffff;
+	let mut colour1 = T::BLACK;
+	let mut colour2 = T::BLACK;
+	let mut mix = T::WHITE;
 	let mut mask:u8 = 0;
 	let mut fom_mask : u8;
 	let mut mixmask:u8;
@@ -200,14 +144,14 @@ pub fn rle_16_decompress(input: &[u8], width: usize, mut height: usize, output:
 				}
 			},
 			8 => {
-				colour1 = input_cursor.read_u16::<LittleEndian>()?;
-				colour2 = input_cursor.read_u16::<LittleEndian>()?;
+				colour1 = T::read_pixel(&mut input_cursor)?;
+				colour2 = T::read_pixel(&mut input_cursor)?;
 			},
 			3 => {
-				colour2 = input_cursor.read_u16::<LittleEndian>()?;
+				colour2 = T::read_pixel(&mut input_cursor)?;
 			},
 			6 | 7 => {
-				mix = input_cursor.read_u16::<LittleEndian>()?;
+				mix = T::read_pixel(&mut input_cursor)?;
 				opcode -= 5;
 			}
 			9 => {
@@ -254,7 +198,7 @@ pub fn rle_16_decompress(input: &[u8], width: usize, mut height: usize, output:
 						repeat!(output[line.unwrap() + x] = output[e + x], count, x, width);
 					}
 					else {
-						repeat!(output[line.unwrap() + x] = 0, count, x, width);
+						repeat!(output[line.unwrap() + x] = T::BLACK, count, x, width);
 					}
 				},
 				1 => {
@@ -292,7 +236,7 @@ pub fn rle_16_decompress(input: &[u8], width: usize, mut height: usize, output:
 								output[line.unwrap() + x] = mix;
 							}
 							else {
-								output[line.unwrap() + x] = 0;
+								output[line.unwrap() + x] = T::BLACK;
 							}
 						}, count, x, width);
 					}
@@ -301,7 +245,7 @@ pub fn rle_16_decompress(input: &[u8], width: usize, mut height: usize, output:
 					repeat!(output[line.unwrap() + x] = colour2, count, x, width);
 				},
 				4 => {
-					repeat!(output[line.unwrap() + x] = input_cursor.read_u16::<LittleEndian>()?, count, x, width);
+					repeat!(output[line.unwrap() + x] = T::read_pixel(&mut input_cursor)?, count, x, width);
 				},
 				8 => {
 					repeat!({
@@ -316,12 +260,12 @@ pub fn rle_16_decompress(input: &[u8], width: usize, mut height: usize, output:
 					}, count, x, width);
 				},
 				0xd => {
-					repeat!(output[line.unwrap() + x] = 0xffff, count, x, width);
+					repeat!(output[line.unwrap() + x] = T::WHITE, count, x, width);
 				},
 				0xe => {
-					repeat!(output[line.unwrap() + x] = 0, count, x, width);
+					repeat!(output[line.unwrap() + x] = T::BLACK, count, x, width);
 				}
-				_ => panic!("opcode")
+				_ => return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, &format!("Invalid RLE opcode {}", opcode))))
 			}
 		}
 	}
@@ -330,6 +274,23 @@ pub fn rle_16_decompress(input: &[u8], width: usize, mut height: usize, output:
 }
 
 
+/// Run length encoding decoding function for 15 and 16 bpp
+pub fn rle_16_decompress(input: &[u8], width: usize, height: usize, output: &mut [u16]) -> RdpResult<()> {
+	rle_decompress(input, width, height, output)
+}
+
+/// Run length encoding decoding function for 8 bpp
+/// Output are palette indexes
+pub fn rle_8_decompress(input: &[u8], width: usize, height: usize, output: &mut [u8]) -> RdpResult<()> {
+	rle_decompress(input, width, height, output)
+}
+
+/// Run length encoding decoding function for 24 bpp
+/// Output pixels are 0x00RRGGBB
+pub fn rle_24_decompress(input: &[u8], width: usize, height: usize, output: &mut [u32]) -> RdpResult<()> {
+	rle_decompress(input, width, height, output)
+}
+
 pub fn rgb565torgb32(input: &[u16], width: usize, height: usize) -> Vec<u8> {
 	let mut result_32_bpp = vec![0 as u8; width as usize * height as usize * 4];
 	for i in 0..height {
@@ -343,4 +304,74 @@ pub fn rgb565torgb32(input: &[u16], width: usize, height: usize) -> Vec<u8> {
 		}
 	}
 	result_32_bpp
-}
\ No newline at end of file
+}
+
+/// Convert 15 bpp (RGB 555) pixels into 32 bpp
+pub fn rgb555torgb32(input: &[u16], width: usize, height: usize) -> Vec<u8> {
+	let mut result_32_bpp = vec![0_u8; width * height * 4];
+	for (index, v) in input.iter().take(width * height).enumerate() {
+		result_32_bpp[index * 4 + 3] = 0xff;
+		result_32_bpp[index * 4 + 2] = ((((v >> 10) & 0x1f) * 527 + 23) >> 6) as u8;
+		result_32_bpp[index * 4 + 1] = ((((v >> 5) & 0x1f) * 527 + 23) >> 6) as u8;
+		result_32_bpp[index * 4] = (((v & 0x1f) * 527 + 23) >> 6) as u8;
+	}
+	result_32_bpp
+}
+
+/// Convert 24 bpp (0x00RRGGBB) pixels into 32 bpp
+pub fn rgb24torgb32(input: &[u32], width: usize, height: usize) -> Vec<u8> {
+	let mut result_32_bpp = vec![0_u8; width * height * 4];
+	for (index, v) in input.iter().take(width * height).enumerate() {
+		result_32_bpp[index * 4..index * 4 + 4].copy_from_slice(&(v | 0xff000000).to_le_bytes());
+	}
+	result_32_bpp
+}
+
+/// Convert palette indexes into 32 bpp
+/// Palette entries are 0x00RRGGBB
+pub fn palette_to_rgb32(input: &[u8], palette: &[u32], width: usize, height: usize) -> Vec<u8> {
+	let mut result_32_bpp = vec![0_u8; width * height * 4];
+	for (index, v) in input.iter().take(width * height).enumerate() {
+		let colour = palette.get(*v as usize).cloned().unwrap_or(0);
+		result_32_bpp[index * 4..index * 4 + 4].copy_from_slice(&(colour | 0xff000000).to_le_bytes());
+	}
+	result_32_bpp
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Color image order, first row is the bottom one
+	#[test]
+	fn test_rle_8_color_image() {
+		let mut output = [0_u8; 4];
+		rle_8_decompress(&[0x84, 1, 2, 3, 4], 2, 2, &mut output).unwrap();
+		assert_eq!(output, [3, 4, 1, 2]);
+	}
+
+	/// Color run with 3 bytes pixel
+	#[test]
+	fn test_rle_24_color_run() {
+		let mut output = [0_u32; 4];
+		rle_24_decompress(&[0x64, 0x11, 0x22, 0x33], 2, 2, &mut output).unwrap();
+		assert_eq!(output, [0x332211; 4]);
+		assert_eq!(rgb24torgb32(&output, 2, 2)[0..4], [0x11, 0x22, 0x33, 0xff]);
+	}
+
+	/// White and black special orders
+	#[test]
+	fn test_rle_16_white_black() {
+		let mut output = [0_u16; 4];
+		rle_16_decompress(&[0xFD, 0xFD, 0xFE, 0xFE], 2, 2, &mut output).unwrap();
+		assert_eq!(output, [0, 0, 0xffff, 0xffff]);
+		assert_eq!(rgb555torgb32(&[0x7c00], 1, 1), [0, 0, 0xff, 0xff]);
+	}
+
+	/// Invalid stream must not panic
+	#[test]
+	fn test_rle_invalid() {
+		let mut output = [0_u8; 4];
+		assert!(rle_8_decompress(&[0x84, 1, 2, 3, 4, 0x84, 1, 2, 3, 4, 0x84], 2, 2, &mut output).is_err());
+	}
+}
@@ -0,0 +1,73 @@
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+
+/// A rectangle decoded from surface bits
+/// Pixels are 32 bpp BGRA, top-down
+pub struct SurfaceRect {
+    /// Position from the left of the surface command destination
+    pub left: u16,
+    /// Position from the top of the surface command destination
+    pub top: u16,
+    pub width: u16,
+    pub height: u16,
+    pub data: Vec<u8>
+}
+
+/// Decoder of bitmap data sent through surface commands
+/// Decoders are registered by codec id
+/// and keep their state between commands
+///
+/// See MS-RDPBCGR 2.2.9.2.1.1 TS_BITMAP_DATA_EX
+pub trait SurfaceDecoder: Send {
+    /// Decode the bitmap data of a surface command
+    /// width and height come from the bitmap data header
+    fn decode(&mut self, width: u16, height: u16, input: &[u8]) -> RdpResult<Vec<SurfaceRect>>;
+}
+
+/// Codec id of uncompressed surface bits
+pub const CODEC_ID_NONE: u8 = 0;
+
+/// Uncompressed surface bits decoder
+/// Pixels are 32 bpp BGRA sent bottom-up
+///
+/// # Example
+/// ```
+/// use rdp::codec::surface::{SurfaceDecoder, UncompressedDecoder};
+/// let rects = UncompressedDecoder.decode(1, 2, &[1, 1, 1, 1, 2, 2, 2, 2]).unwrap();
+/// assert_eq!(rects[0].data, [2, 2, 2, 2, 1, 1, 1, 1]);
+/// ```
+#[derive(Default)]
+pub struct UncompressedDecoder;
+
+impl SurfaceDecoder for UncompressedDecoder {
+    fn decode(&mut self, width: u16, height: u16, input: &[u8]) -> RdpResult<Vec<SurfaceRect>> {
+        let row_size = width as usize * 4;
+        if input.len() != row_size * height as usize {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "SURFACE: Uncompressed surface bits must be 32 bpp")))
+        }
+
+        let mut data = Vec::with_capacity(input.len());
+        if row_size != 0 {
+            for row in input.chunks(row_size).rev() {
+                data.extend_from_slice(row);
+            }
+        }
+        Ok(vec![SurfaceRect {
+            left: 0,
+            top: 0,
+            width,
+            height,
+            data
+        }])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Data size must match a 32 bpp bitmap
+    #[test]
+    fn test_uncompressed_invalid_size() {
+        assert!(UncompressedDecoder.decode(2, 2, &[0; 12]).is_err());
+    }
+}
@@ -9,7 +9,7 @@ use std::convert::TryFrom;
 /// between client and server
 /// This is done by the global channel
 #[repr(u16)]
-#[derive(Eq, PartialEq, Hash, Debug, TryFromPrimitive)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, TryFromPrimitive)]
 pub enum CapabilitySetType {
     CapstypeGeneral = 0x0001,
     CapstypeBitmap = 0x0002,
@@ -92,6 +92,8 @@ impl Capability {
             CapabilitySetType::CapstypeVirtualchannel => ts_virtualchannel_capability_set(),
             CapabilitySetType::CapstypeSound => ts_sound_capability_set(),
             CapabilitySetType::CapsettypeMultifragmentupdate => ts_multifragment_update_capability_ts(),
+            CapabilitySetType::CapsettypeSurfaceCommands => ts_surface_commands_capability_set(None),
+            CapabilitySetType::CapssettypeFrameAcknowledge => ts_frame_acknowledge_capability_set(None),
             _ => {
                 return Err(Error::RdpError(RdpError::new(RdpErrorKind::Unknown, &format!("CAPABILITY: Unknown capability {:?}", cap_type))))
             }
@@ -237,6 +239,26 @@ pub enum OrderFlag {
     OrderflagsExtraFlags = 0x0080
 }
 
+/// Index of a primary drawing order
+/// in the order support array
+/// Only orders decoded by the client are listed
+///
+/// See MS-RDPBCGR 2.2.7.1.3 Order Capability Set (TS_ORDER_CAPABILITYSET)
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OrderSupportIndex {
+    TsNegDstbltIndex = 0x00,
+    /// PatBlt and OpaqueRect
+    TsNegPatbltIndex = 0x01,
+    TsNegScrbltIndex = 0x02,
+    TsNegMembltIndex = 0x03,
+    TsNegLinetoIndex = 0x08,
+    TsNegMultiopaquerectIndex = 0x12,
+    TsNegPolylineIndex = 0x16,
+    TsNegFastGlyphIndex = 0x18,
+    TsNegGlyphIndexIndex = 0x1B
+}
+
 /// Order capability
 /// Some graphical orders options
 ///
@@ -338,7 +360,7 @@ pub enum InputFlags {
     /// This feature is supported by rdp-rs
     InputFlagMousex = 0x0004,
     /// The capability to send fastpath input
-    /// This feature is NOT supported by rdp-rs
+    /// This feature is supported by rdp-rs
     InputFlagFastpathInput = 0x0008,
     /// In order to send keyboard scancode
     /// We can send directly UNICODE code of char
@@ -347,7 +369,9 @@ pub enum InputFlags {
     InputFlagUnicode = 0x0010,
     InputFlagFastpathInput2 = 0x0020,
     InputFlagUnused1 = 0x0040,
-    InputFlagUnused2 = 0x0080,
+    /// Server support relative mouse movement
+    /// This feature is supported by rdp-rs
+    InputFlagMouseRelative = 0x0080,
     /// Support of the mouse wheel
     /// This feature is supported by rdp-rs
     TsInputFlagMouseHwheel = 0x0100
@@ -404,10 +428,10 @@ pub fn ts_brush_capability_set() -> Capability {
 /// Glyph cache entry
 ///
 /// https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-rdpbcgr/cae26830-263c-4c1e-97c2-b561faded3d9
-fn cache_entry() -> Component {
+fn cache_entry(entries: u16, maximum_cell_size: u16) -> Component {
     component![
-        "cacheEntries" => U16::LE(0),
-        "cacheMaximumCellSize" => U16::LE(0)
+        "cacheEntries" => U16::LE(entries),
+        "cacheMaximumCellSize" => U16::LE(maximum_cell_size)
     ]
 }
 
@@ -430,8 +454,8 @@ pub fn ts_glyph_capability_set() -> Capability {
         cap_type: CapabilitySetType::CapstypeGlyphcache,
         message: component![
             "glyphCache" => trame![
-                cache_entry(), cache_entry(), cache_entry(), cache_entry(), cache_entry(),
-                cache_entry(), cache_entry(), cache_entry(), cache_entry(), cache_entry()
+                cache_entry(0, 0), cache_entry(0, 0), cache_entry(0, 0), cache_entry(0, 0), cache_entry(0, 0),
+                cache_entry(0, 0), cache_entry(0, 0), cache_entry(0, 0), cache_entry(0, 0), cache_entry(0, 0)
             ],
             "fragCache" => U32::LE(0),
             "glyphSupportLevel" => U16::LE(0),
@@ -530,4 +554,725 @@ pub fn ts_multifragment_update_capability_ts() -> Capability {
             "MaxRequestSize" => U32::LE(0)
         ]
     }
-}
\ No newline at end of file
+}
+
+/// Surface commands supported by the client
+///
+/// See MS-RDPBCGR 2.2.7.2.9 Surface Commands Capability Set
+#[repr(u32)]
+#[derive(Copy, Clone)]
+pub enum SurfaceCommandsFlag {
+    SurfcmdsSetSurfaceBits = 0x00000002,
+    SurfcmdsFrameMarker = 0x00000010,
+    SurfcmdsStreamSurfaceBits = 0x00000040
+}
+
+/// Surface commands capability
+/// send from client to server
+///
+/// # Example
+/// ```
+/// use rdp::core::capability::{capability_set, ts_surface_commands_capability_set, SurfaceCommandsFlag};
+/// use rdp::model::data::to_vec;
+/// let capability_set = capability_set(Some(ts_surface_commands_capability_set(Some(SurfaceCommandsFlag::SurfcmdsSetSurfaceBits as u32))));
+/// assert_eq!(to_vec(&capability_set), vec![28, 0, 12, 0, 2, 0, 0, 0, 0, 0, 0, 0])
+/// ```
+pub fn ts_surface_commands_capability_set(cmd_flags: Option<u32>) -> Capability {
+    Capability {
+        cap_type: CapabilitySetType::CapsettypeSurfaceCommands,
+        message: component![
+            "cmdFlags" => U32::LE(cmd_flags.unwrap_or(0)),
+            "reserved" => U32::LE(0)
+        ]
+    }
+}
+
+/// Frame acknowledge capability
+/// Client acknowledges frames ended by a frame marker
+/// Server stops sending frames when too many are not acknowledged
+///
+/// See MS-RDPRFX 2.2.1.3 TS_FRAME_ACKNOWLEDGE_CAPABILITYSET
+///
+/// # Example
+/// ```
+/// use rdp::core::capability::{capability_set, ts_frame_acknowledge_capability_set};
+/// use rdp::model::data::to_vec;
+/// let capability_set = capability_set(Some(ts_frame_acknowledge_capability_set(Some(2))));
+/// assert_eq!(to_vec(&capability_set), vec![30, 0, 8, 0, 2, 0, 0, 0])
+/// ```
+pub fn ts_frame_acknowledge_capability_set(max_unacknowledged_frame_count: Option<u32>) -> Capability {
+    Capability {
+        cap_type: CapabilitySetType::CapssettypeFrameAcknowledge,
+        message: component![
+            "maxUnacknowledgedFrameCount" => U32::LE(max_unacknowledged_frame_count.unwrap_or(0))
+        ]
+    }
+}
+
+/// Bitmap codecs handled by the client
+/// The value is the codec id chosen by the client
+/// and used by the server in surface commands
+#[repr(u8)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, TryFromPrimitive)]
+pub enum BitmapCodec {
+    CodecNscodec = 0x01,
+    CodecRemotefx = 0x03
+}
+
+impl BitmapCodec {
+    /// GUID identifying the codec
+    /// in its little endian wire format
+    pub fn guid(self) -> [u8; 16] {
+        match self {
+            // CA8D1BB9-000F-154F-589F-AE2D1A87E2D6
+            BitmapCodec::CodecNscodec => [0xB9, 0x1B, 0x8D, 0xCA, 0x0F, 0x00, 0x4F, 0x15, 0x58, 0x9F, 0xAE, 0x2D, 0x1A, 0x87, 0xE2, 0xD6],
+            // 76772F12-BD72-4463-AFB3-B73C9C6F7886
+            BitmapCodec::CodecRemotefx => [0x12, 0x2F, 0x77, 0x76, 0x72, 0xBD, 0x63, 0x44, 0xAF, 0xB3, 0xB7, 0x3C, 0x9C, 0x6F, 0x78, 0x86]
+        }
+    }
+
+    /// Client properties of the codec
+    pub fn properties(self) -> Vec<u8> {
+        match self {
+            BitmapCodec::CodecNscodec => to_vec(&ts_nscodec_capability_set()),
+            BitmapCodec::CodecRemotefx => to_vec(&ts_rfx_client_capabilities())
+        }
+    }
+}
+
+/// NSCodec client properties
+/// Allow server to reduce fidelity and subsample chroma
+///
+/// See MS-RDPNSC 2.2.1 TS_NSCODEC_CAPABILITYSET
+fn ts_nscodec_capability_set() -> Component {
+    component![
+        "fAllowDynamicFidelity" => 1_u8,
+        "fAllowSubsampling" => 1_u8,
+        "colorLossLevel" => 3_u8
+    ]
+}
+
+/// RemoteFX image capability
+/// one per entropy algorithm
+///
+/// See MS-RDPRFX 2.2.1.1.1.1.1 TS_RFX_ICAP
+fn ts_rfx_icap(entropy_bits: u8) -> Component {
+    component![
+        "version" => U16::LE(0x0100),
+        "tileSize" => U16::LE(0x0040),
+        "flags" => 0_u8,
+        "colConvBits" => 1_u8,
+        "transformBits" => 1_u8,
+        "entropyBits" => entropy_bits
+    ]
+}
+
+/// RemoteFX client capabilities container
+/// Advertise RLGR1 and RLGR3
+///
+/// See MS-RDPRFX 2.2.1.1 TS_RFX_CLNT_CAPS_CONTAINER
+fn ts_rfx_client_capabilities() -> Component {
+    component![
+        "length" => U32::LE(49),
+        "captureFlags" => U32::LE(0x00000001),
+        "capsLength" => U32::LE(37),
+        "capsData" => component![
+            "blockType" => U16::LE(0xCBC0),
+            "blockLen" => U32::LE(8),
+            "numCapsets" => U16::LE(1),
+            "capsetsData" => component![
+                "blockType" => U16::LE(0xCBC1),
+                "blockLen" => U32::LE(29),
+                "codecId" => 1_u8,
+                "capsetType" => U16::LE(0xCFC0),
+                "numIcaps" => U16::LE(2),
+                "icapLen" => U16::LE(8),
+                "icapsData" => trame![ts_rfx_icap(0x01), ts_rfx_icap(0x04)]
+            ]
+        ]
+    ]
+}
+
+/// Bitmap codec entry
+///
+/// See MS-RDPBCGR 2.2.7.2.10.1.1 TS_BITMAPCODEC
+fn ts_bitmap_codec(codec: BitmapCodec) -> Component {
+    let properties = codec.properties();
+    component![
+        "codecGUID" => codec.guid().to_vec(),
+        "codecID" => codec as u8,
+        "codecPropertiesLength" => U16::LE(properties.len() as u16),
+        "codecProperties" => properties
+    ]
+}
+
+/// Bitmap codecs capability
+/// send from client to server
+///
+/// See MS-RDPBCGR 2.2.7.2.10 Bitmap Codecs Capability Set
+///
+/// # Example
+/// ```
+/// use rdp::core::capability::{capability_set, ts_bitmap_codecs_capability_set, BitmapCodec};
+/// use rdp::model::data::to_vec;
+/// let capability_set = to_vec(&capability_set(Some(ts_bitmap_codecs_capability_set(&[BitmapCodec::CodecRemotefx]))));
+/// assert_eq!(capability_set.len(), 4 + 1 + 16 + 1 + 2 + 49);
+/// assert_eq!(capability_set[21], BitmapCodec::CodecRemotefx as u8);
+/// ```
+pub fn ts_bitmap_codecs_capability_set(codecs: &[BitmapCodec]) -> Capability {
+    Capability {
+        cap_type: CapabilitySetType::CapsettypeBitmapCodecs,
+        message: component![
+            "bitmapCodecCount" => codecs.len() as u8,
+            "bitmapCodecArray" => codecs.iter().map(|codec| Box::new(ts_bitmap_codec(*codec)) as Box<dyn Message>).collect::<Trame>()
+        ]
+    }
+}
+
+/// Glyph support level
+/// advertised in the glyph capability set
+///
+/// https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-rdpbcgr/8e292483-9b0f-43b9-be14-dc6cd07e1615
+#[repr(u16)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GlyphSupportLevel {
+    GlyphSupportNone = 0x0000,
+    GlyphSupportPartial = 0x0001,
+    GlyphSupportFull = 0x0002,
+    GlyphSupportEncode = 0x0003
+}
+
+/// Client capability sets
+/// sent by the client in the confirm active PDU
+///
+/// Defaults to a 24 bpp session without drawing orders,
+/// glyph cache, bitmap codecs or frame acknowledge
+/// Whole capability sets can be added or removed
+///
+/// # Example
+/// ```
+/// use rdp::core::capability::{CapabilitySet, CapabilitySetType, InputFlags, GlyphSupportLevel};
+/// use rdp::core::gcc::KeyboardLayout;
+/// let capabilities = CapabilitySet::new()
+///     .color_depth(16)
+///     .input_flags(InputFlags::InputFlagScancodes as u16)
+///     .glyph_support(GlyphSupportLevel::GlyphSupportFull)
+///     .remove_capability(CapabilitySetType::CapstypeSound);
+/// let capability_sets = capabilities.build(800, 600, KeyboardLayout::US).unwrap();
+/// assert_eq!(capability_sets.len(), 12);
+/// assert!(capability_sets.iter().all(|c| c.cap_type != CapabilitySetType::CapstypeSound));
+/// ```
+#[derive(Clone)]
+pub struct CapabilitySet {
+    /// Preferred color depth in bits per pixel
+    color_depth: u16,
+    /// Order capability flags
+    order_flags: u16,
+    /// Supported primary drawing orders
+    order_support: [u8; 32],
+    /// Input capability flags
+    input_flags: u16,
+    /// Maximum size of a reassembled fast path update
+    multifragment_max_size: u32,
+    /// Glyph support level
+    glyph_support: GlyphSupportLevel,
+    /// Bitmap codecs usable in surface commands
+    bitmap_codecs: Vec<BitmapCodec>,
+    /// Maximum number of frames not yet acknowledged
+    /// None to not acknowledge frames
+    frame_acknowledge: Option<u32>,
+    /// Capability sets that will not be sent
+    removed: Vec<CapabilitySetType>,
+    /// Capability sets added by caller
+    /// kept in their encoded form
+    extra: Vec<(CapabilitySetType, Vec<u8>)>
+}
+
+impl CapabilitySet {
+    /// Default client capabilities
+    pub fn new() -> Self {
+        CapabilitySet {
+            color_depth: 24,
+            order_flags: OrderFlag::NEGOTIATEORDERSUPPORT as u16 | OrderFlag::ZEROBOUNDSDELTASSUPPORT as u16,
+            order_support: [0; 32],
+            input_flags: InputFlags::InputFlagScancodes as u16 | InputFlags::InputFlagMousex as u16 | InputFlags::InputFlagUnicode as u16,
+            multifragment_max_size: 0,
+            glyph_support: GlyphSupportLevel::GlyphSupportNone,
+            bitmap_codecs: Vec::new(),
+            frame_acknowledge: None,
+            removed: Vec::new(),
+            extra: Vec::new()
+        }
+    }
+
+    /// Preferred color depth
+    /// Valid values are 8, 15, 16, 24 and 32
+    pub fn color_depth(mut self, color_depth: u16) -> Self {
+        self.color_depth = color_depth;
+        self
+    }
+
+    /// Order capability flags (see OrderFlag)
+    pub fn order_flags(mut self, order_flags: u16) -> Self {
+        self.order_flags = order_flags;
+        self
+    }
+
+    /// Primary drawing orders support array
+    /// indexed by order number
+    pub fn order_support(mut self, order_support: [u8; 32]) -> Self {
+        self.order_support = order_support;
+        self
+    }
+
+    /// Advertise a primary drawing order
+    /// Server will send it instead of bitmaps when possible
+    /// MemBlt also enables the bitmap cache
+    /// Glyph orders also need a glyph support level
+    pub fn order(mut self, index: OrderSupportIndex) -> Self {
+        self.order_support[index as usize] = 1;
+        self
+    }
+
+    /// Input capability flags (see InputFlags)
+    pub fn input_flags(mut self, input_flags: u16) -> Self {
+        self.input_flags = input_flags;
+        self
+    }
+
+    /// Maximum size of a multi fragment update
+    pub fn multifragment_max_size(mut self, max_size: u32) -> Self {
+        self.multifragment_max_size = max_size;
+        self
+    }
+
+    /// Glyph support level
+    /// Any level but GlyphSupportNone enables the glyph cache
+    pub fn glyph_support(mut self, glyph_support: GlyphSupportLevel) -> Self {
+        self.glyph_support = glyph_support;
+        self
+    }
+
+    /// Advertise a bitmap codec
+    /// Server will send surface commands encoded with it
+    /// RemoteFX is only used by servers with a 32 bpp color depth
+    pub fn bitmap_codec(mut self, codec: BitmapCodec) -> Self {
+        if !self.bitmap_codecs.contains(&codec) {
+            self.bitmap_codecs.push(codec);
+        }
+        self
+    }
+
+    /// Acknowledge each frame ended by a frame marker
+    /// Server will not send more than max_unacknowledged_frame_count
+    /// frames in advance
+    pub fn frame_acknowledge(mut self, max_unacknowledged_frame_count: u32) -> Self {
+        self.frame_acknowledge = Some(max_unacknowledged_frame_count);
+        self
+    }
+
+    /// Add a whole capability set
+    /// If a capability of the same type is already sent
+    /// it will be replaced
+    pub fn add_capability(mut self, capability: Capability) -> Self {
+        self.removed.retain(|cap_type| *cap_type != capability.cap_type);
+        self.extra.retain(|(cap_type, _)| *cap_type != capability.cap_type);
+        self.extra.push((capability.cap_type, to_vec(&capability.message)));
+        self
+    }
+
+    /// Remove a whole capability set
+    pub fn remove_capability(mut self, cap_type: CapabilitySetType) -> Self {
+        self.extra.retain(|(extra_type, _)| *extra_type != cap_type);
+        self.removed.push(cap_type);
+        self
+    }
+
+    /// Getter for the preferred color depth
+    pub fn get_color_depth(&self) -> u16 {
+        self.color_depth
+    }
+
+    /// Getter for the order support array
+    pub fn get_order_support(&self) -> &[u8; 32] {
+        &self.order_support
+    }
+
+    /// Getter for input flags
+    pub fn get_input_flags(&self) -> u16 {
+        self.input_flags
+    }
+
+    /// Getter for the multi fragment maximum size
+    pub fn get_multifragment_max_size(&self) -> u32 {
+        self.multifragment_max_size
+    }
+
+    /// Getter for the glyph support level
+    pub fn get_glyph_support(&self) -> GlyphSupportLevel {
+        self.glyph_support
+    }
+
+    /// Getter for advertised bitmap codecs
+    pub fn get_bitmap_codecs(&self) -> &[BitmapCodec] {
+        &self.bitmap_codecs
+    }
+
+    /// Getter for the frame acknowledge setting
+    pub fn get_frame_acknowledge(&self) -> Option<u32> {
+        self.frame_acknowledge
+    }
+
+    /// Check that settings can be sent to the server
+    ///
+    /// # Example
+    /// ```
+    /// use rdp::core::capability::CapabilitySet;
+    /// assert!(CapabilitySet::new().color_depth(16).check().is_ok());
+    /// assert!(CapabilitySet::new().color_depth(12).check().is_err());
+    /// ```
+    pub fn check(&self) -> RdpResult<()> {
+        match self.color_depth {
+            8 | 15 | 16 | 24 | 32 => Ok(()),
+            _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, &format!("CAPABILITY: Invalid color depth {}", self.color_depth))))
+        }
+    }
+
+    /// Build all capability sets in the order they will be sent
+    pub fn build(&self, width: u16, height: u16, layout: KeyboardLayout) -> RdpResult<Vec<Capability>> {
+        self.check()?;
+
+        let mut order = ts_order_capability_set(Some(self.order_flags));
+        order.message.insert("orderSupport".to_string(), Box::new(self.order_support.to_vec()));
+
+        // cache orders are only sent for caches with entries
+        let mut bitmap_cache = ts_bitmap_cache_capability_set();
+        if self.order_support[OrderSupportIndex::TsNegMembltIndex as usize] != 0 {
+            let bytes_per_pixel = (self.color_depth + 7) / 8;
+            for (i, (entries, cell_size)) in [(200, 256), (600, 1024), (1000, 4096)].iter().enumerate() {
+                bitmap_cache.message.insert(format!("cache{}Entries", i), Box::new(U16::LE(*entries)));
+                bitmap_cache.message.insert(format!("cache{}MaximumCellSize", i), Box::new(U16::LE(cell_size * bytes_per_pixel)));
+            }
+        }
+
+        let mut glyph = ts_glyph_capability_set();
+        glyph.message.insert("glyphSupportLevel".to_string(), Box::new(U16::LE(self.glyph_support as u16)));
+        if self.glyph_support != GlyphSupportLevel::GlyphSupportNone {
+            glyph.message.insert("glyphCache".to_string(), Box::new(trame![
+                cache_entry(254, 4), cache_entry(254, 4), cache_entry(254, 8), cache_entry(254, 8), cache_entry(254, 16),
+                cache_entry(254, 32), cache_entry(254, 64), cache_entry(254, 128), cache_entry(254, 256), cache_entry(64, 2048)
+            ]));
+            // 256 fragments of 256 bytes
+            glyph.message.insert("fragCache".to_string(), Box::new(U32::LE(0x0100_0100)));
+        }
+
+        let mut multifragment = ts_multifragment_update_capability_ts();
+        multifragment.message.insert("MaxRequestSize".to_string(), Box::new(U32::LE(self.multifragment_max_size)));
+
+        let mut capabilities = vec![
+            ts_general_capability_set(Some(GeneralExtraFlag::LongCredentialsSupported as u16 | GeneralExtraFlag::NoBitmapCompressionHdr as u16 | GeneralExtraFlag::EncSaltedChecksum as u16 | GeneralExtraFlag::FastpathOutputSupported as u16)),
+            ts_bitmap_capability_set(Some(self.color_depth), Some(width), Some(height)),
+            order,
+            bitmap_cache,
+            ts_pointer_capability_set(),
+            ts_sound_capability_set(),
+            ts_input_capability_set(Some(self.input_flags), Some(layout)),
+            ts_brush_capability_set(),
+            glyph,
+            ts_offscreen_capability_set(),
+            ts_virtualchannel_capability_set(),
+            multifragment,
+            ts_surface_commands_capability_set(Some(SurfaceCommandsFlag::SurfcmdsSetSurfaceBits as u32 | SurfaceCommandsFlag::SurfcmdsFrameMarker as u32 | SurfaceCommandsFlag::SurfcmdsStreamSurfaceBits as u32))
+        ];
+
+        if !self.bitmap_codecs.is_empty() {
+            capabilities.push(ts_bitmap_codecs_capability_set(&self.bitmap_codecs));
+        }
+
+        if let Some(max_unacknowledged_frame_count) = self.frame_acknowledge {
+            capabilities.push(ts_frame_acknowledge_capability_set(Some(max_unacknowledged_frame_count)));
+        }
+
+        // Replace default capabilities by added one
+        for (cap_type, message) in self.extra.iter() {
+            let capability = Capability {
+                cap_type: *cap_type,
+                message: component![
+                    "capabilitySet" => message.clone()
+                ]
+            };
+            match capabilities.iter().position(|c| c.cap_type == *cap_type) {
+                Some(index) => capabilities[index] = capability,
+                None => capabilities.push(capability)
+            }
+        }
+
+        capabilities.retain(|c| !self.removed.contains(&c.cap_type));
+        Ok(capabilities)
+    }
+}
+
+impl Default for CapabilitySet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check that a capability is of the expected type
+fn expect_capability(capability: &Capability, cap_type: CapabilitySetType) -> RdpResult<()> {
+    if capability.cap_type != cap_type {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::UnexpectedType, &format!("CAPABILITY: Expected {:?} got {:?}", cap_type, capability.cap_type))))
+    }
+    Ok(())
+}
+
+/// Typed view of a general capability set
+#[derive(Clone, Debug)]
+pub struct GeneralCapability {
+    pub os_major_type: u16,
+    pub os_minor_type: u16,
+    pub extra_flags: u16,
+    pub refresh_rect_support: bool,
+    pub suppress_output_support: bool
+}
+
+impl GeneralCapability {
+    /// Build the view from a parsed capability
+    ///
+    /// # Example
+    /// ```
+    /// use rdp::core::capability::{ts_general_capability_set, GeneralCapability};
+    /// let general = GeneralCapability::from_capability(&ts_general_capability_set(Some(4))).unwrap();
+    /// assert_eq!(general.extra_flags, 4);
+    /// ```
+    pub fn from_capability(capability: &Capability) -> RdpResult<Self> {
+        expect_capability(capability, CapabilitySetType::CapstypeGeneral)?;
+        Ok(GeneralCapability {
+            os_major_type: cast!(DataType::U16, capability.message["osMajorType"])?,
+            os_minor_type: cast!(DataType::U16, capability.message["osMinorType"])?,
+            extra_flags: cast!(DataType::U16, capability.message["extraFlags"])?,
+            refresh_rect_support: cast!(DataType::U8, capability.message["refreshRectSupport"])? != 0,
+            suppress_output_support: cast!(DataType::U8, capability.message["suppressOutputSupport"])? != 0
+        })
+    }
+}
+
+/// Typed view of a bitmap capability set
+#[derive(Clone, Debug)]
+pub struct BitmapCapability {
+    pub preferred_bits_per_pixel: u16,
+    pub desktop_width: u16,
+    pub desktop_height: u16,
+    pub desktop_resize_flag: bool,
+    pub drawing_flags: u8
+}
+
+impl BitmapCapability {
+    /// Build the view from a parsed capability
+    pub fn from_capability(capability: &Capability) -> RdpResult<Self> {
+        expect_capability(capability, CapabilitySetType::CapstypeBitmap)?;
+        Ok(BitmapCapability {
+            preferred_bits_per_pixel: cast!(DataType::U16, capability.message["preferredBitsPerPixel"])?,
+            desktop_width: cast!(DataType::U16, capability.message["desktopWidth"])?,
+            desktop_height: cast!(DataType::U16, capability.message["desktopHeight"])?,
+            desktop_resize_flag: cast!(DataType::U16, capability.message["desktopResizeFlag"])? != 0,
+            drawing_flags: cast!(DataType::U8, capability.message["drawingFlags"])?
+        })
+    }
+}
+
+/// Typed view of an order capability set
+#[derive(Clone, Debug)]
+pub struct OrderCapability {
+    pub order_flags: u16,
+    pub order_support: Vec<u8>,
+    pub desktop_save_size: u32,
+    pub text_ansi_code_page: u16
+}
+
+impl OrderCapability {
+    /// Build the view from a parsed capability
+    pub fn from_capability(capability: &Capability) -> RdpResult<Self> {
+        expect_capability(capability, CapabilitySetType::CapstypeOrder)?;
+        Ok(OrderCapability {
+            order_flags: cast!(DataType::U16, capability.message["orderFlags"])?,
+            order_support: cast!(DataType::Slice, capability.message["orderSupport"])?.to_vec(),
+            desktop_save_size: cast!(DataType::U32, capability.message["desktopSaveSize"])?,
+            text_ansi_code_page: cast!(DataType::U16, capability.message["textANSICodePage"])?
+        })
+    }
+}
+
+/// Typed view of an input capability set
+#[derive(Clone, Debug)]
+pub struct InputCapability {
+    pub input_flags: u16,
+    pub keyboard_layout: u32,
+    pub keyboard_type: u32,
+    pub keyboard_sub_type: u32,
+    pub keyboard_function_key: u32
+}
+
+impl InputCapability {
+    /// Build the view from a parsed capability
+    pub fn from_capability(capability: &Capability) -> RdpResult<Self> {
+        expect_capability(capability, CapabilitySetType::CapstypeInput)?;
+        Ok(InputCapability {
+            input_flags: cast!(DataType::U16, capability.message["inputFlags"])?,
+            keyboard_layout: cast!(DataType::U32, capability.message["keyboardLayout"])?,
+            keyboard_type: cast!(DataType::U32, capability.message["keyboardType"])?,
+            keyboard_sub_type: cast!(DataType::U32, capability.message["keyboardSubType"])?,
+            keyboard_function_key: cast!(DataType::U32, capability.message["keyboardFunctionKey"])?
+        })
+    }
+}
+
+/// Typed view of a pointer capability set
+#[derive(Clone, Debug)]
+pub struct PointerCapability {
+    pub color_pointer_flag: bool,
+    pub color_pointer_cache_size: u16
+}
+
+impl PointerCapability {
+    /// Build the view from a parsed capability
+    pub fn from_capability(capability: &Capability) -> RdpResult<Self> {
+        expect_capability(capability, CapabilitySetType::CapstypePointer)?;
+        Ok(PointerCapability {
+            color_pointer_flag: cast!(DataType::U16, capability.message["colorPointerFlag"])? != 0,
+            color_pointer_cache_size: cast!(DataType::U16, capability.message["colorPointerCacheSize"])?
+        })
+    }
+}
+
+/// Typed view of a virtual channel capability set
+#[derive(Clone, Debug)]
+pub struct VirtualChannelCapability {
+    pub flags: u32,
+    /// Optional field, only sent by server
+    pub chunk_size: Option<u32>
+}
+
+impl VirtualChannelCapability {
+    /// Build the view from a parsed capability
+    pub fn from_capability(capability: &Capability) -> RdpResult<Self> {
+        expect_capability(capability, CapabilitySetType::CapstypeVirtualchannel)?;
+        Ok(VirtualChannelCapability {
+            flags: cast!(DataType::U32, capability.message["flags"])?,
+            chunk_size: cast!(DataType::U32, capability.message["VCChunkSize"]).ok()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Default capabilities are the one historically sent
+    #[test]
+    fn test_default_capability_set() {
+        let capabilities = CapabilitySet::new().build(800, 600, KeyboardLayout::US).unwrap();
+        assert_eq!(capabilities.len(), 13);
+        assert_eq!(cast!(DataType::U32, capabilities[12].message["cmdFlags"]).unwrap(), 0x52);
+        assert_eq!(cast!(DataType::U16, capabilities[1].message["preferredBitsPerPixel"]).unwrap(), 24);
+        assert_eq!(cast!(DataType::U16, capabilities[2].message["orderFlags"]).unwrap(), OrderFlag::NEGOTIATEORDERSUPPORT as u16 | OrderFlag::ZEROBOUNDSDELTASSUPPORT as u16);
+    }
+
+    /// Builder options are reflected in capability sets
+    #[test]
+    fn test_configured_capability_set() {
+        let mut order_support = [0; 32];
+        order_support[0] = 1;
+        let capabilities = CapabilitySet::new()
+            .color_depth(16)
+            .order_support(order_support)
+            .multifragment_max_size(0x40000)
+            .glyph_support(GlyphSupportLevel::GlyphSupportEncode)
+            .build(1024, 768, KeyboardLayout::French).unwrap();
+
+        assert_eq!(cast!(DataType::U16, capabilities[1].message["preferredBitsPerPixel"]).unwrap(), 16);
+        assert_eq!(cast!(DataType::Slice, capabilities[2].message["orderSupport"]).unwrap(), &order_support[..]);
+        assert_eq!(cast!(DataType::U16, capabilities[8].message["glyphSupportLevel"]).unwrap(), 3);
+        assert_eq!(cast!(DataType::U32, capabilities[8].message["fragCache"]).unwrap(), 0x0100_0100);
+        assert_eq!(cast!(DataType::U16, capabilities[3].message["cache0Entries"]).unwrap(), 0);
+        assert_eq!(cast!(DataType::U32, capabilities[11].message["MaxRequestSize"]).unwrap(), 0x40000);
+        assert_eq!(to_vec(&capability_set(Some(capabilities.into_iter().nth(2).unwrap()))).len(), 88);
+    }
+
+    /// Primary drawing orders are opt-in
+    #[test]
+    fn test_order_support() {
+        let capabilities = CapabilitySet::new()
+            .order(OrderSupportIndex::TsNegPatbltIndex)
+            .order(OrderSupportIndex::TsNegGlyphIndexIndex);
+        let order_support = capabilities.get_order_support();
+        assert_eq!((order_support[1], order_support[0x1B]), (1, 1));
+        assert_eq!(order_support.iter().filter(|x| **x != 0).count(), 2);
+        assert!(CapabilitySet::new().get_order_support().iter().all(|x| *x == 0));
+
+        // MemBlt needs a bitmap cache
+        let capabilities = CapabilitySet::new().order(OrderSupportIndex::TsNegMembltIndex).build(800, 600, KeyboardLayout::US).unwrap();
+        assert_eq!(cast!(DataType::U16, capabilities[3].message["cache0Entries"]).unwrap(), 200);
+        assert_eq!(cast!(DataType::U16, capabilities[3].message["cache2MaximumCellSize"]).unwrap(), 4096 * 3);
+    }
+
+    /// Bitmap codecs and frame acknowledge add their capability sets
+    #[test]
+    fn test_bitmap_codecs_capability_set() {
+        let capabilities = CapabilitySet::new()
+            .color_depth(32)
+            .bitmap_codec(BitmapCodec::CodecRemotefx)
+            .frame_acknowledge(2)
+            .build(800, 600, KeyboardLayout::US).unwrap();
+        assert_eq!(capabilities.len(), 15);
+        assert_eq!(capabilities[12].cap_type, CapabilitySetType::CapsettypeSurfaceCommands);
+        assert_eq!(capabilities[13].cap_type, CapabilitySetType::CapsettypeBitmapCodecs);
+        assert_eq!(cast!(DataType::U32, capabilities[14].message["maxUnacknowledgedFrameCount"]).unwrap(), 2);
+        assert_eq!(BitmapCodec::CodecRemotefx.properties().len(), 49);
+        assert_eq!(BitmapCodec::CodecNscodec.properties(), [1, 1, 3]);
+    }
+
+    /// Add and remove whole capability sets
+    #[test]
+    fn test_add_remove_capability_set() {
+        let capabilities = CapabilitySet::new()
+            .remove_capability(CapabilitySetType::CapstypeSound)
+            .add_capability(Capability {
+                cap_type: CapabilitySetType::CapstypeBrush,
+                message: component!["brushSupportLevel" => U32::LE(1)]
+            })
+            .add_capability(Capability {
+                cap_type: CapabilitySetType::CapsettypeLargePointer,
+                message: component!["largePointerSupportFlags" => U16::LE(1)]
+            })
+            .build(800, 600, KeyboardLayout::US).unwrap();
+
+        assert_eq!(capabilities.len(), 13);
+        assert!(capabilities.iter().all(|c| c.cap_type != CapabilitySetType::CapstypeSound));
+        assert_eq!(to_vec(&capability_set(Some(capabilities.into_iter().find(|c| c.cap_type == CapabilitySetType::CapstypeBrush).unwrap()))), vec![15, 0, 8, 0, 1, 0, 0, 0]);
+    }
+
+    /// Typed views are read from parsed capability sets
+    #[test]
+    fn test_capability_views() {
+        let mut input = capability_set(None);
+        input.read(&mut Cursor::new(to_vec(&capability_set(Some(ts_input_capability_set(Some(InputFlags::InputFlagUnicode as u16), Some(KeyboardLayout::French))))))).unwrap();
+        let input = InputCapability::from_capability(&Capability::from_capability_set(&input).unwrap()).unwrap();
+        assert_eq!(input.input_flags, InputFlags::InputFlagUnicode as u16);
+        assert_eq!(input.keyboard_layout, KeyboardLayout::French as u32);
+
+        let mut virtual_channel = capability_set(None);
+        virtual_channel.read(&mut Cursor::new(vec![20, 0, 8, 0, 1, 0, 0, 0])).unwrap();
+        let virtual_channel = VirtualChannelCapability::from_capability(&Capability::from_capability_set(&virtual_channel).unwrap()).unwrap();
+        assert_eq!(virtual_channel.flags, 1);
+        assert_eq!(virtual_channel.chunk_size, None);
+
+        assert!(BitmapCapability::from_capability(&ts_pointer_capability_set()).is_err());
+    }
+
+    /// Only standard color depth are accepted
+    #[test]
+    fn test_invalid_color_depth() {
+        assert!(CapabilitySet::new().color_depth(12).build(800, 600, KeyboardLayout::US).is_err());
+    }
+}
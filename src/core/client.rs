@@ -5,22 +5,110 @@ use core::tpkt;
 use core::sec;
 use core::global;
 use std::io::{Read, Write};
-use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
-use model::link::{Link, Stream};
-use core::event::{RdpEvent, PointerButton};
-use core::global::{ts_pointer_event, PointerFlag, ts_keyboard_event, KeyboardFlag};
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind, ErrorInfo};
+use model::link::{Link, Stream, ReadTimeout};
+use core::event::{RdpEvent, PointerButton, PointerWheel, Rectangle, UnicodeEvent};
+use core::global::{TSInputEvent, ts_pointer_event, PointerFlag, ts_keyboard_event, KeyboardFlag, ts_sync_event, sync_flags, ts_unicode_keyboard_event, ts_pointerx_event, PointerXFlag, wheel_rotation, ts_pointer_rel_event};
+use codec::mppc::CompressionType;
+use codec::surface::SurfaceDecoder;
+use core::capability::{InputFlags, CapabilitySet, CapabilitySetType, Capability, GeneralCapability, BitmapCapability, OrderCapability, InputCapability, PointerCapability, VirtualChannelCapability};
 use nla::ntlm::Ntlm;
+use std::io::ErrorKind;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 impl From<&str> for KeyboardLayout {
     fn from(e: &str) -> Self {
-        match e {
-            "us" => KeyboardLayout::US,
-            "fr" => KeyboardLayout::French,
-            _ => KeyboardLayout::US,
+        KeyboardLayout::from_str(e).unwrap_or(KeyboardLayout::US)
+    }
+}
+
+/// Result of a shutdown request
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ShutdownResult {
+    /// Server refused to close the session
+    /// A user is logged on, the session is still active
+    Denied,
+    /// Server closed the connection and logged off the session
+    Logoff,
+    /// Server closed the connection
+    /// the session may still exist on the server
+    Disconnect,
+    /// Server didn't respond in time
+    /// the session is still active
+    Timeout
+}
+
+impl ShutdownResult {
+    /// Result of a disconnection
+    /// depending on the last reason sent by the server
+    fn from_error_info(error_info: Option<ErrorInfo>) -> Self {
+        match error_info {
+            Some(ErrorInfo::LogoffByUser) | Some(ErrorInfo::RpcInitiatedLogoff) => ShutdownResult::Logoff,
+            _ => ShutdownResult::Disconnect
         }
     }
 }
 
+/// Parameters negotiated with the server
+/// during the connection sequence
+///
+/// This is a snapshot, use RdpClient::get_session_info
+/// to get an up to date one
+#[derive(Clone, Debug)]
+pub struct SessionInfo {
+    /// RDP version number as sent by the server
+    pub rdp_version: u32,
+    /// Security protocol selected by the server
+    pub selected_protocol: x224::Protocols,
+    /// Share id sent by the server in the demand active PDU
+    pub share_id: Option<u32>,
+    /// User id attributed by the MCS layer
+    pub user_id: u16,
+    /// Global channel id
+    pub global_channel_id: u16,
+    /// Static channels id returned by the server
+    pub channel_ids: Vec<u16>,
+    /// Desktop width
+    pub width: u16,
+    /// Desktop height
+    pub height: u16,
+    /// Type of all capability sets sent by the server
+    pub capability_types: Vec<CapabilitySetType>,
+    pub general: Option<GeneralCapability>,
+    pub bitmap: Option<BitmapCapability>,
+    pub order: Option<OrderCapability>,
+    pub input: Option<InputCapability>,
+    pub pointer: Option<PointerCapability>,
+    pub virtual_channel: Option<VirtualChannelCapability>
+}
+
+/// Find a capability by type and convert it into its typed view
+/// None if the server didn't send it
+fn capability_view<T>(capabilities: &[Capability], cap_type: CapabilitySetType, from_capability: fn(&Capability) -> RdpResult<T>) -> RdpResult<Option<T>> {
+    match capabilities.iter().find(|capability| capability.cap_type == cap_type) {
+        Some(capability) => Ok(Some(from_capability(capability)?)),
+        None => Ok(None)
+    }
+}
+
+/// Press then release each character of a text
+fn text_events(text: &str) -> Vec<RdpEvent> {
+    let mut events = Vec::new();
+    for code_point in text.chars() {
+        events.push(RdpEvent::Unicode(UnicodeEvent { code_point, down: true }));
+        events.push(RdpEvent::Unicode(UnicodeEvent { code_point, down: false }));
+    }
+    events
+}
+
+/// Characters outside the BMP are sent as surrogate pair
+fn unicode_input_events(unicode: &UnicodeEvent) -> Vec<TSInputEvent> {
+    let flags = if unicode.down { 0 } else { KeyboardFlag::KbdflagsRelease as u16 };
+    let mut buffer = [0; 2];
+    unicode.code_point.encode_utf16(&mut buffer).iter().map(|code| ts_unicode_keyboard_event(Some(flags), Some(*code))).collect()
+}
+
 pub struct RdpClient<S> {
     /// Multi channel
     /// This is the main switch layer of the protocol
@@ -56,7 +144,12 @@ impl<S: Read + Write> RdpClient<S> {
     /// ```
     pub fn read<T>(&mut self, callback: T) -> RdpResult<()>
     where T: FnMut(RdpEvent) {
-        let (channel_name, message) = self.mcs.read()?;
+        let error_info = self.global.get_error_info();
+        let (channel_name, message) = self.mcs.read().map_err(|e| match (e, error_info) {
+            // Attach the reason sent by the server
+            (Error::RdpError(ref e), Some(error_info)) if e.kind() == RdpErrorKind::Disconnect => Error::RdpError(RdpError::disconnect(error_info)),
+            (e, _) => e
+        })?;
         match channel_name.as_str() {
             "global" => self.global.read(message, &mut self.mcs, callback),
             _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::UnexpectedType, &format!("Invalid channel name {:?}", channel_name))))
@@ -70,7 +163,7 @@ impl<S: Read + Write> RdpClient<S> {
     /// ```no_run
     /// use std::net::{SocketAddr, TcpStream};
     /// use rdp::core::client::Connector;
-    /// use rdp::core::event::{RdpEvent, PointerEvent, PointerButton};
+    /// use rdp::core::event::{RdpEvent, PointerEvent, PointerButton, PointerWheel};
     /// let addr = "127.0.0.1:3389".parse::<SocketAddr>().unwrap();
     /// let tcp = TcpStream::connect(&addr).unwrap();
     /// let mut connector = Connector::new()
@@ -83,16 +176,62 @@ impl<S: Read + Write> RdpClient<S> {
     ///         x: 100 as u16,
     ///         y: 100 as u16,
     ///         button: PointerButton::Left,
-    ///         down: true
+    ///         down: true,
+    ///         wheel: PointerWheel::None
     ///     }
     /// )).unwrap()
     /// ```
     pub fn write(&mut self, event: RdpEvent) -> RdpResult<()> {
+        let events = self.to_input_events(event)?;
+        self.global.write_input_events(events, &mut self.mcs)
+    }
+
+    /// Write a batch of events to the server
+    /// Events are sent in a single PDU
+    /// using fast path if the server support it
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// use rdp::core::event::{RdpEvent, KeyboardEvent};
+    /// let mut client = connector.connect(tcp).unwrap();
+    /// client.write_batch(vec![
+    ///     RdpEvent::Key(KeyboardEvent { code: 0x1E, down: true }),
+    ///     RdpEvent::Key(KeyboardEvent { code: 0x1E, down: false })
+    /// ]).unwrap()
+    /// ```
+    pub fn write_batch(&mut self, events: Vec<RdpEvent>) -> RdpResult<()> {
+        let mut input_events = Vec::new();
+        for event in events {
+            input_events.append(&mut self.to_input_events(event)?);
+        }
+        self.global.write_input_events(input_events, &mut self.mcs)
+    }
+
+    /// Convert an event into protocol input events
+    fn to_input_events(&self, event: RdpEvent) -> RdpResult<Vec<TSInputEvent>> {
         match event {
             // Pointer event
             // Mouse position an d button position
             RdpEvent::Pointer(pointer) => {
-                // Pointer are sent to global channel
+                // Wheel rotation
+                match pointer.wheel {
+                    PointerWheel::Vertical(delta) => return Ok(vec![ts_pointer_event(Some(PointerFlag::PtrflagsWheel as u16 | wheel_rotation(delta)), Some(pointer.x), Some(pointer.y))]),
+                    PointerWheel::Horizontal(delta) => return Ok(vec![ts_pointer_event(Some(PointerFlag::PtrflagsHwheel as u16 | wheel_rotation(delta)), Some(pointer.x), Some(pointer.y))]),
+                    PointerWheel::None => ()
+                }
+
+                // Extended buttons use the extended mouse event
+                if pointer.button == PointerButton::X1 || pointer.button == PointerButton::X2 {
+                    if !self.global.is_input_flag_supported(InputFlags::InputFlagMousex) {
+                        return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "RDPCLIENT: Server doesn't support extended mouse buttons")))
+                    }
+                    let mut flags = if pointer.button == PointerButton::X1 { PointerXFlag::PtrxflagsButton1 as u16 } else { PointerXFlag::PtrxflagsButton2 as u16 };
+                    if pointer.down {
+                        flags |= PointerXFlag::PtrxflagsDown as u16;
+                    }
+                    return Ok(vec![ts_pointerx_event(Some(flags), Some(pointer.x), Some(pointer.y))])
+                }
+
                 // Compute flags
                 let mut flags: u16 = 0;
                 match pointer.button {
@@ -106,7 +245,25 @@ impl<S: Read + Write> RdpClient<S> {
                     flags |= PointerFlag::PtrflagsDown as u16;
                 }
 
-                self.global.write_input_event(ts_pointer_event(Some(flags), Some(pointer.x), Some(pointer.y)), &mut self.mcs)
+                Ok(vec![ts_pointer_event(Some(flags), Some(pointer.x), Some(pointer.y))])
+            },
+            // Relative mouse movement
+            RdpEvent::RelativePointer(pointer) => {
+                if !self.global.is_input_flag_supported(InputFlags::InputFlagMouseRelative) {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "RDPCLIENT: Server doesn't support relative mouse movement")))
+                }
+                let mut flags: u16 = match pointer.button {
+                    PointerButton::Left => PointerFlag::PtrflagsButton1 as u16,
+                    PointerButton::Right => PointerFlag::PtrflagsButton2 as u16,
+                    PointerButton::Middle => PointerFlag::PtrflagsButton3 as u16,
+                    PointerButton::X1 => PointerXFlag::PtrxflagsButton1 as u16,
+                    PointerButton::X2 => PointerXFlag::PtrxflagsButton2 as u16,
+                    PointerButton::None => PointerFlag::PtrflagsMove as u16
+                };
+                if pointer.down {
+                    flags |= PointerFlag::PtrflagsDown as u16;
+                }
+                Ok(vec![ts_pointer_rel_event(Some(flags), Some(pointer.x_delta), Some(pointer.y_delta))])
             },
             // Raw keyboard input
             RdpEvent::Key(key) => {
@@ -114,8 +271,24 @@ impl<S: Read + Write> RdpClient<S> {
                 if !key.down {
                     flags |= KeyboardFlag::KbdflagsRelease as u16;
                 }
-                self.global.write_input_event(ts_keyboard_event(Some(flags), Some(key.code)), &mut self.mcs)
+                // Extended keys are prefixed by 0xE0
+                let mut code = key.code;
+                if code & 0xFF00 == 0xE000 {
+                    flags |= KeyboardFlag::KbdflagsExtended as u16;
+                    code &= 0x00FF;
+                }
+                Ok(vec![ts_keyboard_event(Some(flags), Some(code))])
             }
+            // Unicode keyboard input
+            // Characters outside the BMP are sent as surrogate pair
+            RdpEvent::Unicode(unicode) => {
+                if !self.global.is_input_flag_supported(InputFlags::InputFlagUnicode) {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "RDPCLIENT: Server doesn't support unicode input")))
+                }
+                Ok(unicode_input_events(&unicode))
+            },
+            // Toggle keys state
+            RdpEvent::Sync(sync) => Ok(vec![ts_sync_event(Some(sync_flags(&sync)))]),
             _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::UnexpectedType, "RDPCLIENT: This event can't be sent")))
         }
     }
@@ -135,10 +308,199 @@ impl<S: Read + Write> RdpClient<S> {
         }
     }
 
+    /// True if the server accept relative mouse events
+    /// Pointer locked applications should check it
+    /// before sending RdpEvent::RelativePointer
+    pub fn is_relative_pointer_supported(&self) -> bool {
+        self.global.is_input_flag_supported(InputFlags::InputFlagMouseRelative)
+    }
+
+    /// Type a whole text using unicode keyboard events
+    /// Each character is pressed then released
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// let mut client = connector.connect(tcp).unwrap();
+    /// client.write_text("Hello Wörld 🦀").unwrap()
+    /// ```
+    pub fn write_text(&mut self, text: &str) -> RdpResult<()> {
+        self.write_batch(text_events(text))
+    }
+
+    /// Ask the server to redraw some areas of the desktop
+    /// Bounds of each area are inclusive
+    ///
+    /// Return a NotImplemented error if the server
+    /// doesn't support the Refresh Rect PDU
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// use rdp::core::event::Rectangle;
+    /// let mut client = connector.connect(tcp).unwrap();
+    /// client.refresh_rect(&[Rectangle { left: 0, top: 0, right: 799, bottom: 599 }]).unwrap()
+    /// ```
+    pub fn refresh_rect(&mut self, areas: &[Rectangle]) -> RdpResult<()> {
+        self.global.write_refresh_rect(areas, &mut self.mcs)
+    }
+
+    /// Ask the server to stop sending display updates
+    /// Use it when the window is minimized or hidden
+    ///
+    /// Return a NotImplemented error if the server
+    /// doesn't support the Suppress Output PDU
+    pub fn suppress_output(&mut self) -> RdpResult<()> {
+        self.global.write_suppress_output(false, &mut self.mcs)
+    }
+
+    /// Ask the server to restart sending display updates
+    /// for the whole desktop
+    pub fn resume_output(&mut self) -> RdpResult<()> {
+        self.global.write_suppress_output(true, &mut self.mcs)
+    }
+
     /// Close client is indeed close the switch layer
     pub fn shutdown(&mut self) -> RdpResult<()> {
         self.mcs.shutdown()
     }
+
+    /// Decode surface bits of a codec id with a custom decoder
+    /// Built-in decoders handle uncompressed data, NSCodec and RemoteFX
+    pub fn register_surface_decoder(&mut self, codec_id: u8, decoder: Box<dyn SurfaceDecoder>) {
+        self.global.register_surface_decoder(codec_id, decoder)
+    }
+
+    /// Palette used by 8 bpp bitmaps
+    /// None until the server sends one
+    pub fn get_palette(&self) -> Option<&[u32]> {
+        self.global.get_palette()
+    }
+
+    /// All capability sets sent by the server
+    pub fn get_server_capabilities(&self) -> &[Capability] {
+        self.global.get_server_capabilities()
+    }
+
+    /// Parameters negotiated with the server
+    /// Useful for diagnostic or to fingerprint a server
+    /// Fails if a capability set sent by the server is malformed
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::net::{SocketAddr, TcpStream};
+    /// use rdp::core::client::Connector;
+    /// let addr = "127.0.0.1:3389".parse::<SocketAddr>().unwrap();
+    /// let tcp = TcpStream::connect(&addr).unwrap();
+    /// let mut connector = Connector::new()
+    ///     .screen(800, 600)
+    ///     .credentials("domain".to_string(), "username".to_string(), "password".to_string());
+    /// let mut client = connector.connect(tcp).unwrap();
+    /// let session_info = client.get_session_info().unwrap();
+    /// println!("Server version {:x}", session_info.rdp_version);
+    /// ```
+    pub fn get_session_info(&self) -> RdpResult<SessionInfo> {
+        let capabilities = self.global.get_server_capabilities();
+        let (width, height) = self.global.get_desktop_size();
+        let (rdp_version, channel_ids) = match self.mcs.get_server_data() {
+            Some(server_data) => (server_data.raw_rdp_version, server_data.channel_ids.clone()),
+            None => (0, Vec::new())
+        };
+
+        Ok(SessionInfo {
+            rdp_version,
+            selected_protocol: self.mcs.get_selected_protocols(),
+            share_id: self.global.get_share_id(),
+            user_id: self.mcs.get_user_id(),
+            global_channel_id: self.mcs.get_global_channel_id(),
+            channel_ids,
+            width,
+            height,
+            capability_types: capabilities.iter().map(|capability| capability.cap_type).collect(),
+            general: capability_view(capabilities, CapabilitySetType::CapstypeGeneral, GeneralCapability::from_capability)?,
+            bitmap: capability_view(capabilities, CapabilitySetType::CapstypeBitmap, BitmapCapability::from_capability)?,
+            order: capability_view(capabilities, CapabilitySetType::CapstypeOrder, OrderCapability::from_capability)?,
+            input: capability_view(capabilities, CapabilitySetType::CapstypeInput, InputCapability::from_capability)?,
+            pointer: capability_view(capabilities, CapabilitySetType::CapstypePointer, PointerCapability::from_capability)?,
+            virtual_channel: capability_view(capabilities, CapabilitySetType::CapstypeVirtualchannel, VirtualChannelCapability::from_capability)?
+        })
+    }
+}
+
+impl<S: Read + Write + ReadTimeout> RdpClient<S> {
+    /// Ask the server to close the session
+    /// the same way mstsc does when user close the window
+    ///
+    /// Events received until the server respond
+    /// are forwarded to the callback
+    ///
+    /// The timeout bound the whole wait, a PDU which is
+    /// not fully received before it is a fatal error
+    /// because the stream can't be resynchronized.
+    /// The socket read timeout is restored before returning
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::net::{SocketAddr, TcpStream};
+    /// use std::time::Duration;
+    /// use rdp::core::client::{Connector, ShutdownResult};
+    /// let addr = "127.0.0.1:3389".parse::<SocketAddr>().unwrap();
+    /// let tcp = TcpStream::connect(&addr).unwrap();
+    /// let mut connector = Connector::new()
+    ///     .screen(800, 600)
+    ///     .credentials("domain".to_string(), "username".to_string(), "password".to_string());
+    /// let mut client = connector.connect(tcp).unwrap();
+    /// if client.request_shutdown(Duration::from_secs(5), |_| ()).unwrap() != ShutdownResult::Logoff {
+    ///     // A user is logged on, force disconnect
+    ///     client.shutdown().unwrap()
+    /// }
+    /// ```
+    pub fn request_shutdown<T>(&mut self, timeout: Duration, callback: T) -> RdpResult<ShutdownResult>
+    where T: FnMut(RdpEvent) {
+        let read_timeout = self.mcs.get_link_ref().get_read_timeout()?;
+        let result = self.wait_shutdown(timeout, callback);
+        self.mcs.get_link_ref().set_read_timeout(read_timeout)?;
+        result
+    }
+
+    /// Send the shutdown request and read PDUs
+    /// until the server respond or the deadline is reached
+    fn wait_shutdown<T>(&mut self, timeout: Duration, mut callback: T) -> RdpResult<ShutdownResult>
+    where T: FnMut(RdpEvent) {
+        let deadline = Instant::now() + timeout;
+        self.global.write_shutdown_request(&mut self.mcs)?;
+        loop {
+            let remaining = match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if remaining > Duration::from_millis(0) => remaining,
+                _ => return Ok(ShutdownResult::Timeout)
+            };
+            self.mcs.get_link_ref().set_read_timeout(Some(remaining))?;
+            let bytes_read = self.mcs.get_link_ref().get_bytes_read();
+
+            let mut denied = false;
+            let result = self.read(|event| match event {
+                RdpEvent::ShutdownDenied => denied = true,
+                _ => callback(event)
+            });
+
+            match result {
+                Ok(()) => if denied {
+                    return Ok(ShutdownResult::Denied)
+                },
+                Err(Error::RdpError(ref e)) if e.kind() == RdpErrorKind::Disconnect => {
+                    return Ok(ShutdownResult::from_error_info(e.error_info().or_else(|| self.global.get_error_info())))
+                },
+                // Server can close the socket without any notification
+                // the reason may have been sent just before
+                Err(Error::Io(ref e)) if e.kind() == ErrorKind::UnexpectedEof => return Ok(ShutdownResult::from_error_info(self.global.get_error_info())),
+                // Nothing was received, check the deadline again
+                Err(Error::Io(ref e)) if (e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut) && self.mcs.get_link_ref().get_bytes_read() == bytes_read => continue,
+                // Part of a PDU is lost, stream is desynchronized
+                Err(Error::Io(ref e)) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidRespond, "CLIENT: Read timeout in the middle of a PDU")))
+                },
+                Err(e) => return Err(e)
+            }
+        }
+    }
 }
 
 pub struct Connector {
@@ -171,7 +533,11 @@ pub struct Connector {
     name: String,
     /// Use network level authentication
     /// default TRUE
-    use_nla: bool
+    use_nla: bool,
+    /// Client capability sets
+    capabilities: CapabilitySet,
+    /// Ask the server to compress updates
+    compression: bool
 }
 
 impl Connector {
@@ -199,7 +565,9 @@ impl Connector {
             blank_creds: false,
             check_certificate: false,
             name: "rdp-rs".to_string(),
-            use_nla: true
+            use_nla: true,
+            capabilities: CapabilitySet::new(),
+            compression: false
         }
     }
 
@@ -248,7 +616,8 @@ impl Connector {
 
         // Create MCS layer and connect it
         let mut mcs = mcs::Client::new(x224);
-        mcs.connect(self.name.clone(), self.width, self.height, self.layout)?;
+        mcs.connect(self.name.clone(), self.width, self.height, self.layout, self.capabilities.get_color_depth())?;
+        let compression = if self.compression { Some(CompressionType::PacketComprType64k) } else { None };
         // state less connection for old secure layer
         if self.restricted_admin_mode {
             sec::connect(
@@ -256,7 +625,8 @@ impl Connector {
                 &"".to_string(),
                 &"".to_string(),
                 &"".to_string(),
-                self.auto_logon
+                self.auto_logon,
+                compression
             )?;
         } else {
             sec::connect(
@@ -264,7 +634,8 @@ impl Connector {
                 &self.domain,
                 &self.username,
                 &self.password,
-                self.auto_logon
+                self.auto_logon,
+                compression
             )?;
         }
 
@@ -275,7 +646,8 @@ impl Connector {
             self.width,
             self.height,
             self.layout,
-            &self.name
+            &self.name,
+            self.capabilities.clone()
         );
 
         Ok(RdpClient {
@@ -348,4 +720,57 @@ impl Connector {
         self.use_nla = use_nla;
         self
     }
-}
\ No newline at end of file
+
+    /// Set capability sets sent to the server
+    /// Color depth is also sent during GCC
+    /// so it's checked here
+    ///
+    /// # Example
+    /// ```
+    /// use rdp::core::client::Connector;
+    /// use rdp::core::capability::{CapabilitySet, CapabilitySetType};
+    /// let mut connector = Connector::new()
+    ///     .capabilities(CapabilitySet::new().color_depth(16).remove_capability(CapabilitySetType::CapstypeSound))
+    ///     .unwrap();
+    /// assert!(Connector::new().capabilities(CapabilitySet::new().color_depth(12)).is_err());
+    /// ```
+    pub fn capabilities(mut self, capabilities: CapabilitySet) -> RdpResult<Self> {
+        capabilities.check()?;
+        self.capabilities = capabilities;
+        Ok(self)
+    }
+
+    /// Ask the server for bulk compressed updates
+    /// Useful on low bandwidth links
+    pub fn compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::data::to_vec;
+
+    /// Test that a character outside the BMP is typed as a surrogate pair
+    #[test]
+    fn test_text_events_surrogate_pair() {
+        let mut input_events = Vec::new();
+        for event in text_events("\u{1F600}") {
+            if let RdpEvent::Unicode(unicode) = event {
+                input_events.append(&mut unicode_input_events(&unicode));
+            }
+            else {
+                panic!("Invalid event type")
+            }
+        }
+
+        let release = KeyboardFlag::KbdflagsRelease as u16;
+        let expected = [(0, 0xD83D), (0, 0xDE00), (release, 0xD83D), (release, 0xDE00)];
+        assert_eq!(input_events.len(), expected.len());
+        for (event, (flags, code)) in input_events.iter().zip(expected.iter()) {
+            assert_eq!(to_vec(event.get_message()), to_vec(ts_unicode_keyboard_event(Some(*flags), Some(*code)).get_message()));
+        }
+    }
+}
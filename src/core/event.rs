@@ -1,6 +1,7 @@
-use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind, ErrorInfo};
 use num_enum::TryFromPrimitive;
-use codec::rle::{rle_32_decompress, rle_16_decompress, rgb565torgb32};
+use core::order::{PrimaryOrder, OrderBounds, SecondaryOrder};
+use codec::rle::{rle_32_decompress, rle_16_decompress, rle_8_decompress, rle_24_decompress, rgb565torgb32, rgb555torgb32, rgb24torgb32, palette_to_rgb32};
 
 /// A bitmap event is used
 /// to notify client that it received
@@ -61,7 +62,6 @@ impl BitmapEvent {
     /// ```
     pub fn decompress(self) -> RdpResult<Vec<u8>> {
 
-        // actually only handle 32 bpp
         match self.bpp {
             32 => {
                 // 32 bpp is straight forward
@@ -75,6 +75,17 @@ impl BitmapEvent {
                     }
                 )
             },
+            24 => {
+                let result_24bpp = if self.is_compress {
+                    let mut result = vec![0_u32; self.width as usize * self.height as usize];
+                    rle_24_decompress(&self.data, self.width as usize, self.height as usize, &mut result)?;
+                    result
+                } else {
+                    self.uncompressed_rows(3)?.chunks(3).map(|p| p[0] as u32 | (p[1] as u32) << 8 | (p[2] as u32) << 16).collect()
+                };
+
+                Ok(rgb24torgb32(&result_24bpp, self.width as usize, self.height as usize))
+            },
             16 => {
                 // 16 bpp is more consumer
                 let result_16bpp = if self.is_compress {
@@ -94,9 +105,76 @@ impl BitmapEvent {
 
                 Ok(rgb565torgb32(&result_16bpp, self.width as usize, self.height as usize))
             },
+            15 => {
+                // same encoding as 16 bpp
+                let result_15bpp = if self.is_compress {
+                    let mut result = vec![0_u16; self.width as usize * self.height as usize];
+                    rle_16_decompress(&self.data, self.width as usize, self.height as usize, &mut result)?;
+                    result
+                } else {
+                    self.uncompressed_rows(2)?.chunks(2).map(|p| (p[1] as u16) << 8 | p[0] as u16).collect()
+                };
+
+                Ok(rgb555torgb32(&result_15bpp, self.width as usize, self.height as usize))
+            },
+            // client converts 8 bpp bitmaps once a palette is received
+            8 => Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "No palette received for 8 bpp bitmap"))),
             _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, &format!("Decompression Algorithm not implemented for bpp {}", self.bpp))))
         }
     }
+
+    /// Decompress a bitmap and convert it into 32 bpp
+    /// 8 bpp bitmap are palettized, palette entries are 0x00RRGGBB
+    /// Client already applies the last Palette event,
+    /// use it for bitmaps received by other means
+    ///
+    /// # Example
+    /// ```
+    /// use rdp::core::event::BitmapEvent;
+    /// let bitmap = BitmapEvent {
+    ///     dest_left: 0, dest_top: 0, dest_right: 0, dest_bottom: 0,
+    ///     width: 4, height: 1, bpp: 8, is_compress: false,
+    ///     data: vec![0, 1, 1, 0]
+    /// };
+    /// let palette = [0x000000, 0xff0000];
+    /// assert_eq!(bitmap.decompress_with_palette(&palette).unwrap(), [0, 0, 0, 0xff, 0, 0, 0xff, 0xff, 0, 0, 0xff, 0xff, 0, 0, 0, 0xff]);
+    /// ```
+    pub fn decompress_with_palette(self, palette: &[u32]) -> RdpResult<Vec<u8>> {
+        if self.bpp != 8 {
+            return self.decompress()
+        }
+
+        let indexes = if self.is_compress {
+            let mut result = vec![0_u8; self.width as usize * self.height as usize];
+            rle_8_decompress(&self.data, self.width as usize, self.height as usize, &mut result)?;
+            result
+        } else {
+            self.uncompressed_rows(1)?
+        };
+
+        Ok(palette_to_rgb32(&indexes, palette, self.width as usize, self.height as usize))
+    }
+
+    /// Uncompressed bitmap are sent bottom-up
+    /// with rows padded on 4 bytes
+    /// Return top-down rows without padding
+    fn uncompressed_rows(&self, bytes_per_pixel: usize) -> RdpResult<Vec<u8>> {
+        if self.height == 0 {
+            return Ok(Vec::new())
+        }
+
+        let row_size = self.width as usize * bytes_per_pixel;
+        let stride = (row_size + 3) & !3;
+        if self.data.len() < stride * (self.height as usize - 1) + row_size {
+            return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "Invalid uncompressed bitmap size")))
+        }
+
+        let mut result = Vec::with_capacity(row_size * self.height as usize);
+        for i in (0..self.height as usize).rev() {
+            result.extend_from_slice(&self.data[i * stride..i * stride + row_size]);
+        }
+        Ok(result)
+    }
 }
 
 #[repr(u8)]
@@ -109,7 +187,26 @@ pub enum PointerButton {
     /// Right mouse button
     Right = 2,
     /// Wheel mouse button
-    Middle = 3
+    Middle = 3,
+    /// First extended button (back)
+    /// Only sent if server support extended mouse event
+    X1 = 4,
+    /// Second extended button (forward)
+    /// Only sent if server support extended mouse event
+    X2 = 5
+}
+
+/// Mouse wheel rotation
+/// Delta is expressed in wheel units
+/// One notch is usually 120 units
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub enum PointerWheel {
+    /// No wheel rotation
+    None,
+    /// Vertical wheel, positive delta scroll up
+    Vertical(i16),
+    /// Horizontal wheel, positive delta scroll right
+    Horizontal(i16)
 }
 
 /// A mouse pointer event
@@ -121,6 +218,23 @@ pub struct PointerEvent {
     /// Which button is pressed
     pub button: PointerButton,
     /// true if it's a down press action
+    pub down: bool,
+    /// Wheel rotation
+    /// If set, button and down are ignored
+    pub wheel: PointerWheel
+}
+
+/// A relative mouse event
+/// Used by pointer locked applications
+/// Only sent if server support relative mouse movement
+pub struct RelativePointerEvent {
+    /// horizontal move since last event
+    pub x_delta: i16,
+    /// vertical move since last event
+    pub y_delta: i16,
+    /// Which button is pressed
+    pub button: PointerButton,
+    /// true if it's a down press action
     pub down: bool
 }
 
@@ -129,17 +243,210 @@ pub struct PointerEvent {
 /// to inform which key is pressed
 pub struct KeyboardEvent {
     /// Scancode of the key
+    /// Extended keys are prefixed by 0xE0 (0xE048 for up arrow)
     pub code: u16,
     /// State of the key
     pub down: bool
 }
 
+/// Unicode keyboard event
+/// Send a character without any layout consideration
+pub struct UnicodeEvent {
+    /// Character to send
+    pub code_point: char,
+    /// State of the key
+    pub down: bool
+}
+
+/// State of keyboard toggle keys
+/// Used to synchronize lock keys between client and server
+#[derive(Copy, Clone, Default)]
+pub struct SyncEvent {
+    pub scroll_lock: bool,
+    pub num_lock: bool,
+    pub caps_lock: bool,
+    pub kana_lock: bool
+}
+
+/// A rectangle area on the remote desktop
+/// Bounds are inclusive
+#[derive(Copy, Clone)]
+pub struct Rectangle {
+    pub left: u16,
+    pub top: u16,
+    pub right: u16,
+    pub bottom: u16
+}
+
+/// User and session identity
+/// sent by the server once the user is logged on
+pub struct LogonInfo {
+    /// Id of the session on the server
+    pub session_id: u32,
+    /// Domain of the logged on user
+    pub domain: String,
+    /// Name of the logged on user
+    pub username: String
+}
+
+/// Auto reconnect cookie
+/// Can be used later to reconnect to the same session
+pub struct AutoReconnectCookie {
+    /// Id of the session on the server
+    pub logon_id: u32,
+    /// Random secret generated by the server
+    pub random_bits: Vec<u8>
+}
+
+/// Error or warning that happen during logon
+///
+/// error_type is either a NTSTATUS or a LOGON_MSG_* code
+/// error_data is either a session id or a LOGON_FAILED_* code
+pub struct LogonErrorInfo {
+    pub error_type: u32,
+    pub error_data: u32
+}
+
+/// Extended logon informations
+pub struct LogonExtendedInfo {
+    /// Present if the server allow auto reconnect
+    pub auto_reconnect: Option<AutoReconnectCookie>,
+    /// Present if an error happen during logon
+    pub error: Option<LogonErrorInfo>
+}
+
+/// Logon notification sent by the server
+/// through the Save Session Info PDU
+pub enum LogonEvent {
+    /// Logon info version 1
+    Info(LogonInfo),
+    /// Logon info version 2
+    /// Same as version 1 but allow longer domain and username
+    LongInfo(LogonInfo),
+    /// User is logged on but no information are sent
+    PlainNotify,
+    /// Extended informations like auto reconnect cookie
+    /// or logon errors
+    Extended(LogonExtendedInfo)
+}
+
+/// Desktop size changed
+/// sent by the server during a reactivation sequence
+#[derive(Copy, Clone, Debug)]
+pub struct ResizeEvent {
+    /// New desktop width
+    pub width: u16,
+    /// New desktop height
+    pub height: u16
+}
+
+/// Decoded rectangle sent through surface commands
+/// Pixels are already decoded, no need to decompress
+#[derive(Clone, Debug)]
+pub struct SurfaceEvent {
+    /// Pixel position from left of the left top angle
+    pub dest_left: u16,
+    /// Pixel position from top of the left top angle
+    pub dest_top: u16,
+    /// Width of the rectangle
+    pub width: u16,
+    /// Height of the rectangle
+    pub height: u16,
+    /// 32 bpp BGRA pixels, top-down
+    pub data: Vec<u8>
+}
+
+/// Frame marker action
+#[repr(u16)]
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive, Copy, Clone)]
+pub enum FrameAction {
+    /// Next surface events belong to the frame
+    Begin = 0,
+    /// The frame is complete and can be displayed
+    End = 1
+}
+
+/// Frame boundary sent through surface commands
+/// Surface events between Begin and End must be displayed at once
+#[derive(Clone, Debug)]
+pub struct FrameMarkerEvent {
+    pub action: FrameAction,
+    /// Same id for begin and end markers
+    pub frame_id: u32
+}
+
+/// Primary drawing order sent by the server
+/// Orders are only sent if advertised in the order capability
+/// MemBlt, glyph and cached brush orders use caches
+/// filled by previous CacheOrder events
+#[derive(Clone, Debug)]
+pub struct OrderEvent {
+    pub order: PrimaryOrder,
+    /// Clipping rectangle, None if the order is not clipped
+    pub bounds: Option<OrderBounds>
+}
+
+/// Server changed the palette
+/// used by 8 bpp bitmaps
+#[derive(Clone, Debug)]
+pub struct PaletteEvent {
+    /// Palette entries as 0x00RRGGBB
+    pub entries: Vec<u32>
+}
+
 /// All event handle by RDP protocol implemented by rdp-rs
 pub enum RdpEvent {
     /// Classic bitmap event
     Bitmap(BitmapEvent),
     /// Mouse event
     Pointer(PointerEvent),
+    /// Relative mouse event
+    RelativePointer(RelativePointerEvent),
     /// Keyboard event
-    Key(KeyboardEvent)
-}
\ No newline at end of file
+    Key(KeyboardEvent),
+    /// Unicode keyboard event
+    Unicode(UnicodeEvent),
+    /// Synchronize toggle keys state
+    Sync(SyncEvent),
+    /// Toggle keys state sent by the server
+    KeyboardIndicators(SyncEvent),
+    /// Session logon notification
+    Logon(LogonEvent),
+    /// Reason of the next disconnection
+    /// sent by the server
+    ErrorInfo(ErrorInfo),
+    /// Server refused to close the session
+    /// because a user is logged on
+    ShutdownDenied,
+    /// Desktop size changed
+    /// Screen buffers must be reallocated
+    Resize(ResizeEvent),
+    /// New palette for 8 bpp sessions
+    /// Next 8 bpp bitmap events are converted with it
+    Palette(PaletteEvent),
+    /// Decoded surface command (RemoteFX, NSCodec)
+    Surface(SurfaceEvent),
+    /// Begin or end of a frame of surface commands
+    FrameMarker(FrameMarkerEvent),
+    /// Primary drawing order (GDI order)
+    Order(OrderEvent),
+    /// Secondary drawing order
+    /// Store its bitmap, glyphs or brush for next primary orders
+    CacheOrder(SecondaryOrder)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Empty uncompressed bitmap produce no pixel
+    #[test]
+    fn test_uncompressed_bitmap_zero_height() {
+        let bitmap = BitmapEvent {
+            dest_left: 0, dest_top: 0, dest_right: 0, dest_bottom: 0,
+            width: 4, height: 0, bpp: 24, is_compress: false,
+            data: vec![]
+        };
+        assert_eq!(bitmap.decompress().unwrap(), Vec::<u8>::new());
+    }
+}
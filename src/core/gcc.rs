@@ -4,6 +4,7 @@ use model::error::{RdpResult, RdpError, RdpErrorKind, Error};
 use core::per;
 use std::io::{Cursor, Read};
 use std::collections::HashMap;
+use std::str::FromStr;
 
 
 const T124_02_98_OID: [u8; 6] = [ 0, 0, 20, 124, 0, 1 ];
@@ -50,7 +51,7 @@ enum Sequence {
 /// Keyboard layout
 /// https://docs.microsoft.com/en-us/previous-versions/windows/it-pro/windows-vista/cc766503(v=ws.10)?redirectedfrom=MSDN
 #[repr(u32)]
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum KeyboardLayout {
     Arabic = 0x00000401,
     Bulgarian = 0x00000402,
@@ -73,6 +74,45 @@ pub enum KeyboardLayout {
     Norwegian = 0x00000414
 }
 
+impl FromStr for KeyboardLayout {
+    type Err = Error;
+
+    /// Parse a layout from its language code or its name
+    ///
+    /// # Example
+    /// ```
+    /// use std::str::FromStr;
+    /// use rdp::core::gcc::KeyboardLayout;
+    /// assert_eq!(KeyboardLayout::from_str("de").unwrap(), KeyboardLayout::German);
+    /// assert_eq!(KeyboardLayout::from_str("Norwegian").unwrap(), KeyboardLayout::Norwegian);
+    /// assert!(KeyboardLayout::from_str("klingon").is_err());
+    /// ```
+    fn from_str(s: &str) -> RdpResult<Self> {
+        match s.to_lowercase().as_str() {
+            "ar" | "arabic" => Ok(KeyboardLayout::Arabic),
+            "bg" | "bulgarian" => Ok(KeyboardLayout::Bulgarian),
+            "zh" | "chinese" => Ok(KeyboardLayout::ChineseUsKeyboard),
+            "cs" | "czech" => Ok(KeyboardLayout::Czech),
+            "da" | "danish" => Ok(KeyboardLayout::Danish),
+            "de" | "german" => Ok(KeyboardLayout::German),
+            "el" | "greek" => Ok(KeyboardLayout::Greek),
+            "us" | "en" | "english" => Ok(KeyboardLayout::US),
+            "es" | "spanish" => Ok(KeyboardLayout::Spanish),
+            "fi" | "finnish" => Ok(KeyboardLayout::Finnish),
+            "fr" | "french" => Ok(KeyboardLayout::French),
+            "he" | "hebrew" => Ok(KeyboardLayout::Hebrew),
+            "hu" | "hungarian" => Ok(KeyboardLayout::Hungarian),
+            "is" | "icelandic" => Ok(KeyboardLayout::Icelandic),
+            "it" | "italian" => Ok(KeyboardLayout::Italian),
+            "ja" | "japanese" => Ok(KeyboardLayout::Japanese),
+            "ko" | "korean" => Ok(KeyboardLayout::Korean),
+            "nl" | "dutch" => Ok(KeyboardLayout::Dutch),
+            "no" | "norwegian" => Ok(KeyboardLayout::Norwegian),
+            _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, &format!("GCC: Unknown keyboard layout {}", s))))
+        }
+    }
+}
+
 /// Keyboard type
 /// Ibm101102Keys is the most common keyboard type
 #[repr(u32)]
@@ -190,7 +230,9 @@ pub struct ClientData {
     pub layout: KeyboardLayout,
     pub server_selected_protocol: u32,
     pub rdp_version: Version,
-    pub name: String
+    pub name: String,
+    /// Requested color depth in bits per pixel
+    pub color_depth: u16
 }
 
 /// This is the first client specific data
@@ -206,9 +248,23 @@ pub fn client_core_data(parameter: Option<ClientData>) -> Component {
             layout: KeyboardLayout::French,
             server_selected_protocol: 0,
             rdp_version: Version::RdpVersion5plus,
-            name: "".to_string()
+            name: "".to_string(),
+            color_depth: 24
         });
 
+    // 32 bpp session is requested through early capability flags
+    let (high_color_depth, supported_color_depth) = match client_parameter.color_depth {
+        8 => (HighColor::HighColor8BPP, 0),
+        15 => (HighColor::HighColor15BPP, Support::RnsUd15BPPSupport as u16),
+        16 => (HighColor::HighColor16BPP, Support::RnsUd16BPPSupport as u16),
+        _ => (HighColor::HighColor24BPP, Support::RnsUd24BPPSupport as u16)
+    };
+
+    let mut early_capability_flags = CapabilityFlag::RnsUdCsSupportErrinfoPDU as u16;
+    if client_parameter.color_depth == 32 {
+        early_capability_flags |= CapabilityFlag::RnsUdCsWant32BPPSession as u16;
+    }
+
     let client_name = if client_parameter.name.len() >= 16 {
         (&client_parameter.name[0..16]).to_string()
     } else {
@@ -231,14 +287,13 @@ pub fn client_core_data(parameter: Option<ClientData>) -> Component {
         "postBeta2ColorDepth" => U16::LE(ColorDepth::RnsUdColor8BPP as u16),
         "clientProductId" => U16::LE(1),
         "serialNumber" => U32::LE(0),
-        "highColorDepth" => U16::LE(HighColor::HighColor24BPP as u16),
+        "highColorDepth" => U16::LE(high_color_depth as u16),
         "supportedColorDepths" => U16::LE(
-            //Support::RnsUd15BPPSupport as u16 |
+            supported_color_depth |
             Support::RnsUd16BPPSupport as u16 |
-            //Support::RnsUd24BPPSupport as u16 |
             Support::RnsUd32BPPSupport as u16
             ),
-        "earlyCapabilityFlags" => U16::LE(CapabilityFlag::RnsUdCsSupportErrinfoPDU as u16),
+        "earlyCapabilityFlags" => U16::LE(early_capability_flags),
         "clientDigProductId" => vec![0; 64],
         "connectionType" => 0 as u8,
         "pad1octet" => 0 as u8,
@@ -325,7 +380,9 @@ pub fn write_conference_create_request(user_data: &[u8]) ->RdpResult<Vec<u8>> {
 
 pub struct ServerData {
     pub channel_ids: Vec<u16>,
-    pub rdp_version : Version
+    pub rdp_version : Version,
+    /// Version number as sent by the server
+    pub raw_rdp_version: u32
 }
 
 /// Read conference create response
@@ -376,8 +433,10 @@ pub fn read_conference_create_response(cc_response: &mut dyn Read) -> RdpResult<
     }
 
     // All section are important
+    let raw_rdp_version = cast!(DataType::U32, result[&MessageType::ScCore]["rdpVersion"])?;
     Ok(ServerData{
         channel_ids: cast!(DataType::Trame, result[&MessageType::ScNet]["channelIdArray"])?.into_iter().map(|x| cast!(DataType::U16, x).unwrap()).collect(),
-        rdp_version: Version::from(cast!(DataType::U32, result[&MessageType::ScCore]["rdpVersion"])?)
+        rdp_version: Version::from(raw_rdp_version),
+        raw_rdp_version
     })
 }
\ No newline at end of file
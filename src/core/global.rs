@@ -1,14 +1,22 @@
 use core::mcs;
 use core::tpkt;
 use std::io::{Read, Write, Cursor};
-use model::error::{RdpResult, Error, RdpErrorKind, RdpError};
+use model::error::{RdpResult, Error, RdpErrorKind, RdpError, ErrorInfo};
 use model::data::{Component, MessageOption, U32, DynOption, U16, DataType, Message, Array, Trame, Check, to_vec};
-use core::event::{RdpEvent, BitmapEvent};
+use core::event::{RdpEvent, BitmapEvent, ResizeEvent, PaletteEvent, SurfaceEvent, FrameMarkerEvent, FrameAction, Rectangle, SyncEvent, LogonEvent, LogonInfo, LogonExtendedInfo, AutoReconnectCookie, LogonErrorInfo};
 use num_enum::TryFromPrimitive;
 use std::convert::TryFrom;
-use core::capability::{Capability, capability_set};
+use core::capability::{Capability, CapabilitySet, BitmapCodec, GlyphSupportLevel, capability_set};
 use core::capability;
 use core::gcc::KeyboardLayout;
+use model::unicode::from_unicode;
+use std::cmp::min;
+use std::collections::HashMap;
+use codec::bulk::BulkDecompressor;
+use codec::rfx::RfxDecoder;
+use codec::nsc::NscDecoder;
+use codec::surface::{SurfaceDecoder, UncompressedDecoder, CODEC_ID_NONE};
+use core::order::OrderDecoder;
 
 
 /// Raw PDU type use by the protocol
@@ -120,7 +128,9 @@ fn share_data_header(share_id: Option<u32>, pdu_type_2: Option<PDUType2>, messag
             "shareId" => U32::LE(share_id.unwrap_or(0)),
             "pad1" => 0 as u8,
             "streamId" => 1 as u8,
-            "uncompressedLength" => DynOption::new(U16::LE(default_message.length() as u16 + 18), | size | MessageOption::Size("payload".to_string(), size.inner() as usize - 18)),
+            // payload is bounded by the share control header
+            // and may be compressed
+            "uncompressedLength" => U16::LE(default_message.length() as u16 + 18),
             "pduType2" => pdu_type_2.unwrap_or(PDUType2::Pdutype2ArcStatusPdu) as u8,
             "compressedType" => 0 as u8,
             "compressedLength" => U16::LE(0),
@@ -169,6 +179,7 @@ enum PDUType2 {
     Pdutype2ArcStatusPdu = 0x32,
     Pdutype2StatusInfoPdu = 0x36,
     Pdutype2MonitorLayoutPdu = 0x37,
+    Pdutype2FrameAcknowledge = 0x38,
     Unknown
 }
 
@@ -190,6 +201,10 @@ impl DataPDU {
             PDUType2::Pdutype2Fontlist => ts_font_list_pdu(),
             PDUType2::Pdutype2Fontmap => ts_font_map_pdu(),
             PDUType2::Pdutype2SetErrorInfoPdu => ts_set_error_info_pdu(),
+            PDUType2::Pdutype2SaveSessionInfo => ts_save_session_info_pdu(),
+            PDUType2::Pdutype2ShutdownDenied => ts_shutdown_denied_pdu(),
+            PDUType2::Pdutype2SetKeyboardIndicators => ts_set_keyboard_indicators_pdu(),
+            PDUType2::Pdutype2Update => ts_update_pdu(),
             _ => return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, &format!("GLOBAL: Data PDU parsing not implemented {:?}", pdu_type))))
         };
         result.message.read(&mut Cursor::new(cast!(DataType::Slice, data_pdu.message["payload"])?))?;
@@ -197,6 +212,92 @@ impl DataPDU {
     }
 }
 
+/// Slow path graphic update
+/// Only the update type is read
+/// update data is parsed by the handler
+///
+/// See MS-RDPBCGR 2.2.9.1.1.3 Slow-Path Graphics Update
+fn ts_update_pdu() -> DataPDU {
+    DataPDU {
+        pdu_type: PDUType2::Pdutype2Update,
+        message: component![
+            "updateType" => U16::LE(0),
+            "updateData" => Vec::<u8>::new()
+        ]
+    }
+}
+
+#[repr(u16)]
+#[derive(Debug, TryFromPrimitive, Copy, Clone, Eq, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+enum UpdateType {
+    UpdatetypeOrders = 0x0000,
+    UpdatetypeBitmap = 0x0001,
+    UpdatetypePalette = 0x0002,
+    UpdatetypeSynchronize = 0x0003
+}
+
+/// Slow path orders update
+/// Orders are read by the order decoder
+///
+/// See MS-RDPEGDI 2.2.2.1 Orders Update (TS_UPDATE_ORDERS_PDU_DATA)
+fn ts_update_orders() -> Component {
+    component![
+        "pad2OctetsA" => U16::LE(0),
+        "numberOrders" => U16::LE(0),
+        "pad2OctetsB" => U16::LE(0),
+        "orderData" => Vec::<u8>::new()
+    ]
+}
+
+/// Maximum number of entries in a palette
+const MAX_PALETTE_ENTRIES: u32 = 256;
+
+/// Palette update
+/// Used by fast path and slow path
+///
+/// See MS-RDPBCGR 2.2.9.1.1.3.1.1.1 Palette Update Data
+fn ts_update_palette_data() -> Component {
+    component![
+        "updateType" => Check::new(U16::LE(UpdateType::UpdatetypePalette as u16)),
+        "pad2Octets" => U16::LE(0),
+        "numberColors" => DynOption::new(U32::LE(0), |count| MessageOption::Size("paletteEntries".to_string(), count.inner() as usize * 3)),
+        "paletteEntries" => Array::new(ts_palette_entry)
+    ]
+}
+
+/// RGB palette entry
+fn ts_palette_entry() -> Component {
+    component![
+        "red" => 0_u8,
+        "green" => 0_u8,
+        "blue" => 0_u8
+    ]
+}
+
+/// Read palette entries as 0x00RRGGBB
+fn read_palette(data: &[u8]) -> RdpResult<Vec<u32>> {
+    let mut palette_data = ts_update_palette_data();
+    palette_data.read(&mut Cursor::new(data))?;
+
+    let number_colors = cast!(DataType::U32, palette_data["numberColors"])?;
+    let entries = cast!(DataType::Trame, palette_data["paletteEntries"])?;
+    if number_colors > MAX_PALETTE_ENTRIES || entries.len() != number_colors as usize {
+        return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "GLOBAL: Invalid number of palette entries")))
+    }
+
+    let mut palette = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let entry = cast!(DataType::Component, entry)?;
+        palette.push(
+            (cast!(DataType::U8, entry["red"])? as u32) << 16 |
+            (cast!(DataType::U8, entry["green"])? as u32) << 8 |
+            cast!(DataType::U8, entry["blue"])? as u32
+        );
+    }
+    Ok(palette)
+}
+
 /// Synchronize payload send by both side (client, server)
 ///
 /// https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-rdpbcgr/3fb4c95e-ad2d-43d1-a46f-5bd49418da49
@@ -237,6 +338,250 @@ fn ts_set_error_info_pdu() -> DataPDU {
     }
 }
 
+/// Shutdown request PDU
+/// Sent by the client to ask the server to close the session
+///
+/// See MS-RDPBCGR 2.2.2.2 Client Shutdown Request PDU
+fn ts_shutdown_request_pdu() -> DataPDU {
+    DataPDU {
+        pdu_type: PDUType2::Pdutype2ShutdownRequest,
+        message: component![]
+    }
+}
+
+/// Shutdown denied PDU
+/// Sent by the server when a user is logged on the session
+///
+/// See MS-RDPBCGR 2.2.2.3 Server Shutdown Request Denied PDU
+fn ts_shutdown_denied_pdu() -> DataPDU {
+    DataPDU {
+        pdu_type: PDUType2::Pdutype2ShutdownDenied,
+        message: component![]
+    }
+}
+
+/// Set keyboard indicators PDU
+/// Sent by the server to update toggle keys state
+///
+/// See MS-RDPBCGR 2.2.8.2.1.1 Set Keyboard Indicators PDU Data
+fn ts_set_keyboard_indicators_pdu() -> DataPDU {
+    DataPDU {
+        pdu_type: PDUType2::Pdutype2SetKeyboardIndicators,
+        message: component![
+            "unitId" => U16::LE(0),
+            "ledFlags" => U16::LE(0)
+        ]
+    }
+}
+
+/// Rectangle defined by its inclusive bounds
+fn ts_rectangle16(rectangle: Option<&Rectangle>) -> Component {
+    component![
+        "left" => U16::LE(rectangle.map_or(0, |r| r.left)),
+        "top" => U16::LE(rectangle.map_or(0, |r| r.top)),
+        "right" => U16::LE(rectangle.map_or(0, |r| r.right)),
+        "bottom" => U16::LE(rectangle.map_or(0, |r| r.bottom))
+    ]
+}
+
+/// Refresh rect PDU
+/// Ask the server to redraw some areas of the desktop
+///
+/// See MS-RDPBCGR 2.2.11.2.1 Refresh Rect PDU Data
+fn ts_refresh_rect_pdu(areas: &[Rectangle]) -> DataPDU {
+    DataPDU {
+        pdu_type: PDUType2::Pdutype2RefreshRect,
+        message: component![
+            "numberOfAreas" => areas.len() as u8,
+            "pad3Octets" => vec![0_u8; 3],
+            "areasToRefresh" => areas.iter().map(|area| Box::new(ts_rectangle16(Some(area))) as Box<dyn Message>).collect::<Trame>()
+        ]
+    }
+}
+
+/// Suppress output PDU
+/// Ask the server to stop or restart sending display updates
+/// Updates are allowed when the desktop rectangle is present
+///
+/// See MS-RDPBCGR 2.2.11.3.1 Suppress Output PDU Data
+fn ts_suppress_output_pdu(desktop_rect: Option<&Rectangle>) -> DataPDU {
+    DataPDU {
+        pdu_type: PDUType2::Pdutype2SuppressOutput,
+        message: component![
+            "allowDisplayUpdates" => desktop_rect.is_some() as u8,
+            "pad3Octets" => vec![0_u8; 3],
+            "desktopRect" => desktop_rect.map(|rect| ts_rectangle16(Some(rect)))
+        ]
+    }
+}
+
+/// Frame acknowledge PDU
+/// Sent once a frame ended by a frame marker is displayed
+///
+/// See MS-RDPRFX 2.2.3.1 TS_FRAME_ACKNOWLEDGE_PDU
+fn ts_frame_acknowledge_pdu(frame_id: u32) -> DataPDU {
+    DataPDU {
+        pdu_type: PDUType2::Pdutype2FrameAcknowledge,
+        message: component![
+            "frameID" => U32::LE(frame_id)
+        ]
+    }
+}
+
+/// Save session info PDU
+/// Sent by the server to notify the client about logon
+///
+/// See MS-RDPBCGR 2.2.10.1.1 Save Session Info PDU Data
+fn ts_save_session_info_pdu() -> DataPDU {
+    DataPDU {
+        pdu_type: PDUType2::Pdutype2SaveSessionInfo,
+        message: component![
+            "infoType" => U32::LE(0),
+            "infoData" => Vec::<u8>::new()
+        ]
+    }
+}
+
+/// Type of data in the save session info PDU
+#[repr(u32)]
+#[derive(Debug, TryFromPrimitive, Copy, Clone, Eq, PartialEq)]
+enum InfoType {
+    Logon = 0x00000000,
+    LogonLong = 0x00000001,
+    PlainNotify = 0x00000002,
+    ExtendedInfo = 0x00000003
+}
+
+/// Logon info version 1
+fn ts_logon_info() -> Component {
+    component![
+        "cbDomain" => U32::LE(0),
+        "domain" => vec![0; 52],
+        "cbUserName" => U32::LE(0),
+        "userName" => vec![0; 512],
+        "sessionId" => U32::LE(0)
+    ]
+}
+
+/// Logon info version 2
+fn ts_logon_info_version_2() -> Component {
+    component![
+        "version" => Check::new(U16::LE(0x0001)),
+        "size" => U32::LE(0),
+        "sessionId" => U32::LE(0),
+        "cbDomain" => DynOption::new(U32::LE(0), |length| MessageOption::Size("domain".to_string(), length.inner() as usize)),
+        "cbUserName" => DynOption::new(U32::LE(0), |length| MessageOption::Size("userName".to_string(), length.inner() as usize)),
+        "pad" => vec![0; 558],
+        "domain" => Vec::<u8>::new(),
+        "userName" => Vec::<u8>::new()
+    ]
+}
+
+/// Extended logon info
+/// Fields are read from logonFields according to fieldsPresent
+fn ts_logon_info_extended() -> Component {
+    component![
+        "length" => DynOption::new(U16::LE(0), |length| MessageOption::Size("logonFields".to_string(), (length.inner() as usize).saturating_sub(6))),
+        "fieldsPresent" => U32::LE(0),
+        "logonFields" => Vec::<u8>::new()
+    ]
+}
+
+#[repr(u32)]
+enum LogonExFlag {
+    AutoReconnectCookie = 0x00000001,
+    LogonErrors = 0x00000002
+}
+
+/// Field of the extended logon info
+fn ts_logon_info_field() -> Component {
+    component![
+        "cbFieldData" => DynOption::new(U32::LE(0), |length| MessageOption::Size("fieldData".to_string(), length.inner() as usize)),
+        "fieldData" => Vec::<u8>::new()
+    ]
+}
+
+/// Auto reconnect cookie
+fn arc_sc_private_packet() -> Component {
+    component![
+        "cbLen" => Check::new(U32::LE(0x0000001C)),
+        "version" => Check::new(U32::LE(0x00000001)),
+        "logonId" => U32::LE(0),
+        "arcRandomBits" => vec![0; 16]
+    ]
+}
+
+/// Logon errors info
+fn ts_logon_errors_info() -> Component {
+    component![
+        "errorNotificationType" => U32::LE(0),
+        "errorNotificationData" => U32::LE(0)
+    ]
+}
+
+/// Build a logon event from a save session info PDU
+fn read_logon_event(save_session_info: &DataPDU) -> RdpResult<LogonEvent> {
+    let mut stream = Cursor::new(cast!(DataType::Slice, save_session_info.message["infoData"])?);
+    match InfoType::try_from(cast!(DataType::U32, save_session_info.message["infoType"])?)? {
+        InfoType::Logon => {
+            let mut info = ts_logon_info();
+            info.read(&mut stream)?;
+            let domain = cast!(DataType::Slice, info["domain"])?;
+            let username = cast!(DataType::Slice, info["userName"])?;
+            Ok(LogonEvent::Info(LogonInfo {
+                session_id: cast!(DataType::U32, info["sessionId"])?,
+                domain: from_unicode(&domain[..min(cast!(DataType::U32, info["cbDomain"])? as usize, domain.len())]),
+                username: from_unicode(&username[..min(cast!(DataType::U32, info["cbUserName"])? as usize, username.len())])
+            }))
+        },
+        InfoType::LogonLong => {
+            let mut info = ts_logon_info_version_2();
+            info.read(&mut stream)?;
+            Ok(LogonEvent::LongInfo(LogonInfo {
+                session_id: cast!(DataType::U32, info["sessionId"])?,
+                domain: from_unicode(cast!(DataType::Slice, info["domain"])?),
+                username: from_unicode(cast!(DataType::Slice, info["userName"])?)
+            }))
+        },
+        InfoType::PlainNotify => Ok(LogonEvent::PlainNotify),
+        InfoType::ExtendedInfo => {
+            let mut info = ts_logon_info_extended();
+            info.read(&mut stream)?;
+            let fields_present = cast!(DataType::U32, info["fieldsPresent"])?;
+            let mut fields = Cursor::new(cast!(DataType::Slice, info["logonFields"])?);
+
+            let mut auto_reconnect = None;
+            if fields_present & LogonExFlag::AutoReconnectCookie as u32 != 0 {
+                let mut field = ts_logon_info_field();
+                field.read(&mut fields)?;
+                let mut cookie = arc_sc_private_packet();
+                cookie.read(&mut Cursor::new(cast!(DataType::Slice, field["fieldData"])?))?;
+                auto_reconnect = Some(AutoReconnectCookie {
+                    logon_id: cast!(DataType::U32, cookie["logonId"])?,
+                    random_bits: cast!(DataType::Slice, cookie["arcRandomBits"])?.to_vec()
+                });
+            }
+
+            let mut error = None;
+            if fields_present & LogonExFlag::LogonErrors as u32 != 0 {
+                let mut field = ts_logon_info_field();
+                field.read(&mut fields)?;
+                let mut errors_info = ts_logon_errors_info();
+                errors_info.read(&mut Cursor::new(cast!(DataType::Slice, field["fieldData"])?))?;
+                error = Some(LogonErrorInfo {
+                    error_type: cast!(DataType::U32, errors_info["errorNotificationType"])?,
+                    error_data: cast!(DataType::U32, errors_info["errorNotificationData"])?
+                });
+            }
+
+            Ok(LogonEvent::Extended(LogonExtendedInfo {
+                auto_reconnect,
+                error
+            }))
+        }
+    }
+}
+
 #[repr(u16)]
 #[allow(dead_code)]
 enum Action {
@@ -301,13 +646,15 @@ fn ts_input_event(message_type: Option<InputEventType>, data: Option<Vec<u8>>) -
 ///
 /// https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-rdpbcgr/a9a26b3d-84a2-495f-83fc-9edd6601f33b
 #[repr(u16)]
+#[derive(Copy, Clone)]
 pub enum InputEventType {
     InputEventSync = 0x0000,
     InputEventUnused = 0x0002,
     InputEventScancode = 0x0004,
     InputEventUnicode = 0x0005,
     InputEventMouse = 0x8001,
-    InputEventMousex = 0x8002
+    InputEventMousex = 0x8002,
+    InputEventMouserel = 0x8004
 }
 
 /// All Terminal Service Slow Path Input Event
@@ -316,6 +663,13 @@ pub struct TSInputEvent {
     message: Component
 }
 
+impl TSInputEvent {
+    /// Payload of the event without the header
+    pub fn get_message(&self) -> &Component {
+        &self.message
+    }
+}
+
 /// All supported flags for pointer event
 ///
 /// https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-rdpbcgr/2c1ced34-340a-46cd-be6e-fc8cab7c3b17
@@ -346,9 +700,131 @@ pub fn ts_pointer_event(flags: Option<u16>, x: Option<u16>, y: Option<u16>) -> T
     }
 }
 
+/// Unicode keyboard event
+/// Characters outside the BMP must be sent
+/// as two events, one for each surrogate
+///
+/// See MS-RDPBCGR 2.2.8.1.1.3.1.1.2 Unicode Keyboard Event
+pub fn ts_unicode_keyboard_event(flags: Option<u16>, unicode_code: Option<u16>) -> TSInputEvent {
+    TSInputEvent {
+        event_type: InputEventType::InputEventUnicode,
+        message: component![
+            "keyboardFlags" => U16::LE(flags.unwrap_or(0)),
+            "unicodeCode" => U16::LE(unicode_code.unwrap_or(0)),
+            "pad2Octets" => U16::LE(0)
+        ]
+    }
+}
+
+/// Toggle keys flags
+/// Used by synchronize event and keyboard indicators
+///
+/// See MS-RDPBCGR 2.2.8.1.1.3.1.1.5 Synchronize Event
+#[repr(u32)]
+#[derive(Copy, Clone)]
+pub enum SyncFlag {
+    ScrollLock = 0x0001,
+    NumLock = 0x0002,
+    CapsLock = 0x0004,
+    KanaLock = 0x0008
+}
+
+/// Convert toggle keys state into flags
+pub fn sync_flags(event: &SyncEvent) -> u32 {
+    let mut flags: u32 = 0;
+    if event.scroll_lock {
+        flags |= SyncFlag::ScrollLock as u32;
+    }
+    if event.num_lock {
+        flags |= SyncFlag::NumLock as u32;
+    }
+    if event.caps_lock {
+        flags |= SyncFlag::CapsLock as u32;
+    }
+    if event.kana_lock {
+        flags |= SyncFlag::KanaLock as u32;
+    }
+    flags
+}
+
+/// Convert flags into toggle keys state
+fn sync_event(flags: u32) -> SyncEvent {
+    SyncEvent {
+        scroll_lock: flags & SyncFlag::ScrollLock as u32 != 0,
+        num_lock: flags & SyncFlag::NumLock as u32 != 0,
+        caps_lock: flags & SyncFlag::CapsLock as u32 != 0,
+        kana_lock: flags & SyncFlag::KanaLock as u32 != 0
+    }
+}
+
+/// Synchronize event
+/// Inform the server about toggle keys state
+pub fn ts_sync_event(toggle_flags: Option<u32>) -> TSInputEvent {
+    TSInputEvent {
+        event_type: InputEventType::InputEventSync,
+        message: component![
+            "pad2Octets" => U16::LE(0),
+            "toggleFlags" => U32::LE(toggle_flags.unwrap_or(0))
+        ]
+    }
+}
+
+/// A relative pointer event
+/// Flags are either PointerFlag or PointerXFlag
+///
+/// See MS-RDPBCGR 2.2.8.1.1.3.1.1.7 Relative Mouse Event
+pub fn ts_pointer_rel_event(flags: Option<u16>, x_delta: Option<i16>, y_delta: Option<i16>) -> TSInputEvent {
+    TSInputEvent {
+        event_type: InputEventType::InputEventMouserel,
+        message : component![
+            "pointerFlags" => U16::LE(flags.unwrap_or(0)),
+            "xDelta" => U16::LE(x_delta.unwrap_or(0) as u16),
+            "yDelta" => U16::LE(y_delta.unwrap_or(0) as u16)
+        ]
+    }
+}
+
+/// Compute wheel rotation flags
+/// Rotation is encoded as a 9 bits two's complement
+/// where the sign bit is PtrflagsWheelNegative
+///
+/// # Example
+/// ```
+/// use rdp::core::global::wheel_rotation;
+/// assert_eq!(wheel_rotation(120), 0x0078);
+/// assert_eq!(wheel_rotation(-120), 0x0188);
+/// ```
+pub fn wheel_rotation(delta: i16) -> u16 {
+    (delta.max(-256).min(255) as u16) & PointerFlag::WheelRotationMask as u16
+}
+
+/// All supported flags for extended pointer event
+///
+/// See MS-RDPBCGR 2.2.8.1.1.3.1.1.4 Extended Mouse Event
+#[repr(u16)]
+pub enum PointerXFlag {
+    PtrxflagsDown = 0x8000,
+    PtrxflagsButton1 = 0x0001,
+    PtrxflagsButton2 = 0x0002
+}
+
+/// An extended pointer event
+/// Used to send X1 and X2 buttons
+pub fn ts_pointerx_event(flags: Option<u16>, x: Option<u16>, y: Option<u16>) -> TSInputEvent {
+    TSInputEvent {
+        event_type: InputEventType::InputEventMousex,
+        message : component![
+            "pointerFlags" => U16::LE(flags.unwrap_or(0)),
+            "xPos" => U16::LE(x.unwrap_or(0)),
+            "yPos" => U16::LE(y.unwrap_or(0))
+        ]
+    }
+}
+
 #[repr(u16)]
 pub enum KeyboardFlag {
     KbdflagsExtended = 0x0100,
+    KbdflagsExtended1 = 0x0200,
     KbdflagsDown = 0x4000,
     KbdflagsRelease = 0x8000
 }
@@ -366,13 +842,106 @@ pub fn ts_keyboard_event(flags: Option<u16>, key_code: Option<u16>) -> TSInputEv
     }
 }
 
+/// Fast path input event code
+///
+/// See MS-RDPBCGR 2.2.8.1.2.2 Fast-Path Input Event
+#[repr(u8)]
+#[derive(Copy, Clone)]
+enum FastPathInputEvent {
+    Scancode = 0x0,
+    Mouse = 0x1,
+    Mousex = 0x2,
+    Sync = 0x3,
+    Unicode = 0x4,
+    Relpointer = 0x5
+}
+
+/// Fast path keyboard flags
+#[repr(u8)]
+enum FastPathKeyboardFlag {
+    Release = 0x01,
+    Extended = 0x02,
+    Extended1 = 0x04
+}
+
+/// Convert a slow path input event
+/// into its fast path counterpart
+fn ts_fp_input_event(event: &TSInputEvent) -> RdpResult<Component> {
+    let (code, flags, data) = match event.event_type {
+        InputEventType::InputEventScancode | InputEventType::InputEventUnicode => {
+            let keyboard_flags = cast!(DataType::U16, event.message["keyboardFlags"])?;
+            let mut flags = 0;
+            if keyboard_flags & KeyboardFlag::KbdflagsRelease as u16 != 0 {
+                flags |= FastPathKeyboardFlag::Release as u8;
+            }
+            if keyboard_flags & KeyboardFlag::KbdflagsExtended as u16 != 0 {
+                flags |= FastPathKeyboardFlag::Extended as u8;
+            }
+            if keyboard_flags & KeyboardFlag::KbdflagsExtended1 as u16 != 0 {
+                flags |= FastPathKeyboardFlag::Extended1 as u8;
+            }
+            if let InputEventType::InputEventScancode = event.event_type {
+                (FastPathInputEvent::Scancode, flags, trame![cast!(DataType::U16, event.message["keyCode"])? as u8])
+            } else {
+                (FastPathInputEvent::Unicode, flags & FastPathKeyboardFlag::Release as u8, trame![U16::LE(cast!(DataType::U16, event.message["unicodeCode"])?)])
+            }
+        },
+        InputEventType::InputEventMouse | InputEventType::InputEventMousex => (
+            if let InputEventType::InputEventMouse = event.event_type { FastPathInputEvent::Mouse } else { FastPathInputEvent::Mousex },
+            0,
+            trame![
+                U16::LE(cast!(DataType::U16, event.message["pointerFlags"])?),
+                U16::LE(cast!(DataType::U16, event.message["xPos"])?),
+                U16::LE(cast!(DataType::U16, event.message["yPos"])?)
+            ]
+        ),
+        InputEventType::InputEventMouserel => (
+            FastPathInputEvent::Relpointer,
+            0,
+            trame![
+                U16::LE(cast!(DataType::U16, event.message["pointerFlags"])?),
+                U16::LE(cast!(DataType::U16, event.message["xDelta"])?),
+                U16::LE(cast!(DataType::U16, event.message["yDelta"])?)
+            ]
+        ),
+        // toggle flags are directly encoded in the event header
+        InputEventType::InputEventSync => (FastPathInputEvent::Sync, cast!(DataType::U32, event.message["toggleFlags"])? as u8 & 0x1F, trame![]),
+        InputEventType::InputEventUnused => return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "GLOBAL: This input event has no fast path counterpart")))
+    };
+
+    Ok(component![
+        "eventHeader" => flags | ((code as u8) << 5),
+        "eventData" => data
+    ])
+}
+
+/// Fragmentation of a fast path update
+/// stored in bits 4-5 of the update header
+///
+/// See MS-RDPBCGR 2.2.9.1.2.1 Fast-Path Update
+#[repr(u8)]
+#[derive(Debug, TryFromPrimitive, Copy, Clone, Eq, PartialEq)]
+enum FastPathFragmentation {
+    Single = 0x0,
+    Last = 0x1,
+    First = 0x2,
+    Next = 0x3
+}
+
+/// Compression of a fast path update
+/// stored in bits 6-7 of the update header
+#[repr(u8)]
+enum FastPathOutputCompression {
+    Used = 0x2
+}
+
 /// Fast Path update (Not a PDU)
 ///
 /// https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-rdpbcgr/a1c4caa8-00ed-45bb-a06e-5177473766d3
 fn ts_fp_update() -> Component {
     component![
         "updateHeader" => DynOption::new(0 as u8, |header| {
-            if (header >> 4) & 0x2 as u8 == 0 as u8 {
+            if (header >> 6) & FastPathOutputCompression::Used as u8 == 0 {
                 MessageOption::SkipField("compressionFlags".to_string())
             }
             else {
@@ -410,16 +979,20 @@ struct FastPathUpdate{
 
 impl FastPathUpdate {
     /// Parse Fast Path update order
-    fn from_fp(fast_path: &Component) -> RdpResult<FastPathUpdate> {
-        let fp_update_type = FastPathUpdateType::try_from(cast!(DataType::U8, fast_path["updateHeader"])? & 0xf)?;
+    /// from an update code and a reassembled payload
+    fn from_fp(update_code: u8, data: &[u8]) -> RdpResult<FastPathUpdate> {
+        let fp_update_type = FastPathUpdateType::try_from(update_code)?;
         let mut result = match fp_update_type {
             FastPathUpdateType::FastpathUpdatetypeBitmap => ts_fp_update_bitmap(),
             FastPathUpdateType::FastpathUpdatetypeColor => ts_colorpointerattribute(),
             FastPathUpdateType::FastpathUpdatetypeSynchronize => ts_fp_update_synchronize(),
             FastPathUpdateType::FastpathUpdatetypePtrNull => ts_fp_systempointerhiddenattribute(),
+            FastPathUpdateType::FastpathUpdatetypePalette => ts_fp_update_palette(),
+            FastPathUpdateType::FastpathUpdatetypeSurfcmds => ts_fp_surfcmds(),
+            FastPathUpdateType::FastpathUpdatetypeOrders => ts_fp_update_orders(),
             _ => return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, &format!("GLOBAL: Fast Path parsing not implemented {:?}", fp_update_type))))
         };
-        result.message.read(&mut Cursor::new(cast!(DataType::Slice, fast_path["updateData"])?))?;
+        result.message.read(&mut Cursor::new(data))?;
         Ok(result)
     }
 }
@@ -474,6 +1047,111 @@ fn ts_fp_update_bitmap() -> FastPathUpdate {
     }
 }
 
+/// Fast Path palette update
+fn ts_fp_update_palette() -> FastPathUpdate {
+    FastPathUpdate {
+        fp_type: FastPathUpdateType::FastpathUpdatetypePalette,
+        message: component![
+            "paletteUpdateData" => Vec::<u8>::new()
+        ]
+    }
+}
+
+/// Fast Path orders update
+///
+/// See MS-RDPEGDI 2.2.2.2 Fast-Path Orders Update (TS_FP_UPDATE_ORDERS)
+fn ts_fp_update_orders() -> FastPathUpdate {
+    FastPathUpdate {
+        fp_type: FastPathUpdateType::FastpathUpdatetypeOrders,
+        message: component![
+            "numberOrders" => U16::LE(0),
+            "orderData" => Vec::<u8>::new()
+        ]
+    }
+}
+
+/// Fast Path surface commands update
+fn ts_fp_surfcmds() -> FastPathUpdate {
+    FastPathUpdate {
+        fp_type: FastPathUpdateType::FastpathUpdatetypeSurfcmds,
+        message: component![
+            "surfaceCommands" => Vec::<u8>::new()
+        ]
+    }
+}
+
+#[repr(u16)]
+#[derive(Debug, TryFromPrimitive, Copy, Clone, Eq, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+enum SurfaceCommandType {
+    CmdtypeSetSurfaceBits = 0x0001,
+    CmdtypeFrameMarker = 0x0004,
+    CmdtypeStreamSurfaceBits = 0x0006
+}
+
+#[repr(u8)]
+enum BitmapDataExFlag {
+    ExCompressedBitmapHeaderPresent = 0x01
+}
+
+/// Extended bitmap data
+///
+/// See MS-RDPBCGR 2.2.9.2.1.1 TS_BITMAP_DATA_EX
+fn ts_bitmap_data_ex() -> Component {
+    component![
+        "bpp" => 0_u8,
+        "flags" => DynOption::new(0_u8, |flags| {
+            if flags & BitmapDataExFlag::ExCompressedBitmapHeaderPresent as u8 == 0 {
+                MessageOption::SkipField("exBitmapDataHeader".to_string())
+            }
+            else {
+                MessageOption::None
+            }
+        }),
+        "reserved" => 0_u8,
+        "codecID" => 0_u8,
+        "width" => U16::LE(0),
+        "height" => U16::LE(0),
+        "bitmapDataLength" => DynOption::new(U32::LE(0), |length| MessageOption::Size("bitmapData".to_string(), length.inner() as usize)),
+        "exBitmapDataHeader" => vec![0_u8; 24],
+        "bitmapData" => Vec::<u8>::new()
+    ]
+}
+
+/// Set surface bits and stream surface bits command
+///
+/// See MS-RDPBCGR 2.2.9.2.1 Set Surface Bits Command
+fn ts_surfcmd_surf_bits() -> Component {
+    component![
+        "destLeft" => U16::LE(0),
+        "destTop" => U16::LE(0),
+        "destRight" => U16::LE(0),
+        "destBottom" => U16::LE(0),
+        "bitmapData" => ts_bitmap_data_ex()
+    ]
+}
+
+/// Frame marker command
+///
+/// See MS-RDPBCGR 2.2.9.2.3 Frame Marker Command
+fn ts_frame_marker() -> Component {
+    component![
+        "frameAction" => U16::LE(0),
+        "frameId" => U32::LE(0)
+    ]
+}
+
+/// Built-in surface bits decoders
+/// Keyed by the codec id advertised in the bitmap codecs capability
+/// Uncompressed surface bits are always allowed with surface commands
+fn default_surface_decoders() -> HashMap<u8, Box<dyn SurfaceDecoder>> {
+    let mut decoders: HashMap<u8, Box<dyn SurfaceDecoder>> = HashMap::new();
+    decoders.insert(CODEC_ID_NONE, Box::new(UncompressedDecoder));
+    decoders.insert(BitmapCodec::CodecNscodec as u8, Box::new(NscDecoder));
+    decoders.insert(BitmapCodec::CodecRemotefx as u8, Box::new(RfxDecoder::new()));
+    decoders
+}
+
 /// A new pointer for mouse
 ///
 /// https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-rdpbcgr/71fad4fc-6ad4-4c7f-8103-a442bebaf7d2
@@ -550,7 +1228,25 @@ pub struct Client {
     /// Keep tracing of server capabilities
     server_capabilities: Vec<Capability>,
     /// Name send to the server
-    name: String
+    name: String,
+    /// Last error info sent by the server
+    error_info: Option<ErrorInfo>,
+    /// Capability sets sent to the server
+    capabilities: CapabilitySet,
+    /// Pending fast path fragments by update code
+    fragments: HashMap<u8, Vec<u8>>,
+    /// Bulk decompression context
+    /// shared by fast path and slow path
+    decompressor: BulkDecompressor,
+    /// Last palette sent by the server
+    /// for 8 bpp sessions
+    palette: Option<Vec<u32>>,
+    /// Surface bits decoders by codec id
+    surface_decoders: HashMap<u8, Box<dyn SurfaceDecoder>>,
+    /// Ended frames waiting for a frame acknowledge PDU
+    frame_acknowledges: Vec<u32>,
+    /// Drawing orders state
+    orders: OrderDecoder
 }
 
 impl Client {
@@ -562,16 +1258,19 @@ impl Client {
     /// ```rust, ignore
     /// use rdp::core::global;
     /// use rdp::core::gcc::KeyboardLayout;
+    /// use rdp::core::capability::CapabilitySet;
     /// let mut global_channel = global::Client::new(
     ///     mcs.get_user_id(),
     ///     mcs.get_global_channel_id(),
     ///     800,
     ///     600,
     ///     KeyboardLayout::US,
-    ///     "mstsc-rs"
+    ///     "mstsc-rs",
+    ///     CapabilitySet::new()
     /// );
     /// ```
-    pub fn new(user_id: u16, channel_id: u16, width: u16, height: u16, layout: KeyboardLayout, name: &str) -> Client {
+    pub fn new(user_id: u16, channel_id: u16, width: u16, height: u16, layout: KeyboardLayout, name: &str, capabilities: CapabilitySet) -> Client {
+        let orders = OrderDecoder::new().glyph_v2(capabilities.get_glyph_support() == GlyphSupportLevel::GlyphSupportEncode);
         Client {
             state: ClientState::DemandActivePDU,
             server_capabilities: Vec::new(),
@@ -581,7 +1280,15 @@ impl Client {
             width,
             height,
             layout,
-            name: String::from(name)
+            name: String::from(name),
+            error_info: None,
+            capabilities,
+            fragments: HashMap::new(),
+            decompressor: BulkDecompressor::new(),
+            palette: None,
+            surface_decoders: default_surface_decoders(),
+            frame_acknowledges: Vec::new(),
+            orders
         }
     }
 
@@ -589,16 +1296,26 @@ impl Client {
     /// This message is sent from server to client
     /// and inform about server capabilities
     ///
+    /// The desktop size is taken from the server bitmap capability
+    /// It can change during a deactivation reactivation sequence
+    ///
     /// This function return true if it read the expected PDU
     fn read_demand_active_pdu(&mut self, stream: &mut dyn Read) -> RdpResult<bool> {
         let pdu = PDU::from_stream(stream)?;
         if pdu.pdu_type == PDUType::PdutypeDemandactivepdu {
+            // Capabilities of a previous activation are obsolete
+            self.server_capabilities.clear();
             for capability_set in cast!(DataType::Trame, pdu.message["capabilitySets"])?.iter() {
                 match Capability::from_capability_set(cast!(DataType::Component, capability_set)?) {
                     Ok(capability) => self.server_capabilities.push(capability),
                     Err(e) => println!("GLOBAL: {:?}", e)
                 }
             }
+            if let Some(bitmap) = self.get_server_capability(capability::CapabilitySetType::CapstypeBitmap) {
+                let bitmap = capability::BitmapCapability::from_capability(bitmap)?;
+                self.width = bitmap.desktop_width;
+                self.height = bitmap.desktop_height;
+            }
             self.share_id = Some(cast!(DataType::U32, pdu.message["shareId"])?);
             return Ok(true)
         }
@@ -658,18 +1375,21 @@ impl Client {
     /// Expect data PDU
     /// This is the old school PDU for bitmap
     /// transfer. Now all version use Fast Path transfer PDU
-    fn read_data_pdu(&mut self, stream: &mut dyn Read) -> RdpResult<()> {
+    fn read_data_pdu<T>(&mut self, stream: &mut dyn Read, mut callback: T) -> RdpResult<()>
+    where T: FnMut(RdpEvent) {
         //let pdu = PDU::from_stream(stream)?;
         let mut message = Array::new(|| share_control_header(None, None, None));
         message.read(stream)?;
 
         for pdu in message.inner() {
-            let pdu = PDU::from_control(cast!(DataType::Component, pdu)?)?;
+            let mut pdu = PDU::from_control(cast!(DataType::Component, pdu)?)?;
 
             // Ask for a new handshake
             if pdu.pdu_type == PDUType::PdutypeDeactivateallpdu {
                 println!("GLOBAL: deactive/reactive sequence initiated");
                 self.state = ClientState::DemandActivePDU;
+                self.fragments.clear();
+                self.orders.reset();
                 continue;
             }
             if pdu.pdu_type != PDUType::PdutypeDatapdu {
@@ -677,10 +1397,48 @@ impl Client {
                 continue;
             }
 
+            let compressed_type = cast!(DataType::U8, pdu.message["compressedType"])?;
+            if compressed_type != 0 {
+                // uncompressed length include the 18 bytes of headers
+                let uncompressed_length = (cast!(DataType::U16, pdu.message["uncompressedLength"])? as usize).saturating_sub(18);
+                match self.decompressor.decompress(cast!(DataType::Slice, pdu.message["payload"])?, compressed_type, uncompressed_length) {
+                    Ok(payload) => { pdu.message.insert("payload".to_string(), Box::new(payload)); },
+                    Err(e) => {
+                        println!("GLOBAL: Drop compressed Data PDU {:?}", e);
+                        continue;
+                    }
+                }
+            }
+
             match DataPDU::from_pdu(&pdu) {
                 Ok(data_pdu) => {
                     match data_pdu.pdu_type {
-                        PDUType2::Pdutype2SetErrorInfoPdu => println!("GLOBAL: Receive error PDU from server {:?}", cast!(DataType::U32, data_pdu.message["errorInfo"])?),
+                        PDUType2::Pdutype2SetErrorInfoPdu => {
+                            let code = cast!(DataType::U32, data_pdu.message["errorInfo"])?;
+                            let error_info = ErrorInfo::from_code(code);
+                            if let ErrorInfo::Unknown(code) = error_info {
+                                println!("GLOBAL: Unknown error info code {:?}", code);
+                            }
+                            // None is sent to reset the last error
+                            self.error_info = if error_info == ErrorInfo::None { None } else { Some(error_info) };
+                            if let Some(error_info) = self.error_info {
+                                callback(RdpEvent::ErrorInfo(error_info));
+                            }
+                        },
+                        PDUType2::Pdutype2SaveSessionInfo => callback(RdpEvent::Logon(read_logon_event(&data_pdu)?)),
+                        PDUType2::Pdutype2ShutdownDenied => callback(RdpEvent::ShutdownDenied),
+                        PDUType2::Pdutype2SetKeyboardIndicators => callback(RdpEvent::KeyboardIndicators(sync_event(cast!(DataType::U16, data_pdu.message["ledFlags"])? as u32))),
+                        PDUType2::Pdutype2Update => {
+                            match UpdateType::try_from(cast!(DataType::U16, data_pdu.message["updateType"])?) {
+                                Ok(UpdateType::UpdatetypePalette) => self.update_palette(cast!(DataType::Slice, pdu.message["payload"])?, &mut callback)?,
+                                Ok(UpdateType::UpdatetypeOrders) => {
+                                    let mut orders = ts_update_orders();
+                                    orders.read(&mut Cursor::new(cast!(DataType::Slice, data_pdu.message["updateData"])?))?;
+                                    self.read_orders(cast!(DataType::U16, orders["numberOrders"])?, cast!(DataType::Slice, orders["orderData"])?, &mut callback)?;
+                                },
+                                update_type => println!("GLOBAL: Slow path update not handled {:?}", update_type)
+                            }
+                        },
                         _ => println!("GLOBAL: Data PDU not handle {:?}", data_pdu.pdu_type)
                     }
                 },
@@ -690,6 +1448,188 @@ impl Client {
         Ok(())
     }
 
+    /// Maximum size of a reassembled fast path update
+    /// The value advertised by the client is what it accepts to buffer
+    /// so it always wins, then the server one, then a full 32 bpp screen
+    fn get_multifragment_max_size(&self) -> usize {
+        let server_max_size = match self.get_server_capability(capability::CapabilitySetType::CapsettypeMultifragmentupdate) {
+            Some(multifragment) => cast!(DataType::U32, multifragment.message["MaxRequestSize"]).unwrap_or(0),
+            None => 0
+        };
+
+        match (self.capabilities.get_multifragment_max_size(), server_max_size) {
+            (0, 0) => self.width as usize * self.height as usize * 4,
+            (0, server_max_size) => server_max_size as usize,
+            (client_max_size, _) => client_max_size as usize
+        }
+    }
+
+    /// Handle fragmentation and compression of a fast path update
+    /// Each update type has its own fragment buffer
+    ///
+    /// Return the update code and the whole update payload
+    /// or None if more fragments are expected
+    fn reassemble_fast_path(&mut self, fp_update: &Component) -> RdpResult<Option<(u8, Vec<u8>)>> {
+        let header = cast!(DataType::U8, fp_update["updateHeader"])?;
+        let update_code = header & 0xf;
+        let fragmentation = FastPathFragmentation::try_from((header >> 4) & 0x3)?;
+
+        let max_size = self.get_multifragment_max_size();
+        let data = cast!(DataType::Slice, fp_update["updateData"])?;
+        let data = if (header >> 6) & FastPathOutputCompression::Used as u8 != 0 {
+            match self.decompressor.decompress(data, cast!(DataType::U8, fp_update["compressionFlags"])?, max_size) {
+                Ok(data) => data,
+                Err(e) => {
+                    self.fragments.remove(&update_code);
+                    return Err(e)
+                }
+            }
+        } else {
+            data.to_vec()
+        };
+        let data = data.as_slice();
+
+        match fragmentation {
+            FastPathFragmentation::Single => {
+                if self.fragments.remove(&update_code).is_some() {
+                    println!("GLOBAL: Drop incomplete fast path fragments {:?}", update_code);
+                }
+                Ok(Some((update_code, data.to_vec())))
+            },
+            FastPathFragmentation::First => {
+                if data.len() > max_size {
+                    self.fragments.remove(&update_code);
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "GLOBAL: Fast path update exceed max request size")))
+                }
+                self.fragments.insert(update_code, data.to_vec());
+                Ok(None)
+            },
+            FastPathFragmentation::Next | FastPathFragmentation::Last => {
+                let buffer = self.fragments.get_mut(&update_code).ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "GLOBAL: Fast path fragment received without first fragment")))?;
+                if buffer.len() + data.len() > max_size {
+                    self.fragments.remove(&update_code);
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "GLOBAL: Fast path update exceed max request size")))
+                }
+                buffer.extend_from_slice(data);
+
+                if fragmentation == FastPathFragmentation::Last {
+                    Ok(self.fragments.remove(&update_code).map(|buffer| (update_code, buffer)))
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    /// Keep the new palette
+    /// and notify the caller
+    fn update_palette<T>(&mut self, data: &[u8], callback: &mut T) -> RdpResult<()>
+    where T: FnMut(RdpEvent) {
+        let palette = read_palette(data)?;
+        self.palette = Some(palette.clone());
+        callback(RdpEvent::Palette(PaletteEvent { entries: palette }));
+        Ok(())
+    }
+
+    /// 8 bpp bitmaps are converted into 32 bpp
+    /// using the last palette, so caller can decompress them directly
+    /// Bitmaps received before any palette are left untouched
+    fn apply_palette(&self, bitmap: BitmapEvent) -> RdpResult<BitmapEvent> {
+        match &self.palette {
+            Some(palette) if bitmap.bpp == 8 => Ok(BitmapEvent {
+                dest_left: bitmap.dest_left,
+                dest_top: bitmap.dest_top,
+                dest_right: bitmap.dest_right,
+                dest_bottom: bitmap.dest_bottom,
+                width: bitmap.width,
+                height: bitmap.height,
+                bpp: 32,
+                is_compress: false,
+                data: bitmap.decompress_with_palette(palette)?
+            }),
+            _ => Ok(bitmap)
+        }
+    }
+
+    /// Read all surface commands of an update
+    fn read_surface_commands<T>(&mut self, data: &[u8], callback: &mut T) -> RdpResult<()>
+    where T: FnMut(RdpEvent) {
+        let mut stream = Cursor::new(data);
+        while (stream.position() as usize) < data.len() {
+            let mut cmd_type = U16::LE(0);
+            cmd_type.read(&mut stream)?;
+            match SurfaceCommandType::try_from(cmd_type.inner())? {
+                SurfaceCommandType::CmdtypeSetSurfaceBits | SurfaceCommandType::CmdtypeStreamSurfaceBits => {
+                    let mut surface_bits = ts_surfcmd_surf_bits();
+                    surface_bits.read(&mut stream)?;
+                    self.read_surface_bits(&surface_bits, callback)?;
+                },
+                SurfaceCommandType::CmdtypeFrameMarker => {
+                    let mut frame_marker = ts_frame_marker();
+                    frame_marker.read(&mut stream)?;
+                    let event = FrameMarkerEvent {
+                        action: FrameAction::try_from(cast!(DataType::U16, frame_marker["frameAction"])?)?,
+                        frame_id: cast!(DataType::U32, frame_marker["frameId"])?
+                    };
+                    if event.action == FrameAction::End && self.is_frame_acknowledge_supported() {
+                        self.frame_acknowledges.push(event.frame_id);
+                    }
+                    callback(RdpEvent::FrameMarker(event));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode surface bits using the decoder registered for its codec id
+    fn read_surface_bits<T>(&mut self, surface_bits: &Component, callback: &mut T) -> RdpResult<()>
+    where T: FnMut(RdpEvent) {
+        let dest_left = cast!(DataType::U16, surface_bits["destLeft"])?;
+        let dest_top = cast!(DataType::U16, surface_bits["destTop"])?;
+        let bitmap_data = cast!(DataType::Component, surface_bits["bitmapData"])?;
+        let codec_id = cast!(DataType::U8, bitmap_data["codecID"])?;
+
+        let decoder = match self.surface_decoders.get_mut(&codec_id) {
+            Some(decoder) => decoder,
+            None => {
+                println!("GLOBAL: Surface bits codec not handled {:?}", codec_id);
+                return Ok(())
+            }
+        };
+
+        let rects = decoder.decode(cast!(DataType::U16, bitmap_data["width"])?, cast!(DataType::U16, bitmap_data["height"])?, cast!(DataType::Slice, bitmap_data["bitmapData"])?)?;
+
+        for rect in rects {
+            callback(RdpEvent::Surface(SurfaceEvent {
+                dest_left: dest_left.saturating_add(rect.left),
+                dest_top: dest_top.saturating_add(rect.top),
+                width: rect.width,
+                height: rect.height,
+                data: rect.data
+            }));
+        }
+        Ok(())
+    }
+
+    /// Decode drawing orders of an update
+    /// If an order can't be decoded the decoder state is reset
+    /// because next delta encoded orders would use stale fields
+    fn read_orders<T>(&mut self, number_orders: u16, data: &[u8], callback: &mut T) -> RdpResult<()>
+    where T: FnMut(RdpEvent) {
+        match self.orders.decode(number_orders, data) {
+            Ok(orders) => {
+                for order in orders {
+                    callback(order);
+                }
+                Ok(())
+            },
+            Err(e) => {
+                self.orders.reset();
+                Err(e)
+            }
+        }
+    }
+
     /// Read fast path input data
     /// Reading is processed using a callback patterm
     /// This is where bitmap are received
@@ -700,13 +1640,23 @@ impl Client {
         fp_messages.read(stream)?;
 
         for fp_message in fp_messages.inner().iter() {
-            match FastPathUpdate::from_fp(cast!(DataType::Component, fp_message)?) {
+            let (update_code, data) = match self.reassemble_fast_path(cast!(DataType::Component, fp_message)?) {
+                Ok(Some(update)) => update,
+                // wait for next fragments
+                Ok(None) => continue,
+                Err(e) => {
+                    println!("GLOBAL: Drop Fast Path update {:?}", e);
+                    continue;
+                }
+            };
+
+            match FastPathUpdate::from_fp(update_code, &data) {
                 Ok(order) => {
                     match order.fp_type {
                         FastPathUpdateType::FastpathUpdatetypeBitmap => {
                             for rectangle in cast!(DataType::Trame, order.message["rectangles"])? {
                                 let bitmap = cast!(DataType::Component, rectangle)?;
-                                callback(RdpEvent::Bitmap(
+                                callback(RdpEvent::Bitmap(self.apply_palette(
                                     BitmapEvent {
                                         dest_left: cast!(DataType::U16, bitmap["destLeft"])?,
                                         dest_top: cast!(DataType::U16, bitmap["destTop"])?,
@@ -718,9 +1668,12 @@ impl Client {
                                         is_compress: cast!(DataType::U16, bitmap["flags"])? & BitmapFlag::BitmapCompression as u16 != 0,
                                         data: cast!(DataType::Slice, bitmap["bitmapDataStream"])?.to_vec()
                                     }
-                                ));
+                                )?));
                             }
                         },
+                        FastPathUpdateType::FastpathUpdatetypePalette => self.update_palette(cast!(DataType::Slice, order.message["paletteUpdateData"])?, &mut callback)?,
+                        FastPathUpdateType::FastpathUpdatetypeSurfcmds => self.read_surface_commands(cast!(DataType::Slice, order.message["surfaceCommands"])?, &mut callback)?,
+                        FastPathUpdateType::FastpathUpdatetypeOrders => self.read_orders(cast!(DataType::U16, order.message["numberOrders"])?, cast!(DataType::Slice, order.message["orderData"])?, &mut callback)?,
                         // do nothing
                         FastPathUpdateType::FastpathUpdatetypeColor | FastPathUpdateType::FastpathUpdatetypePtrNull | FastPathUpdateType::FastpathUpdatetypeSynchronize => (),
                         _ => println!("GLOBAL: Fast Path order not handled {:?}", order.fp_type)
@@ -736,22 +1689,11 @@ impl Client {
     /// Write confirm active pdu
     /// This PDU include all client capabilities
     fn write_confirm_active_pdu<S: Read + Write>(&mut self, mcs: &mut mcs::Client<S>) -> RdpResult<()> {
-        let pdu = ts_confirm_active_pdu(self.share_id, Some(self.name.as_bytes().to_vec()), Some(Array::from_trame(
-            trame![
-                capability_set(Some(capability::ts_general_capability_set(Some(capability::GeneralExtraFlag::LongCredentialsSupported as u16 | capability::GeneralExtraFlag::NoBitmapCompressionHdr as u16 | capability::GeneralExtraFlag::EncSaltedChecksum as u16 | capability::GeneralExtraFlag::FastpathOutputSupported as u16)))),
-                capability_set(Some(capability::ts_bitmap_capability_set(Some(0x0018), Some(self.width), Some(self.height)))),
-                capability_set(Some(capability::ts_order_capability_set(Some(capability::OrderFlag::NEGOTIATEORDERSUPPORT as u16 | capability::OrderFlag::ZEROBOUNDSDELTASSUPPORT as u16)))),
-                capability_set(Some(capability::ts_bitmap_cache_capability_set())),
-                capability_set(Some(capability::ts_pointer_capability_set())),
-                capability_set(Some(capability::ts_sound_capability_set())),
-                capability_set(Some(capability::ts_input_capability_set(Some(capability::InputFlags::InputFlagScancodes as u16 | capability::InputFlags::InputFlagMousex as u16 | capability::InputFlags::InputFlagUnicode as u16), Some(self.layout)))),
-                capability_set(Some(capability::ts_brush_capability_set())),
-                capability_set(Some(capability::ts_glyph_capability_set())),
-                capability_set(Some(capability::ts_offscreen_capability_set())),
-                capability_set(Some(capability::ts_virtualchannel_capability_set())),
-                capability_set(Some(capability::ts_multifragment_update_capability_ts()))
-            ]
-        )));
+        let capability_sets = self.capabilities.build(self.width, self.height, self.layout)?
+            .into_iter()
+            .map(|capability| Box::new(capability_set(Some(capability))) as Box<dyn Message>)
+            .collect::<Trame>();
+        let pdu = ts_confirm_active_pdu(self.share_id, Some(self.name.as_bytes().to_vec()), Some(Array::from_trame(capability_sets)));
         self.write_pdu(pdu, mcs)
     }
 
@@ -774,6 +1716,126 @@ impl Client {
         self.write_pdu(share_data_header(self.share_id, Some(message.pdu_type), Some(to_vec(&message.message))), mcs)
     }
 
+    /// Ask the server to close the session
+    /// Server will respond either by a shutdown denied PDU
+    /// or by a disconnection
+    pub fn write_shutdown_request<S: Read + Write>(&self, mcs: &mut mcs::Client<S>) -> RdpResult<()> {
+        match self.state {
+            ClientState::Data => self.write_data_pdu(ts_shutdown_request_pdu(), mcs),
+            _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidAutomata, "You cannot send data once it's not connected")))
+        }
+    }
+
+    /// Server capability sent during the demand active PDU
+    fn get_server_capability(&self, cap_type: capability::CapabilitySetType) -> Option<&Capability> {
+        self.server_capabilities.iter().find(|capability| capability.cap_type == cap_type)
+    }
+
+    /// True if the server input capability advertise this flag
+    pub fn is_input_flag_supported(&self, flag: capability::InputFlags) -> bool {
+        match self.get_server_capability(capability::CapabilitySetType::CapstypeInput) {
+            Some(capability) => cast!(DataType::U16, capability.message["inputFlags"]).unwrap_or(0) & flag as u16 != 0,
+            None => false
+        }
+    }
+
+    /// True if the server accept Refresh Rect PDU
+    pub fn is_refresh_rect_supported(&self) -> bool {
+        match self.get_server_capability(capability::CapabilitySetType::CapstypeGeneral) {
+            Some(capability) => cast!(DataType::U8, capability.message["refreshRectSupport"]).unwrap_or(0) != 0,
+            None => false
+        }
+    }
+
+    /// True if the server accept Suppress Output PDU
+    pub fn is_suppress_output_supported(&self) -> bool {
+        match self.get_server_capability(capability::CapabilitySetType::CapstypeGeneral) {
+            Some(capability) => cast!(DataType::U8, capability.message["suppressOutputSupport"]).unwrap_or(0) != 0,
+            None => false
+        }
+    }
+
+    /// True if both sides advertised the frame acknowledge capability
+    pub fn is_frame_acknowledge_supported(&self) -> bool {
+        self.capabilities.get_frame_acknowledge().is_some() && self.get_server_capability(capability::CapabilitySetType::CapssettypeFrameAcknowledge).is_some()
+    }
+
+    /// Acknowledge all frames ended since the last call
+    fn write_frame_acknowledges<S: Read + Write>(&mut self, mcs: &mut mcs::Client<S>) -> RdpResult<()> {
+        for frame_id in std::mem::take(&mut self.frame_acknowledges) {
+            self.write_data_pdu(ts_frame_acknowledge_pdu(frame_id), mcs)?;
+        }
+        Ok(())
+    }
+
+    /// Decode surface bits of a codec id with a custom decoder
+    /// The codec id must match the one advertised in the bitmap codecs capability
+    /// Replace the built-in decoder if any
+    pub fn register_surface_decoder(&mut self, codec_id: u8, decoder: Box<dyn SurfaceDecoder>) {
+        self.surface_decoders.insert(codec_id, decoder);
+    }
+
+    /// Ask the server to redraw some areas of the desktop
+    pub fn write_refresh_rect<S: Read + Write>(&self, areas: &[Rectangle], mcs: &mut mcs::Client<S>) -> RdpResult<()> {
+        match self.state {
+            ClientState::Data => {
+                if !self.is_refresh_rect_supported() {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "GLOBAL: Server doesn't support Refresh Rect PDU")))
+                }
+                self.write_data_pdu(ts_refresh_rect_pdu(areas), mcs)
+            },
+            _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidAutomata, "You cannot send data once it's not connected")))
+        }
+    }
+
+    /// Ask the server to stop (allow = false) or restart (allow = true)
+    /// sending display updates for the whole desktop
+    pub fn write_suppress_output<S: Read + Write>(&self, allow: bool, mcs: &mut mcs::Client<S>) -> RdpResult<()> {
+        match self.state {
+            ClientState::Data => {
+                if !self.is_suppress_output_supported() {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "GLOBAL: Server doesn't support Suppress Output PDU")))
+                }
+                let desktop_rect = Rectangle {
+                    left: 0,
+                    top: 0,
+                    right: self.width.saturating_sub(1),
+                    bottom: self.height.saturating_sub(1)
+                };
+                self.write_data_pdu(ts_suppress_output_pdu(if allow { Some(&desktop_rect) } else { None }), mcs)
+            },
+            _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidAutomata, "You cannot send data once it's not connected")))
+        }
+    }
+
+    /// All capabilities sent by the server
+    /// during the last demand active PDU
+    pub fn get_server_capabilities(&self) -> &[Capability] {
+        &self.server_capabilities
+    }
+
+    /// Share id negotiated during the capability exchange
+    pub fn get_share_id(&self) -> Option<u32> {
+        self.share_id
+    }
+
+    /// Palette used by 8 bpp bitmaps
+    /// None until the server sends one
+    pub fn get_palette(&self) -> Option<&[u32]> {
+        self.palette.as_deref()
+    }
+
+    /// Current desktop size
+    pub fn get_desktop_size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    /// Last disconnect reason sent by the server
+    /// through the Set Error Info PDU
+    pub fn get_error_info(&self) -> Option<ErrorInfo> {
+        self.error_info
+    }
+
     /// Public interface to sent input event
     ///
     /// # Example
@@ -798,8 +1860,36 @@ impl Client {
     /// )
     /// ```
     pub fn write_input_event<S: Read + Write>(&self, event: TSInputEvent, mcs: &mut mcs::Client<S>) -> RdpResult<()> {
+        self.write_input_events(vec![event], mcs)
+    }
+
+    /// True if the server accept fast path input PDU
+    pub fn is_fast_path_input_supported(&self) -> bool {
+        self.is_input_flag_supported(capability::InputFlags::InputFlagFastpathInput) || self.is_input_flag_supported(capability::InputFlags::InputFlagFastpathInput2)
+    }
+
+    /// Send a batch of input events
+    /// Use fast path if the server support it
+    /// else a single slow path input PDU
+    pub fn write_input_events<S: Read + Write>(&self, events: Vec<TSInputEvent>, mcs: &mut mcs::Client<S>) -> RdpResult<()> {
         match self.state {
-            ClientState::Data => Ok(self.write_data_pdu(ts_input_pdu_data(Some(Array::from_trame(trame![ts_input_event(Some(event.event_type), Some(to_vec(&event.message)))]))), mcs)?),
+            ClientState::Data => {
+                if self.is_fast_path_input_supported() {
+                    // number of events is encoded on a single byte
+                    for chunk in events.chunks(255) {
+                        let mut fp_events = trame![];
+                        for event in chunk {
+                            fp_events.push(Box::new(ts_fp_input_event(event)?));
+                        }
+                        mcs.write_fast_path(chunk.len() as u8, fp_events)?;
+                    }
+                    Ok(())
+                }
+                else {
+                    let slow_path_events = events.iter().map(|event| Box::new(ts_input_event(Some(event.event_type), Some(to_vec(&event.message)))) as Box<dyn Message>).collect();
+                    self.write_data_pdu(ts_input_pdu_data(Some(Array::from_trame(slow_path_events))), mcs)
+                }
+            },
             _ => Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidAutomata, "You cannot send data once it's not connected")))
         }
     }
@@ -819,11 +1909,18 @@ impl Client {
     ///     ...
     /// }
     /// ```
-    pub fn read<S: Read + Write, T>(&mut self, payload: tpkt::Payload, mcs: &mut mcs::Client<S>, callback: T) -> RdpResult<()>
+    pub fn read<S: Read + Write, T>(&mut self, payload: tpkt::Payload, mcs: &mut mcs::Client<S>, mut callback: T) -> RdpResult<()>
     where T: FnMut(RdpEvent){
         match self.state {
             ClientState::DemandActivePDU => {
+                let (width, height) = (self.width, self.height);
                 if self.read_demand_active_pdu(&mut try_let!(tpkt::Payload::Raw, payload)?)? {
+                    if (width, height) != (self.width, self.height) {
+                        callback(RdpEvent::Resize(ResizeEvent {
+                            width: self.width,
+                            height: self.height
+                        }));
+                    }
                     self.write_confirm_active_pdu(mcs)?;
                     self.write_client_finalize(mcs)?;
                     // now wait for server synchronize
@@ -862,9 +1959,10 @@ impl Client {
             ClientState::Data => {
                 // Now we can receive update data
                 match payload {
-                    tpkt::Payload::Raw(mut stream) => self.read_data_pdu(&mut stream),
-                    tpkt::Payload::FastPath(_sec_flag, mut stream) => self.read_fast_path(&mut stream, callback)
+                    tpkt::Payload::Raw(mut stream) => self.read_data_pdu(&mut stream, callback)?,
+                    tpkt::Payload::FastPath(_sec_flag, mut stream) => self.read_fast_path(&mut stream, callback)?
                 }
+                self.write_frame_acknowledges(mcs)
             }
         }
     }
@@ -873,6 +1971,9 @@ impl Client {
 #[cfg(test)]
 mod test {
     use super::*;
+    use codec::surface::SurfaceRect;
+    use core::event::OrderEvent;
+    use core::order::PrimaryOrder;
 
     /// Test format message of demand active pdu
     #[test]
@@ -902,28 +2003,444 @@ mod test {
     #[test]
     fn test_read_synchronize_pdu() {
         let mut stream = Cursor::new(vec![22, 0, 23, 0, 234, 3, 234, 3, 1, 0, 0, 2, 22, 0, 31, 0, 0, 0, 1, 0, 0, 0]);
-        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo");
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
         assert!(global.read_synchronize_pdu(&mut stream).unwrap())
     }
 
     #[test]
     fn test_read_control_cooperate_pdu() {
         let mut stream = Cursor::new(vec![26, 0, 23, 0, 234, 3, 234, 3, 1, 0, 0, 2, 26, 0, 20, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0]);
-        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo");
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
         assert!(global.read_control_pdu(&mut stream, Action::CtrlactionCooperate).unwrap())
     }
 
     #[test]
     fn test_read_control_granted_pdu() {
         let mut stream = Cursor::new(vec![26, 0, 23, 0, 234, 3, 234, 3, 1, 0, 0, 2, 26, 0, 20, 0, 0, 0, 2, 0, 236, 3, 234, 3, 0, 0]);
-        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo");
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
         assert!(global.read_control_pdu(&mut stream, Action::CtrlactionGrantedControl).unwrap())
     }
 
     #[test]
     fn test_read_font_map_pdu() {
         let mut stream = Cursor::new(vec![26, 0, 23, 0, 234, 3, 234, 3, 1, 0, 0, 2, 26, 0, 40, 0, 0, 0, 0, 0, 0, 0, 3, 0, 4, 0]);
-        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo");
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
         assert!(global.read_font_map_pdu(&mut stream).unwrap())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_read_logon_info() {
+        let mut info = vec![0; 576];
+        info[0] = 8;
+        info[4..12].copy_from_slice(&[100, 0, 111, 0, 109, 0, 0, 0]);
+        info[56] = 8;
+        info[60..68].copy_from_slice(&[102, 0, 111, 0, 111, 0, 0, 0]);
+        info[572] = 2;
+        let mut data_pdu = ts_save_session_info_pdu();
+        data_pdu.message.read(&mut Cursor::new([vec![0, 0, 0, 0], info].concat())).unwrap();
+        if let LogonEvent::Info(logon) = read_logon_event(&data_pdu).unwrap() {
+            assert_eq!(logon.session_id, 2);
+            assert_eq!(logon.domain, "dom");
+            assert_eq!(logon.username, "foo");
+        }
+        else {
+            panic!("invalid logon event")
+        }
+    }
+
+    #[test]
+    fn test_read_logon_extended_info() {
+        let mut data_pdu = ts_save_session_info_pdu();
+        data_pdu.message.read(&mut Cursor::new(vec![3, 0, 0, 0, 18, 0, 2, 0, 0, 0, 8, 0, 0, 0, 0xfd, 0xff, 0xff, 0xff, 1, 0, 0, 0])).unwrap();
+        if let LogonEvent::Extended(extended) = read_logon_event(&data_pdu).unwrap() {
+            assert!(extended.auto_reconnect.is_none());
+            let error = extended.error.unwrap();
+            assert_eq!(error.error_type, 0xfffffffd);
+            assert_eq!(error.error_data, 1);
+        }
+        else {
+            panic!("invalid logon event")
+        }
+    }
+
+    #[test]
+    fn test_read_set_error_info_pdu() {
+        let mut stream = Cursor::new(vec![22, 0, 23, 0, 234, 3, 234, 3, 1, 0, 0, 2, 22, 0, 47, 0, 0, 0, 3, 0, 0, 0]);
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        let mut events = vec![];
+        global.read_data_pdu(&mut stream, |event| events.push(event)).unwrap();
+        assert_eq!(global.get_error_info(), Some(ErrorInfo::IdleTimeout));
+        match events.as_slice() {
+            [RdpEvent::ErrorInfo(ErrorInfo::IdleTimeout)] => (),
+            _ => panic!("invalid event")
+        }
+    }
+
+    /// Codes not in the specification keep their raw value
+    #[test]
+    fn test_read_unknown_error_info_pdu() {
+        let mut stream = Cursor::new(vec![22, 0, 23, 0, 234, 3, 234, 3, 1, 0, 0, 2, 22, 0, 47, 0, 0, 0, 0x96, 0x11, 0, 0]);
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        global.read_data_pdu(&mut stream, |_| ()).unwrap();
+        assert_eq!(global.get_error_info(), Some(ErrorInfo::Unknown(0x1196)));
+    }
+
+    /// Test read of the shutdown denied PDU
+    #[test]
+    fn test_read_shutdown_denied_pdu() {
+        let mut stream = Cursor::new(vec![18, 0, 23, 0, 234, 3, 234, 3, 1, 0, 0, 2, 18, 0, 37, 0, 0, 0]);
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        let mut events = vec![];
+        global.read_data_pdu(&mut stream, |event| events.push(event)).unwrap();
+        match events.as_slice() {
+            [RdpEvent::ShutdownDenied] => (),
+            _ => panic!("invalid event")
+        }
+    }
+
+    /// Test format of the refresh rect PDU
+    #[test]
+    fn test_refresh_rect_pdu() {
+        let pdu = ts_refresh_rect_pdu(&[Rectangle { left: 0, top: 0, right: 799, bottom: 599 }]);
+        assert_eq!(to_vec(&pdu.message), [1, 0, 0, 0, 0, 0, 0, 0, 31, 3, 87, 2])
+    }
+
+    /// Test format of the suppress output PDU
+    #[test]
+    fn test_suppress_output_pdu() {
+        assert_eq!(to_vec(&ts_suppress_output_pdu(None).message), [0, 0, 0, 0]);
+        let pdu = ts_suppress_output_pdu(Some(&Rectangle { left: 0, top: 0, right: 799, bottom: 599 }));
+        assert_eq!(to_vec(&pdu.message), [1, 0, 0, 0, 0, 0, 0, 0, 31, 3, 87, 2])
+    }
+
+    /// Test detection of refresh rect and suppress output support
+    #[test]
+    fn test_output_support_from_general_capability() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        assert!(!global.is_refresh_rect_supported());
+        let mut general = capability::ts_general_capability_set(None);
+        general.message.insert("refreshRectSupport".to_string(), Box::new(1_u8));
+        global.server_capabilities.push(general);
+        assert!(global.is_refresh_rect_supported());
+        assert!(!global.is_suppress_output_supported());
+    }
+
+    /// A new demand active replace capabilities and desktop size
+    #[test]
+    fn test_demand_active_resize() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        global.server_capabilities.push(capability::ts_general_capability_set(None));
+
+        let capability_sets = trame![capability_set(Some(capability::ts_bitmap_capability_set(Some(32), Some(1024), Some(768))))];
+        let demand_active = component![
+            "shareId" => U32::LE(0x103ea),
+            "lengthSourceDescriptor" => U16::LE(0),
+            "lengthCombinedCapabilities" => U16::LE(capability_sets.length() as u16 + 4),
+            "sourceDescriptor" => Vec::<u8>::new(),
+            "numberCapabilities" => U16::LE(1),
+            "pad2Octets" => U16::LE(0),
+            "capabilitySets" => capability_sets,
+            "sessionId" => U32::LE(0)
+        ];
+        let mut stream = Cursor::new(to_vec(&share_control_header(Some(PDUType::PdutypeDemandactivepdu), Some(0), Some(to_vec(&demand_active)))));
+
+        assert!(global.read_demand_active_pdu(&mut stream).unwrap());
+        assert_eq!(global.get_desktop_size(), (1024, 768));
+        assert_eq!(global.get_share_id(), Some(0x103ea));
+        assert_eq!(global.server_capabilities.len(), 1);
+        assert!(global.get_server_capability(capability::CapabilitySetType::CapstypeGeneral).is_none());
+    }
+
+    /// Build a fast path update as received from the server
+    fn fp_update(header: u8, compression_flags: Option<u8>, data: &[u8]) -> Component {
+        let mut fp_update = ts_fp_update();
+        fp_update.read(&mut Cursor::new(to_vec(&trame![header, compression_flags, U16::LE(data.len() as u16), data.to_vec()]))).unwrap();
+        fp_update
+    }
+
+    /// Fragments are reassembled by update type
+    #[test]
+    fn test_fast_path_fragments() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        assert_eq!(global.reassemble_fast_path(&fp_update(0x01, None, &[1, 2])).unwrap(), Some((1, vec![1, 2])));
+        assert_eq!(global.reassemble_fast_path(&fp_update(0x21, None, &[1])).unwrap(), None);
+        assert_eq!(global.reassemble_fast_path(&fp_update(0x2B, None, &[5])).unwrap(), None);
+        assert_eq!(global.reassemble_fast_path(&fp_update(0x31, None, &[2])).unwrap(), None);
+        assert_eq!(global.reassemble_fast_path(&fp_update(0x1B, None, &[6])).unwrap(), Some((0xB, vec![5, 6])));
+        assert_eq!(global.reassemble_fast_path(&fp_update(0x11, None, &[3])).unwrap(), Some((1, vec![1, 2, 3])));
+        assert!(global.reassemble_fast_path(&fp_update(0x11, None, &[3])).is_err());
+    }
+
+    /// Fragments are bounded by the multi fragment max size
+    #[test]
+    fn test_fast_path_fragments_max_size() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new().multifragment_max_size(4));
+        assert_eq!(global.reassemble_fast_path(&fp_update(0x21, None, &[1, 2, 3])).unwrap(), None);
+        assert!(global.reassemble_fast_path(&fp_update(0x11, None, &[4, 5])).is_err());
+        assert!(global.fragments.is_empty());
+    }
+
+    /// Compressed updates are detected from bits 6-7 of the header
+    #[test]
+    fn test_fast_path_compression_flags() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        assert_eq!(global.reassemble_fast_path(&fp_update(0x81, Some(0), &[1])).unwrap(), Some((1, vec![1])));
+        // MPPC 64K with a single literal
+        assert_eq!(global.reassemble_fast_path(&fp_update(0x81, Some(0xA1), &[0x61])).unwrap(), Some((1, vec![0x61])));
+        assert!(global.reassemble_fast_path(&fp_update(0x81, Some(0x21), &[0xFF, 0xFF])).is_err());
+    }
+
+    /// Palette is kept and decompress 8 bpp bitmaps
+    #[test]
+    fn test_fast_path_palette() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        let palette = [2, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0xff, 0, 0];
+        let bitmap = [1, 0, 1, 0, 0, 0, 0, 0, 3, 0, 0, 0, 4, 0, 1, 0, 8, 0, 0, 0, 4, 0, 0, 1, 1, 0];
+        let mut stream = Cursor::new(to_vec(&trame![
+            0x02_u8, U16::LE(palette.len() as u16), palette.to_vec(),
+            0x01_u8, U16::LE(bitmap.len() as u16), bitmap.to_vec()
+        ]));
+
+        let mut events = Vec::new();
+        global.read_fast_path(&mut stream, |event| events.push(event)).unwrap();
+        assert_eq!(global.get_palette(), Some(&[0x000000, 0xff0000][..]));
+        assert_eq!(events.len(), 2);
+        match events.pop() {
+            Some(RdpEvent::Bitmap(bitmap)) => assert_eq!(bitmap.decompress().unwrap(), [0, 0, 0, 0xff, 0, 0, 0xff, 0xff, 0, 0, 0xff, 0xff, 0, 0, 0, 0xff]),
+            _ => panic!("Expected bitmap event")
+        }
+        match events.pop() {
+            Some(RdpEvent::Palette(palette)) => assert_eq!(palette.entries, [0x000000, 0xff0000]),
+            _ => panic!("Expected palette event")
+        }
+    }
+
+    /// RemoteFX surface bits are decoded into surface events
+    #[test]
+    fn test_surface_bits_remotefx() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        let mut rfx = vec![0xC2, 0xCC, 12, 0, 0, 0, 1, 0, 64, 0, 64, 0];
+        rfx.extend_from_slice(&[0xC4, 0xCC, 14, 0, 0, 0, 1, 0, 5, 0, 0, 0, 1, 0]);
+        rfx.extend_from_slice(&[0xC6, 0xCC, 23, 0, 0, 0, 1, 0, 1, 1, 0, 0, 0, 0, 0, 2, 0, 1, 0, 0xC1, 0xCA, 0, 0]);
+        rfx.extend_from_slice(&[0xC5, 0xCC, 8, 0, 0, 0, 1, 0]);
+
+        // frame marker then set surface bits
+        let mut commands = vec![4, 0, 0, 0, 1, 0, 0, 0];
+        commands.extend_from_slice(&[1, 0, 10, 0, 20, 0, 12, 0, 21, 0, 32, 0, 0, BitmapCodec::CodecRemotefx as u8, 2, 0, 1, 0]);
+        commands.extend_from_slice(&(rfx.len() as u32).to_le_bytes());
+        commands.extend(rfx);
+
+        let mut events = vec![];
+        global.read_surface_commands(&commands, &mut |event| events.push(event)).unwrap();
+        match events.as_slice() {
+            [RdpEvent::FrameMarker(marker), RdpEvent::Surface(surface)] => {
+                assert_eq!((marker.action, marker.frame_id), (FrameAction::Begin, 1));
+                assert_eq!((surface.dest_left, surface.dest_top, surface.width, surface.height), (10, 20, 2, 1));
+                assert_eq!(surface.data.len(), 8);
+            },
+            _ => panic!("Expected frame marker and surface event")
+        }
+    }
+
+    /// Surface bits that can't be decoded are reported
+    #[test]
+    fn test_surface_bits_invalid() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        let rfx = [0xC0, 0xCC, 0xFF, 0, 0, 0];
+        let mut commands = vec![1, 0, 10, 0, 20, 0, 12, 0, 21, 0, 32, 0, 0, BitmapCodec::CodecRemotefx as u8, 2, 0, 1, 0];
+        commands.extend_from_slice(&(rfx.len() as u32).to_le_bytes());
+        commands.extend_from_slice(&rfx);
+        assert!(global.read_surface_commands(&commands, &mut |_| ()).is_err());
+    }
+
+    /// End frame markers are acknowledged only when negotiated
+    #[test]
+    fn test_frame_acknowledge() {
+        let end_marker = [4, 0, 1, 0, 7, 0, 0, 0];
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new().frame_acknowledge(2));
+        global.read_surface_commands(&end_marker, &mut |_| ()).unwrap();
+        assert!(global.frame_acknowledges.is_empty());
+
+        global.server_capabilities.push(capability::ts_frame_acknowledge_capability_set(Some(2)));
+        let mut events = vec![];
+        global.read_surface_commands(&end_marker, &mut |event| events.push(event)).unwrap();
+        match events.as_slice() {
+            [RdpEvent::FrameMarker(marker)] => assert_eq!((marker.action, marker.frame_id), (FrameAction::End, 7)),
+            _ => panic!("Expected frame marker event")
+        }
+        assert_eq!(global.frame_acknowledges, [7]);
+        assert_eq!(to_vec(&ts_frame_acknowledge_pdu(7).message), [7, 0, 0, 0]);
+    }
+
+    /// Custom decoders replace built-in ones
+    #[test]
+    fn test_register_surface_decoder() {
+        struct FillDecoder;
+        impl SurfaceDecoder for FillDecoder {
+            fn decode(&mut self, width: u16, height: u16, _input: &[u8]) -> RdpResult<Vec<SurfaceRect>> {
+                Ok(vec![SurfaceRect { left: 1, top: 0, width, height, data: vec![0xff; width as usize * height as usize * 4] }])
+            }
+        }
+
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        global.register_surface_decoder(0x80, Box::new(FillDecoder));
+        let commands = [1, 0, 5, 0, 6, 0, 6, 0, 7, 0, 32, 0, 0, 0x80, 1, 0, 1, 0, 0, 0, 0, 0];
+
+        let mut events = vec![];
+        global.read_surface_commands(&commands, &mut |event| events.push(event)).unwrap();
+        match events.as_slice() {
+            [RdpEvent::Surface(surface)] => assert_eq!((surface.dest_left, surface.dest_top, surface.data.len()), (6, 6, 4)),
+            _ => panic!("Expected surface event")
+        }
+    }
+
+    /// NSCodec surface bits are drawn at destination
+    #[test]
+    fn test_surface_bits_nscodec() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        let nsc = [1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0x80, 0, 0];
+
+        let mut commands = vec![6, 0, 5, 0, 6, 0, 6, 0, 7, 0, 32, 0, 0, BitmapCodec::CodecNscodec as u8, 1, 0, 1, 0];
+        commands.extend_from_slice(&(nsc.len() as u32).to_le_bytes());
+        commands.extend_from_slice(&nsc);
+
+        let mut events = vec![];
+        global.read_surface_commands(&commands, &mut |event| events.push(event)).unwrap();
+        match events.as_slice() {
+            [RdpEvent::Surface(surface)] => {
+                assert_eq!((surface.dest_left, surface.dest_top, surface.width, surface.height), (5, 6, 1, 1));
+                assert_eq!(surface.data, [0x80, 0x80, 0x80, 0xff]);
+            },
+            _ => panic!("Expected surface event")
+        }
+    }
+
+    /// Fast path orders keep their state between updates
+    #[test]
+    fn test_fast_path_orders() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        let first = [1, 0, 0x09, 0x0A, 0x7F, 10, 0, 20, 0, 30, 0, 40, 0, 0xFF, 0, 0];
+        let second = [1, 0, 0x11, 0x01, 5];
+        let mut stream = Cursor::new(to_vec(&trame![
+            0x00_u8, U16::LE(first.len() as u16), first.to_vec(),
+            0x00_u8, U16::LE(second.len() as u16), second.to_vec()
+        ]));
+
+        let mut events = Vec::new();
+        global.read_fast_path(&mut stream, |event| events.push(event)).unwrap();
+        match events.as_slice() {
+            [RdpEvent::Order(_), RdpEvent::Order(OrderEvent { order: PrimaryOrder::OpaqueRect(rect), bounds: None })] => {
+                assert_eq!((rect.left, rect.top, rect.width, rect.height, rect.color), (15, 20, 30, 40, [0xFF, 0, 0]));
+            },
+            _ => panic!("Expected opaque rect events")
+        }
+    }
+
+    /// An order that can't be decoded fails the update
+    /// and next orders start from a fresh state
+    #[test]
+    fn test_fast_path_orders_invalid() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        let update = |orders: &[u8]| Cursor::new(to_vec(&trame![0x00_u8, U16::LE(orders.len() as u16), orders.to_vec()]));
+        global.read_fast_path(&mut update(&[1, 0, 0x09, 0x0A, 0x7F, 10, 0, 20, 0, 30, 0, 40, 0, 0xFF, 0, 0]), |_| ()).unwrap();
+        // alternate secondary order
+        assert!(global.read_fast_path(&mut update(&[2, 0, 0x09, 0x0A, 0x01, 1, 0, 0x02]), |_| ()).is_err());
+
+        // previous type is PatBlt again
+        let mut events = Vec::new();
+        global.read_fast_path(&mut update(&[1, 0, 0x01, 0x00, 0x00]), |event| events.push(event)).unwrap();
+        match events.as_slice() {
+            [RdpEvent::Order(OrderEvent { order: PrimaryOrder::PatBlt(_), .. })] => (),
+            _ => panic!("Expected a pat blt event")
+        }
+    }
+
+    /// Palette with more than 256 entries is rejected
+    #[test]
+    fn test_read_palette_invalid() {
+        assert!(read_palette(&[2, 0, 0, 0, 1, 1, 0, 0]).is_err());
+        assert!(read_palette(&[1, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    /// Test format of the synchronize input event
+    #[test]
+    fn test_sync_event() {
+        let event = ts_sync_event(Some(sync_flags(&SyncEvent { num_lock: true, caps_lock: true, ..Default::default() })));
+        assert_eq!(to_vec(&event.message), [0, 0, 6, 0, 0, 0])
+    }
+
+    /// Test read of the set keyboard indicators PDU
+    #[test]
+    fn test_read_set_keyboard_indicators_pdu() {
+        let mut stream = Cursor::new(vec![22, 0, 23, 0, 234, 3, 234, 3, 1, 0, 0, 2, 22, 0, 41, 0, 0, 0, 0, 0, 4, 0]);
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        let mut events = vec![];
+        global.read_data_pdu(&mut stream, |event| events.push(event)).unwrap();
+        match events.as_slice() {
+            [RdpEvent::KeyboardIndicators(SyncEvent { caps_lock: true, num_lock: false, scroll_lock: false, kana_lock: false })] => (),
+            _ => panic!("invalid event")
+        }
+    }
+
+    /// Test format of the unicode keyboard event
+    #[test]
+    fn test_unicode_keyboard_event() {
+        let event = ts_unicode_keyboard_event(Some(KeyboardFlag::KbdflagsRelease as u16), Some(0x00e9));
+        assert_eq!(to_vec(&event.message), [0, 128, 233, 0, 0, 0])
+    }
+
+    /// Test detection of input flags from server input capability
+    #[test]
+    fn test_input_flag_supported() {
+        let mut global = Client::new(0,0, 800, 600, KeyboardLayout::US, "foo", CapabilitySet::new());
+        assert!(!global.is_input_flag_supported(capability::InputFlags::InputFlagUnicode));
+        global.server_capabilities.push(capability::ts_input_capability_set(Some(capability::InputFlags::InputFlagUnicode as u16), None));
+        assert!(global.is_input_flag_supported(capability::InputFlags::InputFlagUnicode));
+        assert!(!global.is_input_flag_supported(capability::InputFlags::InputFlagMousex));
+    }
+
+    /// Test format of the wheel pointer event
+    #[test]
+    fn test_wheel_pointer_event() {
+        let event = ts_pointer_event(Some(PointerFlag::PtrflagsWheel as u16 | wheel_rotation(-120)), Some(10), Some(20));
+        assert_eq!(to_vec(&event.message), [136, 3, 10, 0, 20, 0]);
+        assert_eq!(wheel_rotation(-1000), 0x0100);
+        assert_eq!(wheel_rotation(1000), 0x00FF);
+    }
+
+    /// Test format of the extended pointer event
+    #[test]
+    fn test_pointerx_event() {
+        let event = ts_pointerx_event(Some(PointerXFlag::PtrxflagsDown as u16 | PointerXFlag::PtrxflagsButton2 as u16), Some(1), Some(2));
+        assert_eq!(to_vec(&event.message), [2, 128, 1, 0, 2, 0])
+    }
+
+    /// Test format of the relative pointer event
+    #[test]
+    fn test_pointer_rel_event() {
+        let event = ts_pointer_rel_event(Some(PointerFlag::PtrflagsMove as u16), Some(-2), Some(3));
+        assert_eq!(to_vec(&event.message), [0, 8, 254, 255, 3, 0])
+    }
+
+    /// Test conversion of input events into fast path events
+    #[test]
+    fn test_fp_input_event() {
+        let scancode = ts_keyboard_event(Some(KeyboardFlag::KbdflagsRelease as u16 | KeyboardFlag::KbdflagsExtended as u16), Some(0x1C));
+        assert_eq!(to_vec(&ts_fp_input_event(&scancode).unwrap()), [3, 28]);
+
+        let unicode = ts_unicode_keyboard_event(Some(KeyboardFlag::KbdflagsRelease as u16), Some(0x00e9));
+        assert_eq!(to_vec(&ts_fp_input_event(&unicode).unwrap()), [129, 233, 0]);
+
+        let mouse = ts_pointer_event(Some(PointerFlag::PtrflagsMove as u16), Some(1), Some(2));
+        assert_eq!(to_vec(&ts_fp_input_event(&mouse).unwrap()), [32, 0, 8, 1, 0, 2, 0]);
+
+        let mousex = ts_pointerx_event(Some(PointerXFlag::PtrxflagsButton1 as u16), Some(1), Some(2));
+        assert_eq!(to_vec(&ts_fp_input_event(&mousex).unwrap()), [64, 1, 0, 1, 0, 2, 0]);
+
+        let sync = ts_sync_event(Some(SyncFlag::CapsLock as u32));
+        assert_eq!(to_vec(&ts_fp_input_event(&sync).unwrap()), [100]);
+
+        let relative = ts_pointer_rel_event(Some(PointerFlag::PtrflagsMove as u16), Some(-1), Some(1));
+        assert_eq!(to_vec(&ts_fp_input_event(&relative).unwrap()), [160, 0, 8, 255, 255, 1, 0]);
+    }
+}
@@ -0,0 +1,478 @@
+use core::gcc::KeyboardLayout;
+use core::event::KeyboardEvent;
+
+/// Scancodes of the alphanumeric block
+/// in the same order than characters in layout rows
+///
+/// Row E : grave key, 1 to 0, minus and equal keys
+/// Row D : Q to P and the two bracket keys
+/// Row C : A to L, the semicolon, quote and backslash keys
+/// Row B : the ISO key near left shift, Z to M and the three punctuation keys
+const ROWS_SCANCODE: [&[u8]; 4] = [
+    &[0x29, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D],
+    &[0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x1B],
+    &[0x1E, 0x1F, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x2B],
+    &[0x56, 0x2C, 0x2D, 0x2E, 0x2F, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35]
+];
+
+/// Characters produced by each key of the alphanumeric block
+///
+/// Each level is made of four rows in ROWS_SCANCODE order
+/// A null character means no character or a dead key
+/// AltGr rows may be shorter than the row
+struct LayoutTable {
+    normal: [&'static str; 4],
+    shift: [&'static str; 4],
+    alt_gr: [&'static str; 4],
+    /// Keys outside of the common block
+    /// (scancode, normal, shift)
+    extra: &'static [(u8, char, char)]
+}
+
+const US: LayoutTable = LayoutTable {
+    normal: ["`1234567890-=", "qwertyuiop[]", "asdfghjkl;'\\", "\\zxcvbnm,./"],
+    shift: ["~!@#$%^&*()_+", "QWERTYUIOP{}", "ASDFGHJKL:\"|", "|ZXCVBNM<>?"],
+    alt_gr: ["", "", "", ""],
+    extra: &[]
+};
+
+const ARABIC: LayoutTable = LayoutTable {
+    normal: ["ذ1234567890-=", "ضصثقفغعهخحجد", "شسيبلاتنمكط\\", "\0ئءؤر\0ىةوزظ"],
+    shift: ["\0!@#$%^&*)(_+", "\0\0\0\0\0إ\0÷×؛<>", "\0\0][\0أـ،/:\"|", "\0~\0}{\0آ’,.؟"],
+    alt_gr: ["", "", "", ""],
+    extra: &[]
+};
+
+const BULGARIAN: LayoutTable = LayoutTable {
+    normal: ["(1234567890-.", ",уеишщксдзц;", "ьяаожгтнвмч\0", "\0юйъэфхпрлб"],
+    shift: [")!?+\"%=:/_№І\0", "ыУЕИШЩКСДЗЦ§", "\0ЯАОЖГТНВМЧ\0", "\0ЮЙЪЭФХПРЛБ"],
+    alt_gr: ["", "", "", ""],
+    extra: &[]
+};
+
+const CZECH: LayoutTable = LayoutTable {
+    normal: [";+ěščřžýáíé=\0", "qwertzuiopú)", "asdfghjklů§\0", "\\yxcvbnm,.-"],
+    shift: ["°1234567890%\0", "QWERTZUIOP/(", "ASDFGHJKL\"!'", "|YXCVBNM?:_"],
+    alt_gr: ["", "\\|€\0\0\0\0\0\0\0÷×", "\0đĐ[]\0\0łŁ$ß¤", "\0\0#&@{}\0<>*"],
+    extra: &[]
+};
+
+const DANISH: LayoutTable = LayoutTable {
+    normal: ["½1234567890+\0", "qwertyuiopå\0", "asdfghjklæø'", "<zxcvbnm,.-"],
+    shift: ["§!\"#¤%&/()=?\0", "QWERTYUIOPÅ\0", "ASDFGHJKLÆØ*", ">ZXCVBNM;:_"],
+    alt_gr: ["\0\0@£$€\0{[]}\0|", "\0\0€", "", "\\\0\0\0\0\0\0µ"],
+    extra: &[]
+};
+
+const GERMAN: LayoutTable = LayoutTable {
+    normal: ["\x001234567890ß\0", "qwertzuiopü+", "asdfghjklöä#", "<yxcvbnm,.-"],
+    shift: ["°!\"§$%&/()=?\0", "QWERTZUIOPÜ*", "ASDFGHJKLÖÄ'", ">YXCVBNM;:_"],
+    alt_gr: ["\0\0²³\0\0\0{[]}\\", "@\0€\0\0\0\0\0\0\0\0~", "", "|\0\0\0\0\0\0µ"],
+    extra: &[]
+};
+
+const GREEK: LayoutTable = LayoutTable {
+    normal: ["`1234567890-=", ";ςερτυθιοπ[]", "ασδφγηξκλ\0'\\", "<ζχψωβνμ,./"],
+    shift: ["~!@#$%^&*()_+", ":\0ΕΡΤΥΘΙΟΠ{}", "ΑΣΔΦΓΗΞΚΛ\0\"|", ">ΖΧΨΩΒΝΜ<>?"],
+    alt_gr: ["\0\0²³£§¶\0¤¦°±½", "\0\0€®\0¥", "", "\0\0\0©"],
+    extra: &[]
+};
+
+const SPANISH: LayoutTable = LayoutTable {
+    normal: ["º1234567890'¡", "qwertyuiop\0+", "asdfghjklñ\0ç", "<zxcvbnm,.-"],
+    shift: ["ª!\"·$%&/()=?¿", "QWERTYUIOP\0*", "ASDFGHJKLÑ\0Ç", ">ZXCVBNM;:_"],
+    alt_gr: ["\\|@#\0€¬", "\0\0€\0\0\0\0\0\0\0[]", "\0\0\0\0\0\0\0\0\0\0{}", ""],
+    extra: &[]
+};
+
+const FINNISH: LayoutTable = LayoutTable {
+    normal: ["§1234567890+\0", "qwertyuiopå\0", "asdfghjklöä'", "<zxcvbnm,.-"],
+    shift: ["½!\"#¤%&/()=?\0", "QWERTYUIOPÅ\0", "ASDFGHJKLÖÄ*", ">ZXCVBNM;:_"],
+    alt_gr: ["\0\0@£$€\0{[]}\\", "\0\0€", "", "|\0\0\0\0\0\0µ"],
+    extra: &[]
+};
+
+const FRENCH: LayoutTable = LayoutTable {
+    normal: ["²&é\"'(-è_çà)=", "azertyuiop\0$", "qsdfghjklmù*", "<wxcvbn,;:!"],
+    shift: ["\x001234567890°+", "AZERTYUIOP\0£", "QSDFGHJKLM%µ", ">WXCVBN?./§"],
+    alt_gr: ["\0\0\0#{[|\0\\^@]}", "\0\0€\0\0\0\0\0\0\0\0¤", "", ""],
+    extra: &[]
+};
+
+const HEBREW: LayoutTable = LayoutTable {
+    normal: [";1234567890-=", "/'קראטוןםפ][", "שדגכעיחלךף,\\", "\\זסבהנמצתץ."],
+    shift: ["~!@#$%^&*)(_+", "QWERTYUIOP}{", "ASDFGHJKL:\"|", "|ZXCVBNM><?"],
+    alt_gr: ["\0\0\0\0₪", "\0\0€", "", ""],
+    extra: &[]
+};
+
+const HUNGARIAN: LayoutTable = LayoutTable {
+    normal: ["0123456789öüó", "qwertzuiopőú", "asdfghjkléáű", "íyxcvbnm,.-"],
+    shift: ["§'\"+!%/=()ÖÜÓ", "QWERTZUIOPŐÚ", "ASDFGHJKLÉÁŰ", "ÍYXCVBNM?:_"],
+    alt_gr: ["", "\\|Ä\0\0\0€Í\0\0÷×", "äđĐ[]\0íłŁ$ß¤", "<>#&@{}\0;\0*"],
+    extra: &[]
+};
+
+const ICELANDIC: LayoutTable = LayoutTable {
+    normal: ["\x001234567890ö-", "qwertyuiopð'", "asdfghjklæ\0+", "<zxcvbnm,.þ"],
+    shift: ["\0!\"#$%&/()=Ö_", "QWERTYUIOPÐ?", "ASDFGHJKLÆ\0*", ">ZXCVBNM;:Þ"],
+    alt_gr: ["\0\0\0\0\0\0\0{[]}\0\\", "@\0€\0\0\0\0\0\0\0\0~", "", "|\0\0\0\0\0\0µ"],
+    extra: &[]
+};
+
+const ITALIAN: LayoutTable = LayoutTable {
+    normal: ["\\1234567890'ì", "qwertyuiopè+", "asdfghjklòàù", "<zxcvbnm,.-"],
+    shift: ["|!\"£$%&/()=?^", "QWERTYUIOPé*", "ASDFGHJKLç°§", ">ZXCVBNM;:_"],
+    alt_gr: ["", "\0\0€\0\0\0\0\0\0\0[]", "\0\0\0\0\0\0\0\0\0@#", ""],
+    extra: &[]
+};
+
+/// Japanese 106 keys in alphanumeric mode
+const JAPANESE: LayoutTable = LayoutTable {
+    normal: ["\x001234567890-^", "qwertyuiop@[", "asdfghjkl;:]", "\0zxcvbnm,./"],
+    shift: ["\0!\"#$%&'()\0=~", "QWERTYUIOP`{", "ASDFGHJKL+*}", "\0ZXCVBNM<>?"],
+    alt_gr: ["", "", "", ""],
+    extra: &[(0x73, '\\', '_'), (0x7D, '\\', '|')]
+};
+
+const DUTCH: LayoutTable = LayoutTable {
+    normal: ["@1234567890/°", "qwertyuiop\0*", "asdfghjkl+\0<", "]zxcvbnm,.-"],
+    shift: ["§!\"#$%&_()'?\0", "QWERTYUIOP\0|", "ASDFGHJKL±\0>", "[ZXCVBNM;:="],
+    alt_gr: ["¬¹²³¼½¾£{}\0\\", "\0\0€", "\0ß", "¦\0\0\0\0\0\0µ"],
+    extra: &[]
+};
+
+const NORWEGIAN: LayoutTable = LayoutTable {
+    normal: ["|1234567890+\\", "qwertyuiopå\0", "asdfghjkløæ'", "<zxcvbnm,.-"],
+    shift: ["§!\"#¤%&/()=?\0", "QWERTYUIOPÅ\0", "ASDFGHJKLØÆ*", ">ZXCVBNM;:_"],
+    alt_gr: ["\0\0@£$€\0{[]}", "\0\0€", "", "\0\0\0\0\0\0\0µ"],
+    extra: &[]
+};
+
+/// Retrieve the table of a layout
+/// Chinese and Korean keyboards use the US
+/// table for latin characters
+fn layout_table(layout: KeyboardLayout) -> &'static LayoutTable {
+    match layout {
+        KeyboardLayout::Arabic => &ARABIC,
+        KeyboardLayout::Bulgarian => &BULGARIAN,
+        KeyboardLayout::ChineseUsKeyboard => &US,
+        KeyboardLayout::Czech => &CZECH,
+        KeyboardLayout::Danish => &DANISH,
+        KeyboardLayout::German => &GERMAN,
+        KeyboardLayout::Greek => &GREEK,
+        KeyboardLayout::US => &US,
+        KeyboardLayout::Spanish => &SPANISH,
+        KeyboardLayout::Finnish => &FINNISH,
+        KeyboardLayout::French => &FRENCH,
+        KeyboardLayout::Hebrew => &HEBREW,
+        KeyboardLayout::Hungarian => &HUNGARIAN,
+        KeyboardLayout::Icelandic => &ICELANDIC,
+        KeyboardLayout::Italian => &ITALIAN,
+        KeyboardLayout::Japanese => &JAPANESE,
+        KeyboardLayout::Korean => &US,
+        KeyboardLayout::Dutch => &DUTCH,
+        KeyboardLayout::Norwegian => &NORWEGIAN
+    }
+}
+
+/// Modifiers that must be held
+/// to produce a character
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    /// AltGr is sent as Left Ctrl + Right Alt
+    pub alt_gr: bool
+}
+
+/// A physical key with its modifiers
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyStroke {
+    pub scancode: u8,
+    pub extended: bool,
+    pub modifiers: Modifiers
+}
+
+const SCANCODE_LEFT_SHIFT: u16 = 0x002A;
+const SCANCODE_LEFT_CTRL: u16 = 0x001D;
+const SCANCODE_RIGHT_ALT: u16 = 0xE038;
+
+impl KeyStroke {
+    fn new(scancode: u8, extended: bool) -> Self {
+        KeyStroke {
+            scancode,
+            extended,
+            modifiers: Modifiers::default()
+        }
+    }
+
+    /// Scancode as expected by KeyboardEvent
+    /// Extended keys are prefixed by 0xE0
+    ///
+    /// # Example
+    /// ```
+    /// use rdp::core::keyboard::{vk_to_keystroke, VK_UP};
+    /// use rdp::core::gcc::KeyboardLayout;
+    /// assert_eq!(vk_to_keystroke(KeyboardLayout::US, VK_UP).unwrap().code(), 0xE048);
+    /// ```
+    pub fn code(&self) -> u16 {
+        if self.extended {
+            0xE000 | self.scancode as u16
+        } else {
+            self.scancode as u16
+        }
+    }
+
+    /// Keyboard events to press and release the key
+    /// Modifiers are pressed before and released after
+    ///
+    /// # Example
+    /// ```
+    /// use rdp::core::keyboard::char_to_keystroke;
+    /// use rdp::core::gcc::KeyboardLayout;
+    /// let events = char_to_keystroke(KeyboardLayout::US, 'A').unwrap().events();
+    /// let codes: Vec<(u16, bool)> = events.iter().map(|e| (e.code, e.down)).collect();
+    /// assert_eq!(codes, [(0x2A, true), (0x1E, true), (0x1E, false), (0x2A, false)]);
+    /// ```
+    pub fn events(&self) -> Vec<KeyboardEvent> {
+        let mut modifiers = vec![];
+        if self.modifiers.alt_gr {
+            modifiers.push(SCANCODE_LEFT_CTRL);
+            modifiers.push(SCANCODE_RIGHT_ALT);
+        }
+        if self.modifiers.shift {
+            modifiers.push(SCANCODE_LEFT_SHIFT);
+        }
+
+        let mut events: Vec<KeyboardEvent> = modifiers.iter().map(|code| KeyboardEvent { code: *code, down: true }).collect();
+        events.push(KeyboardEvent { code: self.code(), down: true });
+        events.push(KeyboardEvent { code: self.code(), down: false });
+        events.extend(modifiers.iter().rev().map(|code| KeyboardEvent { code: *code, down: false }));
+        events
+    }
+}
+
+/// Find a character in a level of a layout table
+fn find_in_rows(rows: &[&str; 4], c: char) -> Option<u8> {
+    for (row, scancodes) in rows.iter().zip(ROWS_SCANCODE.iter()) {
+        if let Some(index) = row.chars().position(|e| e == c) {
+            return scancodes.get(index).copied()
+        }
+    }
+    None
+}
+
+/// Find the key that produces a character
+/// for a particular layout
+///
+/// Dead keys and characters that need
+/// an input method are not handled
+///
+/// # Example
+/// ```
+/// use rdp::core::keyboard::char_to_keystroke;
+/// use rdp::core::gcc::KeyboardLayout;
+/// let stroke = char_to_keystroke(KeyboardLayout::French, 'a').unwrap();
+/// assert_eq!(stroke.scancode, 0x10);
+/// let stroke = char_to_keystroke(KeyboardLayout::German, '@').unwrap();
+/// assert_eq!(stroke.scancode, 0x10);
+/// assert!(stroke.modifiers.alt_gr);
+/// ```
+pub fn char_to_keystroke(layout: KeyboardLayout, c: char) -> Option<KeyStroke> {
+    // Same key for all layouts
+    match c {
+        '\0' => return None,
+        ' ' => return Some(KeyStroke::new(0x39, false)),
+        '\t' => return Some(KeyStroke::new(0x0F, false)),
+        '\r' | '\n' => return Some(KeyStroke::new(0x1C, false)),
+        '\u{8}' => return Some(KeyStroke::new(0x0E, false)),
+        '\u{1b}' => return Some(KeyStroke::new(0x01, false)),
+        _ => ()
+    }
+
+    let table = layout_table(layout);
+    let levels = [
+        (&table.normal, Modifiers { shift: false, alt_gr: false }),
+        (&table.shift, Modifiers { shift: true, alt_gr: false }),
+        (&table.alt_gr, Modifiers { shift: false, alt_gr: true })
+    ];
+    for (rows, modifiers) in levels.iter() {
+        if let Some(scancode) = find_in_rows(rows, c) {
+            return Some(KeyStroke { scancode, extended: false, modifiers: *modifiers })
+        }
+    }
+
+    for (scancode, normal, shift) in table.extra {
+        if *normal == c {
+            return Some(KeyStroke::new(*scancode, false))
+        }
+        if *shift == c {
+            return Some(KeyStroke { scancode: *scancode, extended: false, modifiers: Modifiers { shift: true, alt_gr: false } })
+        }
+    }
+    None
+}
+
+pub const VK_BACK: u8 = 0x08;
+pub const VK_TAB: u8 = 0x09;
+pub const VK_RETURN: u8 = 0x0D;
+pub const VK_SHIFT: u8 = 0x10;
+pub const VK_CONTROL: u8 = 0x11;
+pub const VK_MENU: u8 = 0x12;
+pub const VK_CAPITAL: u8 = 0x14;
+pub const VK_ESCAPE: u8 = 0x1B;
+pub const VK_SPACE: u8 = 0x20;
+pub const VK_PRIOR: u8 = 0x21;
+pub const VK_NEXT: u8 = 0x22;
+pub const VK_END: u8 = 0x23;
+pub const VK_HOME: u8 = 0x24;
+pub const VK_LEFT: u8 = 0x25;
+pub const VK_UP: u8 = 0x26;
+pub const VK_RIGHT: u8 = 0x27;
+pub const VK_DOWN: u8 = 0x28;
+pub const VK_SNAPSHOT: u8 = 0x2C;
+pub const VK_INSERT: u8 = 0x2D;
+pub const VK_DELETE: u8 = 0x2E;
+pub const VK_LWIN: u8 = 0x5B;
+pub const VK_RWIN: u8 = 0x5C;
+pub const VK_APPS: u8 = 0x5D;
+pub const VK_NUMPAD0: u8 = 0x60;
+pub const VK_MULTIPLY: u8 = 0x6A;
+pub const VK_ADD: u8 = 0x6B;
+pub const VK_SUBTRACT: u8 = 0x6D;
+pub const VK_DECIMAL: u8 = 0x6E;
+pub const VK_DIVIDE: u8 = 0x6F;
+pub const VK_F1: u8 = 0x70;
+pub const VK_F12: u8 = 0x7B;
+pub const VK_NUMLOCK: u8 = 0x90;
+pub const VK_SCROLL: u8 = 0x91;
+pub const VK_LSHIFT: u8 = 0xA0;
+pub const VK_RSHIFT: u8 = 0xA1;
+pub const VK_LCONTROL: u8 = 0xA2;
+pub const VK_RCONTROL: u8 = 0xA3;
+pub const VK_LMENU: u8 = 0xA4;
+pub const VK_RMENU: u8 = 0xA5;
+
+/// Find the key of a Windows virtual key code
+/// for a particular layout
+///
+/// Letters and digits follow the layout
+/// (VK_A is on the key that produce 'a')
+/// OEM virtual key codes are not handled
+///
+/// # Example
+/// ```
+/// use rdp::core::keyboard::vk_to_keystroke;
+/// use rdp::core::gcc::KeyboardLayout;
+/// assert_eq!(vk_to_keystroke(KeyboardLayout::French, b'Q').unwrap().scancode, 0x1E);
+/// assert_eq!(vk_to_keystroke(KeyboardLayout::Greek, b'Q').unwrap().scancode, 0x10);
+/// ```
+pub fn vk_to_keystroke(layout: KeyboardLayout, vk: u8) -> Option<KeyStroke> {
+    let (scancode, extended) = match vk {
+        VK_BACK => (0x0E, false),
+        VK_TAB => (0x0F, false),
+        VK_RETURN => (0x1C, false),
+        VK_SHIFT | VK_LSHIFT => (0x2A, false),
+        VK_RSHIFT => (0x36, false),
+        VK_CONTROL | VK_LCONTROL => (0x1D, false),
+        VK_RCONTROL => (0x1D, true),
+        VK_MENU | VK_LMENU => (0x38, false),
+        VK_RMENU => (0x38, true),
+        VK_CAPITAL => (0x3A, false),
+        VK_ESCAPE => (0x01, false),
+        VK_SPACE => (0x39, false),
+        VK_PRIOR => (0x49, true),
+        VK_NEXT => (0x51, true),
+        VK_END => (0x4F, true),
+        VK_HOME => (0x47, true),
+        VK_LEFT => (0x4B, true),
+        VK_UP => (0x48, true),
+        VK_RIGHT => (0x4D, true),
+        VK_DOWN => (0x50, true),
+        VK_SNAPSHOT => (0x37, true),
+        VK_INSERT => (0x52, true),
+        VK_DELETE => (0x53, true),
+        VK_LWIN => (0x5B, true),
+        VK_RWIN => (0x5C, true),
+        VK_APPS => (0x5D, true),
+        VK_NUMPAD0..=0x69 => ([0x52, 0x4F, 0x50, 0x51, 0x4B, 0x4C, 0x4D, 0x47, 0x48, 0x49][(vk - VK_NUMPAD0) as usize], false),
+        VK_MULTIPLY => (0x37, false),
+        VK_ADD => (0x4E, false),
+        VK_SUBTRACT => (0x4A, false),
+        VK_DECIMAL => (0x53, false),
+        VK_DIVIDE => (0x35, true),
+        VK_F1..=0x79 => (0x3B + vk - VK_F1, false),
+        0x7A..=VK_F12 => (0x57 + vk - 0x7A, false),
+        VK_NUMLOCK => (0x45, false),
+        VK_SCROLL => (0x46, false),
+        // Letters and digits depend on layout
+        b'0'..=b'9' | b'A'..=b'Z' => {
+            let c = (vk as char).to_ascii_lowercase();
+            let table = layout_table(layout);
+            // Non latin layouts keep latin letters on US position
+            let scancode = find_in_rows(&table.normal, c)
+                .or_else(|| find_in_rows(&table.shift, c))
+                .or_else(|| find_in_rows(&US.normal, c))?;
+            (scancode, false)
+        },
+        _ => return None
+    };
+    Some(KeyStroke::new(scancode, extended))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALL_LAYOUTS: [KeyboardLayout; 19] = [
+        KeyboardLayout::Arabic, KeyboardLayout::Bulgarian, KeyboardLayout::ChineseUsKeyboard,
+        KeyboardLayout::Czech, KeyboardLayout::Danish, KeyboardLayout::German,
+        KeyboardLayout::Greek, KeyboardLayout::US, KeyboardLayout::Spanish,
+        KeyboardLayout::Finnish, KeyboardLayout::French, KeyboardLayout::Hebrew,
+        KeyboardLayout::Hungarian, KeyboardLayout::Icelandic, KeyboardLayout::Italian,
+        KeyboardLayout::Japanese, KeyboardLayout::Korean, KeyboardLayout::Dutch,
+        KeyboardLayout::Norwegian
+    ];
+
+    /// Each row of each table must be aligned on scancodes
+    #[test]
+    fn test_layout_tables_size() {
+        for layout in ALL_LAYOUTS.iter() {
+            let table = layout_table(*layout);
+            for (index, scancodes) in ROWS_SCANCODE.iter().enumerate() {
+                assert_eq!(table.normal[index].chars().count(), scancodes.len());
+                assert_eq!(table.shift[index].chars().count(), scancodes.len());
+                assert!(table.alt_gr[index].chars().count() <= scancodes.len());
+            }
+        }
+    }
+
+    /// All layouts must be able to produce digits
+    #[test]
+    fn test_digits_on_all_layouts() {
+        for layout in ALL_LAYOUTS.iter() {
+            for vk in b'0'..=b'9' {
+                assert!(vk_to_keystroke(*layout, vk).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_char_to_keystroke() {
+        assert_eq!(char_to_keystroke(KeyboardLayout::US, '"'), Some(KeyStroke { scancode: 0x28, extended: false, modifiers: Modifiers { shift: true, alt_gr: false } }));
+        assert!(char_to_keystroke(KeyboardLayout::French, '1').unwrap().modifiers.shift);
+        assert_eq!(char_to_keystroke(KeyboardLayout::French, '€').unwrap().scancode, 0x12);
+        assert_eq!(char_to_keystroke(KeyboardLayout::German, 'z').unwrap().scancode, 0x15);
+        assert_eq!(char_to_keystroke(KeyboardLayout::Hungarian, '0').unwrap().scancode, 0x29);
+        assert_eq!(char_to_keystroke(KeyboardLayout::Greek, 'λ').unwrap().scancode, 0x26);
+        assert_eq!(char_to_keystroke(KeyboardLayout::Japanese, '_').unwrap().scancode, 0x73);
+        assert_eq!(char_to_keystroke(KeyboardLayout::US, 'é'), None);
+    }
+
+    #[test]
+    fn test_altgr_events() {
+        let events = char_to_keystroke(KeyboardLayout::French, '@').unwrap().events();
+        let codes: Vec<(u16, bool)> = events.iter().map(|e| (e.code, e.down)).collect();
+        assert_eq!(codes, [(0x1D, true), (0xE038, true), (0x0B, true), (0x0B, false), (0xE038, false), (0x1D, false)]);
+    }
+}
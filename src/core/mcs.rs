@@ -1,5 +1,6 @@
 use core::x224;
 use core::tpkt;
+use model::link::Link;
 use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
 use core::gcc::{KeyboardLayout, client_core_data, ClientData, ServerData, client_security_data, client_network_data, block_header, write_conference_create_request, MessageType, read_conference_create_response, Version};
 use model::data::{Trame, to_vec, Message, DataType, U16};
@@ -180,14 +181,15 @@ impl<S: Read + Write> Client<S> {
     /// Write connection initial payload
     /// This payload include a lot of
     /// client specific config parameters
-    fn write_connect_initial(&mut self, screen_width: u16, screen_height: u16, keyboard_layout: KeyboardLayout, client_name: String) -> RdpResult<()> {
+    fn write_connect_initial(&mut self, screen_width: u16, screen_height: u16, keyboard_layout: KeyboardLayout, client_name: String, color_depth: u16) -> RdpResult<()> {
         let client_core_data = client_core_data(Some(ClientData {
             width: screen_width,
             height: screen_height,
             layout: keyboard_layout,
             server_selected_protocol: self.x224.get_selected_protocols() as u32,
             rdp_version: Version::RdpVersion5plus,
-            name: client_name
+            name: client_name,
+            color_depth
         }));
         let client_security_data = client_security_data();
         let client_network_data = client_network_data(trame![]);
@@ -221,10 +223,10 @@ impl<S: Read + Write> Client<S> {
     /// # Example
     /// ```rust, ignore
     /// let mut mcs = mcs::Client(x224);
-    /// mcs.connect(800, 600, KeyboardLayout::French).unwrap()
+    /// mcs.connect("mstsc-rs".to_string(), 800, 600, KeyboardLayout::French, 24).unwrap()
     /// ```
-    pub fn connect(&mut self, client_name: String, screen_width: u16, screen_height: u16, keyboard_layout: KeyboardLayout) -> RdpResult<()> {
-        self.write_connect_initial(screen_width, screen_height, keyboard_layout, client_name)?;
+    pub fn connect(&mut self, client_name: String, screen_width: u16, screen_height: u16, keyboard_layout: KeyboardLayout, color_depth: u16) -> RdpResult<()> {
+        self.write_connect_initial(screen_width, screen_height, keyboard_layout, client_name, color_depth)?;
         self.read_connect_response()?;
         self.x224.write(erect_domain_request()?)?;
         self.x224.write(attach_user_request())?;
@@ -254,7 +256,7 @@ impl<S: Read + Write> Client<S> {
     /// # Example
     /// ```rust, ignore
     /// let mut mcs = mcs::Client(x224);
-    /// mcs.connect(800, 600, KeyboardLayout::French).unwrap();
+    /// mcs.connect("mstsc-rs".to_string(), 800, 600, KeyboardLayout::French, 24).unwrap();
     /// mcs.write("global".to_string(), trame![U16::LE(0)])
     /// ```
     pub fn write<T: 'static>(&mut self, channel_name: &String, message: T) -> RdpResult<()>
@@ -269,6 +271,14 @@ impl<S: Read + Write> Client<S> {
         ])
     }
 
+    /// Send a fast path message
+    /// Fast path messages bypass the mcs layer
+    /// and are implicitly sent to the global channel
+    pub fn write_fast_path<T>(&mut self, num_events: u8, message: T) -> RdpResult<()>
+    where T: 'static + Message {
+        self.x224.write_fast_path(num_events, message)
+    }
+
     /// Receive a message for a specific channel
     /// Actually by design you can't ask for a specific channel
     /// the caller need to handle all channels
@@ -276,7 +286,7 @@ impl<S: Read + Write> Client<S> {
     /// # Example
     /// ```rust, ignore
     /// let mut mcs = mcs::Client(x224);
-    /// mcs.connect(800, 600, KeyboardLayout::French).unwrap();
+    /// mcs.connect("mstsc-rs".to_string(), 800, 600, KeyboardLayout::French, 24).unwrap();
     /// let (channel_name, payload) = mcs.read().unwrap();
     /// match channel_name.as_str() {
     ///     "global" => println!("main channel");
@@ -332,6 +342,16 @@ impl<S: Read + Write> Client<S> {
         self.server_data.as_ref().unwrap().rdp_version == Version::RdpVersion5plus
     }
 
+    /// Getter of the server data sent during connection steps
+    pub fn get_server_data(&self) -> Option<&ServerData> {
+        self.server_data.as_ref()
+    }
+
+    /// Getter of the security protocol selected by the server
+    pub fn get_selected_protocols(&self) -> x224::Protocols {
+        self.x224.get_selected_protocols()
+    }
+
     /// Getter of the user id negotiated during connection steps
     pub fn get_user_id(&self) -> u16 {
         self.user_id.unwrap()
@@ -341,6 +361,11 @@ impl<S: Read + Write> Client<S> {
     pub fn get_global_channel_id(&self) -> u16 {
         self.channel_ids["global"]
     }
+
+    /// Getter of the underlying link layer
+    pub fn get_link_ref(&self) -> &Link<S> {
+        self.x224.get_link_ref()
+    }
 }
 
 #[cfg(test)]
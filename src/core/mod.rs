@@ -8,4 +8,6 @@ pub mod sec;
 pub mod license;
 pub mod global;
 pub mod capability;
-pub mod event;
\ No newline at end of file
+pub mod event;
+pub mod order;
+pub mod keyboard;
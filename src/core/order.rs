@@ -0,0 +1,1350 @@
+use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
+use core::event::{OrderEvent, BitmapEvent, RdpEvent};
+use std::io::{Cursor, Read};
+use byteorder::{ReadBytesExt, LittleEndian};
+use num_enum::TryFromPrimitive;
+use std::convert::TryFrom;
+
+/// Control flags of a drawing order
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2 Primary Drawing Order (PRIMARY_DRAWING_ORDER)
+#[repr(u8)]
+#[derive(Copy, Clone)]
+pub enum ControlFlag {
+    TsStandard = 0x01,
+    TsSecondary = 0x02,
+    TsBounds = 0x04,
+    TsTypeChange = 0x08,
+    TsDeltaCoordinates = 0x10,
+    TsZeroBoundsDeltas = 0x20,
+    TsZeroFieldByteBit0 = 0x40,
+    TsZeroFieldByteBit1 = 0x80
+}
+
+/// Primary drawing orders handled by the decoder
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2 Primary Drawing Order (PRIMARY_DRAWING_ORDER)
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, TryFromPrimitive)]
+pub enum PrimaryOrderType {
+    TsEncDstbltOrder = 0x00,
+    TsEncPatbltOrder = 0x01,
+    TsEncScrbltOrder = 0x02,
+    TsEncLinetoOrder = 0x09,
+    TsEncOpaquerectOrder = 0x0A,
+    TsEncMembltOrder = 0x0D,
+    TsEncMultiopaquerectOrder = 0x12,
+    TsEncPolylineOrder = 0x16,
+    TsEncFastGlyphOrder = 0x18,
+    TsEncIndexOrder = 0x1B
+}
+
+impl PrimaryOrderType {
+    /// Number of bytes of the field flags
+    /// before zero field bytes are removed
+    fn field_bytes(self) -> usize {
+        match self {
+            PrimaryOrderType::TsEncDstbltOrder | PrimaryOrderType::TsEncScrbltOrder | PrimaryOrderType::TsEncOpaquerectOrder | PrimaryOrderType::TsEncPolylineOrder => 1,
+            PrimaryOrderType::TsEncIndexOrder => 3,
+            _ => 2
+        }
+    }
+}
+
+/// Maximum number of rectangles of a multi opaque rect order
+const MAX_DELTA_RECTS: u8 = 45;
+
+/// Maximum number of points of a polyline order
+const MAX_DELTA_POINTS: u8 = 32;
+
+/// Secondary drawing orders handled by the decoder
+/// Bitmap cache revision 3 orders are not handled
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.1.1 Secondary Drawing Order Header (SECONDARY_DRAWING_ORDER_HEADER)
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, TryFromPrimitive)]
+pub enum SecondaryOrderType {
+    TsCacheBitmapUncompressed = 0x00,
+    TsCacheColorTable = 0x01,
+    TsCacheBitmapCompressed = 0x02,
+    TsCacheGlyph = 0x03,
+    TsCacheBitmapUncompressedRev2 = 0x04,
+    TsCacheBitmapCompressedRev2 = 0x05,
+    TsCacheBrush = 0x07
+}
+
+/// Extra flags of cache bitmap revision 1
+/// and cache glyph orders
+#[repr(u16)]
+#[derive(Copy, Clone)]
+pub enum SecondaryOrderFlag {
+    CgGlyphUnicodePresent = 0x0010,
+    NoBitmapCompressionHdr = 0x0400
+}
+
+/// Flags of a cache bitmap revision 2 order
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.3 Cache Bitmap - Revision 2 (CACHE_BITMAP_REV2_ORDER)
+#[repr(u16)]
+#[derive(Copy, Clone)]
+pub enum CacheBitmapRev2Flag {
+    Cbr2HeightSameAsWidth = 0x01,
+    Cbr2PersistentKeyPresent = 0x02,
+    Cbr2NoBitmapCompressionHdr = 0x08,
+    Cbr2DoNotCache = 0x10
+}
+
+/// Clipping rectangle of an order
+/// Right and bottom are inclusive
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct OrderBounds {
+    pub left: i16,
+    pub top: i16,
+    pub right: i16,
+    pub bottom: i16
+}
+
+/// Brush of pattern orders
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.3 PatBlt (PATBLT_ORDER)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Brush {
+    pub x: i8,
+    pub y: i8,
+    pub style: u8,
+    pub hatch: u8,
+    pub extra: [u8; 7]
+}
+
+/// Destination only blit
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.1 DstBlt (DSTBLT_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct DstBltOrder {
+    pub left: i16,
+    pub top: i16,
+    pub width: i16,
+    pub height: i16,
+    /// Ternary raster operation
+    pub rop: u8
+}
+
+/// Pattern blit
+/// Colors are red (or palette index), green, blue
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.3 PatBlt (PATBLT_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct PatBltOrder {
+    pub left: i16,
+    pub top: i16,
+    pub width: i16,
+    pub height: i16,
+    pub rop: u8,
+    pub back_color: [u8; 3],
+    pub fore_color: [u8; 3],
+    pub brush: Brush
+}
+
+/// Screen to screen blit
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.7 ScrBlt (SCRBLT_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct ScrBltOrder {
+    pub left: i16,
+    pub top: i16,
+    pub width: i16,
+    pub height: i16,
+    pub rop: u8,
+    pub src_x: i16,
+    pub src_y: i16
+}
+
+/// Solid color rectangle
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.5 OpaqueRect (OPAQUERECT_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct OpaqueRectOrder {
+    pub left: i16,
+    pub top: i16,
+    pub width: i16,
+    pub height: i16,
+    pub color: [u8; 3]
+}
+
+/// A rectangle of a multi opaque rect order
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DeltaRect {
+    pub left: i16,
+    pub top: i16,
+    pub width: i16,
+    pub height: i16
+}
+
+/// Solid color rectangles clipped by the order rectangle
+/// Rectangles are already in absolute coordinates
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.6 MultiOpaqueRect (MULTI_OPAQUERECT_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct MultiOpaqueRectOrder {
+    pub left: i16,
+    pub top: i16,
+    pub width: i16,
+    pub height: i16,
+    pub color: [u8; 3],
+    pub rects: Vec<DeltaRect>
+}
+
+/// Blit from the bitmap cache
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.9 MemBlt (MEMBLT_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct MemBltOrder {
+    /// Cache id in the low byte
+    /// color table index in the high byte
+    pub cache_id: u16,
+    pub left: i16,
+    pub top: i16,
+    pub width: i16,
+    pub height: i16,
+    pub rop: u8,
+    pub src_x: i16,
+    pub src_y: i16,
+    pub cache_index: u16
+}
+
+/// Line drawn with a pen
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.11 LineTo (LINETO_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct LineToOrder {
+    pub back_mode: u16,
+    pub start_x: i16,
+    pub start_y: i16,
+    pub end_x: i16,
+    pub end_y: i16,
+    pub back_color: [u8; 3],
+    /// Binary raster operation
+    pub rop2: u8,
+    pub pen_style: u8,
+    pub pen_width: u8,
+    pub pen_color: [u8; 3]
+}
+
+/// Connected lines
+/// Points are already in absolute coordinates
+/// and don't include the start point
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.16 Polyline (POLYLINE_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct PolylineOrder {
+    pub start_x: i16,
+    pub start_y: i16,
+    pub rop2: u8,
+    pub brush_cache_entry: u16,
+    pub pen_color: [u8; 3],
+    pub points: Vec<(i16, i16)>
+}
+
+/// Text drawn from the glyph cache
+/// Glyph fragments are left encoded in data
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.13 GlyphIndex (GLYPHINDEX_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct GlyphIndexOrder {
+    pub cache_id: u8,
+    pub fl_accel: u8,
+    pub ul_char_inc: u8,
+    pub op_redundant: u8,
+    pub back_color: [u8; 3],
+    pub fore_color: [u8; 3],
+    pub bk_left: i16,
+    pub bk_top: i16,
+    pub bk_right: i16,
+    pub bk_bottom: i16,
+    pub op_left: i16,
+    pub op_top: i16,
+    pub op_right: i16,
+    pub op_bottom: i16,
+    pub brush: Brush,
+    pub x: i16,
+    pub y: i16,
+    pub data: Vec<u8>
+}
+
+/// Glyph sent with a fast glyph or a cache glyph order
+/// It must be stored in the glyph cache
+#[derive(Clone, Debug, Default)]
+pub struct FastGlyph {
+    pub x: i16,
+    pub y: i16,
+    pub cx: u16,
+    pub cy: u16,
+    /// 1 bpp bitmap, rows padded on a byte
+    pub data: Vec<u8>
+}
+
+/// Single glyph text
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.14 FastGlyph (FASTGLYPH_ORDER)
+#[derive(Clone, Debug, Default)]
+pub struct FastGlyphOrder {
+    pub cache_id: u8,
+    pub ul_char_inc: u8,
+    pub fl_accel: u8,
+    pub back_color: [u8; 3],
+    pub fore_color: [u8; 3],
+    pub bk_left: i16,
+    pub bk_top: i16,
+    pub bk_right: i16,
+    pub bk_bottom: i16,
+    pub op_left: i16,
+    pub op_top: i16,
+    pub op_right: i16,
+    pub op_bottom: i16,
+    pub x: i16,
+    pub y: i16,
+    pub cache_index: u8,
+    /// Present when the glyph is not yet cached
+    pub glyph: Option<FastGlyph>
+}
+
+/// A decoded primary drawing order
+#[derive(Clone, Debug)]
+pub enum PrimaryOrder {
+    DstBlt(DstBltOrder),
+    PatBlt(PatBltOrder),
+    ScrBlt(ScrBltOrder),
+    OpaqueRect(OpaqueRectOrder),
+    MultiOpaqueRect(MultiOpaqueRectOrder),
+    MemBlt(MemBltOrder),
+    LineTo(LineToOrder),
+    Polyline(PolylineOrder),
+    GlyphIndex(GlyphIndexOrder),
+    FastGlyph(FastGlyphOrder)
+}
+
+/// Bitmap to store in the bitmap cache
+/// Data is compressed like bitmap updates when is_compress is set
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.2 Cache Bitmap - Revision 1 (CACHE_BITMAP_ORDER)
+/// and 2.2.2.2.1.2.3 Cache Bitmap - Revision 2 (CACHE_BITMAP_REV2_ORDER)
+#[derive(Clone, Debug)]
+pub struct CacheBitmapOrder {
+    pub cache_id: u8,
+    pub cache_index: u16,
+    pub width: u16,
+    pub height: u16,
+    pub bpp: u16,
+    pub is_compress: bool,
+    /// Persistent cache key of revision 2 orders
+    pub key: Option<(u32, u32)>,
+    pub data: Vec<u8>
+}
+
+impl CacheBitmapOrder {
+    /// Bitmap event of the cached bitmap
+    /// Use its decompress functions to get the pixels
+    pub fn to_bitmap(&self) -> BitmapEvent {
+        BitmapEvent {
+            dest_left: 0,
+            dest_top: 0,
+            dest_right: self.width.saturating_sub(1),
+            dest_bottom: self.height.saturating_sub(1),
+            width: self.width,
+            height: self.height,
+            bpp: self.bpp,
+            is_compress: self.is_compress,
+            data: self.data.clone()
+        }
+    }
+}
+
+/// Palette to store in the color table cache
+/// used by 8 bpp MemBlt orders
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.4 Cache Color Table (CACHE_COLOR_TABLE_ORDER)
+#[derive(Clone, Debug)]
+pub struct CacheColorTableOrder {
+    pub cache_index: u8,
+    /// Palette entries as 0x00RRGGBB
+    pub entries: Vec<u32>
+}
+
+/// Glyphs to store in the glyph cache
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.5 Cache Glyph - Revision 1 (CACHE_GLYPH_ORDER)
+/// and 2.2.2.2.1.2.6 Cache Glyph - Revision 2 (CACHE_GLYPH_REV2_ORDER)
+#[derive(Clone, Debug)]
+pub struct CacheGlyphOrder {
+    pub cache_id: u8,
+    /// Cache index and glyph
+    pub glyphs: Vec<(u16, FastGlyph)>,
+    /// Unicode character of each glyph if sent by the server
+    pub unicode: Option<Vec<u16>>
+}
+
+/// Brush to store in the brush cache
+/// Brush data is left encoded
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.7 Cache Brush (CACHE_BRUSH_ORDER)
+#[derive(Clone, Debug)]
+pub struct CacheBrushOrder {
+    pub cache_index: u8,
+    /// Bitmap format (BMF_1BPP to BMF_32BPP)
+    pub format: u8,
+    pub width: u8,
+    pub height: u8,
+    pub style: u8,
+    pub data: Vec<u8>
+}
+
+/// A decoded secondary drawing order
+/// It fills a cache used by next primary orders
+#[derive(Clone, Debug)]
+pub enum SecondaryOrder {
+    CacheBitmap(CacheBitmapOrder),
+    CacheColorTable(CacheColorTableOrder),
+    CacheGlyph(CacheGlyphOrder),
+    CacheBrush(CacheBrushOrder)
+}
+
+/// Read a variable length delta value
+/// One or two bytes, sign is on bit 6
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.1.4 Delta-Encoded Points (DELTA_PTS_FIELD)
+fn read_delta(stream: &mut dyn Read) -> RdpResult<i16> {
+    let byte = stream.read_u8()?;
+    let mut value = if byte & 0x40 != 0 { (byte | 0xC0) as i8 as i16 } else { (byte & 0x3F) as i16 };
+    if byte & 0x80 != 0 {
+        value = (value << 8) | stream.read_u8()? as i16;
+    }
+    Ok(value)
+}
+
+/// Two bytes signed value of a glyph
+/// Sign is a separate bit
+fn read_two_byte_signed(stream: &mut dyn Read) -> RdpResult<i16> {
+    let byte = stream.read_u8()?;
+    let mut value = (byte & 0x3F) as i16;
+    if byte & 0x80 != 0 {
+        value = (value << 8) | stream.read_u8()? as i16;
+    }
+    Ok(if byte & 0x40 != 0 { -value } else { value })
+}
+
+/// Two bytes unsigned value of a glyph or a cached bitmap
+fn read_two_byte_unsigned(stream: &mut dyn Read) -> RdpResult<u16> {
+    let byte = stream.read_u8()?;
+    let mut value = (byte & 0x7F) as u16;
+    if byte & 0x80 != 0 {
+        value = (value << 8) | stream.read_u8()? as u16;
+    }
+    Ok(value)
+}
+
+/// Four bytes unsigned value of a cached bitmap
+/// Number of extra bytes is on bits 6 and 7
+fn read_four_byte_unsigned(stream: &mut dyn Read) -> RdpResult<u32> {
+    let byte = stream.read_u8()?;
+    let mut value = (byte & 0x3F) as u32;
+    for _ in 0..byte >> 6 {
+        value = (value << 8) | stream.read_u8()? as u32;
+    }
+    Ok(value)
+}
+
+/// Size of a 1 bpp glyph bitmap
+/// Rows are padded on a byte and the bitmap on 4 bytes
+fn glyph_size(cx: u16, cy: u16) -> usize {
+    ((cx as usize + 7) / 8 * cy as usize + 3) / 4 * 4
+}
+
+/// Read the data of a secondary order
+/// Length is checked against the order size before allocating
+fn read_order_data(stream: &mut Cursor<&[u8]>, length: usize) -> RdpResult<Vec<u8>> {
+    let start = stream.position() as usize;
+    let data = stream.get_ref().get(start..start.saturating_add(length)).ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "ORDER: Secondary order data overflow")))?.to_vec();
+    stream.set_position((start + length) as u64);
+    Ok(data)
+}
+
+/// Read the length prefixed data of a field
+fn read_variable_data(stream: &mut dyn Read, length: usize) -> RdpResult<Vec<u8>> {
+    let mut data = vec![0; length];
+    stream.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Rectangles of a multi opaque rect order
+/// Each rectangle is relative to the previous one
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.1.5 Delta-Encoded Rectangles (DELTA_RECTS_FIELD)
+fn read_delta_rects(data: &[u8], number: u8) -> RdpResult<Vec<DeltaRect>> {
+    let mut stream = Cursor::new(data);
+    let zero_bits = read_variable_data(&mut stream, (number as usize + 1) / 2)?;
+    let mut rects: Vec<DeltaRect> = Vec::with_capacity(number as usize);
+    let mut flags = 0;
+    for i in 0..number as usize {
+        if i % 2 == 0 {
+            flags = zero_bits[i / 2];
+        }
+        let previous = rects.last().copied().unwrap_or_default();
+        rects.push(DeltaRect {
+            left: previous.left.wrapping_add(if flags & 0x80 == 0 { read_delta(&mut stream)? } else { 0 }),
+            top: previous.top.wrapping_add(if flags & 0x40 == 0 { read_delta(&mut stream)? } else { 0 }),
+            width: if flags & 0x20 == 0 { read_delta(&mut stream)? } else { previous.width },
+            height: if flags & 0x10 == 0 { read_delta(&mut stream)? } else { previous.height }
+        });
+        flags <<= 4;
+    }
+    Ok(rects)
+}
+
+/// Points of a polyline order
+/// Each point is relative to the previous one
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.1.4 Delta-Encoded Points (DELTA_PTS_FIELD)
+fn read_delta_points(data: &[u8], number: u8, start_x: i16, start_y: i16) -> RdpResult<Vec<(i16, i16)>> {
+    let mut stream = Cursor::new(data);
+    let zero_bits = read_variable_data(&mut stream, (number as usize + 3) / 4)?;
+    let mut points = Vec::with_capacity(number as usize);
+    let (mut x, mut y) = (start_x, start_y);
+    let mut flags = 0;
+    for i in 0..number as usize {
+        if i % 4 == 0 {
+            flags = zero_bits[i / 4];
+        }
+        if flags & 0x80 == 0 {
+            x = x.wrapping_add(read_delta(&mut stream)?);
+        }
+        if flags & 0x40 == 0 {
+            y = y.wrapping_add(read_delta(&mut stream)?);
+        }
+        points.push((x, y));
+        flags <<= 2;
+    }
+    Ok(points)
+}
+
+/// Data of a fast glyph order
+/// A cache index optionally followed by the glyph
+///
+/// See MS-RDPEGDI 2.2.2.2.1.1.2.14 FastGlyph (FASTGLYPH_ORDER)
+fn read_fast_glyph_data(data: &[u8]) -> RdpResult<(u8, Option<FastGlyph>)> {
+    let mut stream = Cursor::new(data);
+    let cache_index = stream.read_u8()?;
+    if data.len() == 1 {
+        return Ok((cache_index, None))
+    }
+
+    let x = read_two_byte_signed(&mut stream)?;
+    let y = read_two_byte_signed(&mut stream)?;
+    let cx = read_two_byte_unsigned(&mut stream)?;
+    let cy = read_two_byte_unsigned(&mut stream)?;
+    Ok((cache_index, Some(FastGlyph {
+        x,
+        y,
+        cx,
+        cy,
+        data: read_variable_data(&mut stream, glyph_size(cx, cy))?
+    })))
+}
+
+/// Cache bitmap revision 1 order
+/// The compression header is dropped
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.2 Cache Bitmap - Revision 1 (CACHE_BITMAP_ORDER)
+fn read_cache_bitmap(stream: &mut Cursor<&[u8]>, extra_flags: u16, is_compress: bool) -> RdpResult<CacheBitmapOrder> {
+    let cache_id = stream.read_u8()?;
+    // pad1Octet
+    stream.read_u8()?;
+    let width = stream.read_u8()? as u16;
+    let height = stream.read_u8()? as u16;
+    let bpp = stream.read_u8()? as u16;
+    let mut length = stream.read_u16::<LittleEndian>()? as usize;
+    let cache_index = stream.read_u16::<LittleEndian>()?;
+    if is_compress && extra_flags & SecondaryOrderFlag::NoBitmapCompressionHdr as u16 == 0 {
+        // bitmapLength includes the compression header
+        length = length.checked_sub(8).ok_or_else(|| Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "ORDER: Invalid cache bitmap length")))?;
+        read_order_data(stream, 8)?;
+    }
+    Ok(CacheBitmapOrder {
+        cache_id,
+        cache_index,
+        width,
+        height,
+        bpp,
+        is_compress,
+        key: None,
+        data: read_order_data(stream, length)?
+    })
+}
+
+/// Cache bitmap revision 2 order
+/// Cache id, bpp and flags are in extra flags
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.3 Cache Bitmap - Revision 2 (CACHE_BITMAP_REV2_ORDER)
+fn read_cache_bitmap_rev2(stream: &mut Cursor<&[u8]>, extra_flags: u16, is_compress: bool) -> RdpResult<CacheBitmapOrder> {
+    let flags = extra_flags >> 7;
+    let bpp = match (extra_flags >> 3) & 0x0F {
+        0x03 => 8,
+        0x04 => 16,
+        0x05 => 24,
+        0x06 => 32,
+        bpp_id => return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, &format!("ORDER: Invalid cache bitmap bpp id {}", bpp_id))))
+    };
+    let key = if flags & CacheBitmapRev2Flag::Cbr2PersistentKeyPresent as u16 != 0 {
+        Some((stream.read_u32::<LittleEndian>()?, stream.read_u32::<LittleEndian>()?))
+    } else {
+        None
+    };
+    let width = read_two_byte_unsigned(stream)?;
+    let height = if flags & CacheBitmapRev2Flag::Cbr2HeightSameAsWidth as u16 != 0 { width } else { read_two_byte_unsigned(stream)? };
+    let mut length = read_four_byte_unsigned(stream)? as usize;
+    let cache_index = read_two_byte_unsigned(stream)?;
+    if is_compress && flags & CacheBitmapRev2Flag::Cbr2NoBitmapCompressionHdr as u16 == 0 {
+        // cbCompFirstRowSize then cbCompMainBodySize, cbScanWidth and cbUncompressedSize
+        stream.read_u16::<LittleEndian>()?;
+        length = stream.read_u16::<LittleEndian>()? as usize;
+        stream.read_u32::<LittleEndian>()?;
+    }
+    Ok(CacheBitmapOrder {
+        cache_id: (extra_flags & 0x07) as u8,
+        cache_index,
+        width,
+        height,
+        bpp,
+        is_compress,
+        key,
+        data: read_order_data(stream, length)?
+    })
+}
+
+/// Cache color table order
+/// Entries are blue, green, red and a pad byte
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.4 Cache Color Table (CACHE_COLOR_TABLE_ORDER)
+fn read_cache_color_table(stream: &mut Cursor<&[u8]>) -> RdpResult<CacheColorTableOrder> {
+    let cache_index = stream.read_u8()?;
+    let number_colors = stream.read_u16::<LittleEndian>()? as usize;
+    let data = read_order_data(stream, number_colors * 4)?;
+    Ok(CacheColorTableOrder {
+        cache_index,
+        entries: data.chunks_exact(4).map(|quad| (quad[2] as u32) << 16 | (quad[1] as u32) << 8 | quad[0] as u32).collect()
+    })
+}
+
+/// Cache glyph order
+/// Revision 2 is used when the client advertise GlyphSupportEncode
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.5 Cache Glyph - Revision 1 (CACHE_GLYPH_ORDER)
+/// and 2.2.2.2.1.2.6 Cache Glyph - Revision 2 (CACHE_GLYPH_REV2_ORDER)
+fn read_cache_glyph(stream: &mut Cursor<&[u8]>, extra_flags: u16, glyph_v2: bool) -> RdpResult<CacheGlyphOrder> {
+    let (cache_id, number_glyphs) = if glyph_v2 {
+        ((extra_flags & 0x0F) as u8, (extra_flags >> 8) as u8)
+    } else {
+        (stream.read_u8()?, stream.read_u8()?)
+    };
+
+    let mut glyphs = Vec::with_capacity(number_glyphs as usize);
+    for _ in 0..number_glyphs {
+        let (cache_index, x, y, cx, cy) = if glyph_v2 {
+            (stream.read_u8()? as u16, read_two_byte_signed(stream)?, read_two_byte_signed(stream)?, read_two_byte_unsigned(stream)?, read_two_byte_unsigned(stream)?)
+        } else {
+            (stream.read_u16::<LittleEndian>()?, stream.read_i16::<LittleEndian>()?, stream.read_i16::<LittleEndian>()?, stream.read_u16::<LittleEndian>()?, stream.read_u16::<LittleEndian>()?)
+        };
+        glyphs.push((cache_index, FastGlyph {
+            x,
+            y,
+            cx,
+            cy,
+            data: read_order_data(stream, glyph_size(cx, cy))?
+        }));
+    }
+
+    // same bit in both revisions
+    let unicode = if extra_flags & SecondaryOrderFlag::CgGlyphUnicodePresent as u16 != 0 {
+        let mut unicode = Vec::with_capacity(number_glyphs as usize);
+        for _ in 0..number_glyphs {
+            unicode.push(stream.read_u16::<LittleEndian>()?);
+        }
+        Some(unicode)
+    } else {
+        None
+    };
+
+    Ok(CacheGlyphOrder {
+        cache_id,
+        glyphs,
+        unicode
+    })
+}
+
+/// Cache brush order
+///
+/// See MS-RDPEGDI 2.2.2.2.1.2.7 Cache Brush (CACHE_BRUSH_ORDER)
+fn read_cache_brush(stream: &mut Cursor<&[u8]>) -> RdpResult<CacheBrushOrder> {
+    let cache_index = stream.read_u8()?;
+    let format = stream.read_u8()?;
+    let width = stream.read_u8()?;
+    let height = stream.read_u8()?;
+    let style = stream.read_u8()?;
+    let length = stream.read_u8()? as usize;
+    Ok(CacheBrushOrder {
+        cache_index,
+        format,
+        width,
+        height,
+        style,
+        data: read_order_data(stream, length)?
+    })
+}
+
+/// Read one side of the bounds
+/// Bits 0 to 3 are absolute values, bits 4 to 7 are deltas
+fn read_bound(stream: &mut dyn Read, flags: u8, side: u8, bound: &mut i16) -> RdpResult<()> {
+    if flags & (0x01 << side) != 0 {
+        *bound = stream.read_i16::<LittleEndian>()?;
+    } else if flags & (0x10 << side) != 0 {
+        *bound = bound.wrapping_add(stream.read_i8()? as i16);
+    }
+    Ok(())
+}
+
+/// Read fields present in the field flags
+/// Absent fields keep the value of the previous order
+struct FieldReader<'a> {
+    stream: &'a mut dyn Read,
+    flags: u32,
+    /// Coordinates are one byte deltas
+    delta: bool
+}
+
+impl<'a> FieldReader<'a> {
+    /// Fields are numbered from 1
+    fn is_present(&self, field: u32) -> bool {
+        self.flags & (1 << (field - 1)) != 0
+    }
+
+    fn coord(&mut self, field: u32, value: &mut i16) -> RdpResult<()> {
+        if self.is_present(field) {
+            *value = if self.delta { value.wrapping_add(self.stream.read_i8()? as i16) } else { self.stream.read_i16::<LittleEndian>()? };
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, field: u32, value: &mut u8) -> RdpResult<()> {
+        if self.is_present(field) {
+            *value = self.stream.read_u8()?;
+        }
+        Ok(())
+    }
+
+    fn signed_byte(&mut self, field: u32, value: &mut i8) -> RdpResult<()> {
+        if self.is_present(field) {
+            *value = self.stream.read_i8()?;
+        }
+        Ok(())
+    }
+
+    fn word(&mut self, field: u32, value: &mut u16) -> RdpResult<()> {
+        if self.is_present(field) {
+            *value = self.stream.read_u16::<LittleEndian>()?;
+        }
+        Ok(())
+    }
+
+    fn signed_word(&mut self, field: u32, value: &mut i16) -> RdpResult<()> {
+        if self.is_present(field) {
+            *value = self.stream.read_i16::<LittleEndian>()?;
+        }
+        Ok(())
+    }
+
+    fn color(&mut self, field: u32, value: &mut [u8; 3]) -> RdpResult<()> {
+        if self.is_present(field) {
+            self.stream.read_exact(value)?;
+        }
+        Ok(())
+    }
+
+    /// Brush fields starting at field
+    fn brush(&mut self, field: u32, brush: &mut Brush) -> RdpResult<()> {
+        self.signed_byte(field, &mut brush.x)?;
+        self.signed_byte(field + 1, &mut brush.y)?;
+        self.byte(field + 2, &mut brush.style)?;
+        self.byte(field + 3, &mut brush.hatch)?;
+        if self.is_present(field + 4) {
+            self.stream.read_exact(&mut brush.extra)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decoder of drawing orders
+/// Each primary order is encoded against the previous order of the same type
+/// so the decoder keeps the last value of each field and the last bounds
+///
+/// Secondary orders fill the caches used by MemBlt, glyph
+/// and cached brush orders, they are returned in stream order
+/// Secondary orders not handled are skipped
+/// Alternate secondary orders and other primary orders are not supported
+/// and stop the decoding
+///
+/// See MS-RDPEGDI 3.2.5.1 Processing Drawing Orders
+///
+/// # Example
+/// ```
+/// use rdp::core::event::{RdpEvent, OrderEvent};
+/// use rdp::core::order::{OrderDecoder, PrimaryOrder};
+/// let mut decoder = OrderDecoder::new();
+/// // type change to OpaqueRect, all fields present
+/// let orders = decoder.decode(1, &[0x09, 0x0A, 0x7F, 10, 0, 20, 0, 30, 0, 40, 0, 0xFF, 0, 0]).unwrap();
+/// match &orders[0] {
+///     RdpEvent::Order(OrderEvent { order: PrimaryOrder::OpaqueRect(rect), .. }) => assert_eq!((rect.left, rect.top, rect.width, rect.height, rect.color), (10, 20, 30, 40, [0xFF, 0, 0])),
+///     _ => panic!("Invalid order")
+/// }
+/// ```
+pub struct OrderDecoder {
+    /// Type of the last primary order
+    order_type: PrimaryOrderType,
+    /// Last bounds
+    bounds: OrderBounds,
+    dst_blt: DstBltOrder,
+    pat_blt: PatBltOrder,
+    scr_blt: ScrBltOrder,
+    opaque_rect: OpaqueRectOrder,
+    multi_opaque_rect: MultiOpaqueRectOrder,
+    /// Number of rectangles of the last multi opaque rect order
+    number_rects: u8,
+    mem_blt: MemBltOrder,
+    line_to: LineToOrder,
+    polyline: PolylineOrder,
+    /// Number of points of the last polyline order
+    number_points: u8,
+    glyph_index: GlyphIndexOrder,
+    fast_glyph: FastGlyphOrder,
+    /// Cache glyph orders use revision 2
+    glyph_v2: bool
+}
+
+impl OrderDecoder {
+    /// Orders start with PatBlt as previous type
+    pub fn new() -> Self {
+        OrderDecoder {
+            order_type: PrimaryOrderType::TsEncPatbltOrder,
+            bounds: OrderBounds::default(),
+            dst_blt: DstBltOrder::default(),
+            pat_blt: PatBltOrder::default(),
+            scr_blt: ScrBltOrder::default(),
+            opaque_rect: OpaqueRectOrder::default(),
+            multi_opaque_rect: MultiOpaqueRectOrder::default(),
+            number_rects: 0,
+            mem_blt: MemBltOrder::default(),
+            line_to: LineToOrder::default(),
+            polyline: PolylineOrder::default(),
+            number_points: 0,
+            glyph_index: GlyphIndexOrder::default(),
+            fast_glyph: FastGlyphOrder::default(),
+            glyph_v2: false
+        }
+    }
+
+    /// Server sends cache glyph revision 2 orders
+    /// when the client advertise GlyphSupportEncode
+    pub fn glyph_v2(mut self, glyph_v2: bool) -> Self {
+        self.glyph_v2 = glyph_v2;
+        self
+    }
+
+    /// Forget the fields of previous primary orders
+    /// Used on reactivation or when an order can't be decoded
+    pub fn reset(&mut self) {
+        *self = OrderDecoder::new().glyph_v2(self.glyph_v2);
+    }
+
+    /// Decode number_orders drawing orders
+    /// Primary orders are returned as Order events
+    /// and secondary orders as CacheOrder events
+    pub fn decode(&mut self, number_orders: u16, data: &[u8]) -> RdpResult<Vec<RdpEvent>> {
+        let mut stream = Cursor::new(data);
+        let mut result = Vec::new();
+
+        for _ in 0..number_orders {
+            let control_flags = stream.read_u8()?;
+            if control_flags & ControlFlag::TsStandard as u8 == 0 {
+                return Err(Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, "ORDER: Alternate secondary orders are not supported")))
+            }
+
+            if control_flags & ControlFlag::TsSecondary as u8 != 0 {
+                // orderLength is the order size minus 13
+                let order_length = stream.read_i16::<LittleEndian>()? as i64;
+                let extra_flags = stream.read_u16::<LittleEndian>()?;
+                let order_type = stream.read_u8()?;
+                let start = stream.position() as i64;
+                let next = start + order_length + 7;
+                if order_length + 7 < 0 || next as usize > data.len() {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidSize, "ORDER: Invalid secondary order length")))
+                }
+                if let Some(order) = self.read_secondary(order_type, extra_flags, &data[start as usize..next as usize])? {
+                    result.push(RdpEvent::CacheOrder(order));
+                }
+                stream.set_position(next as u64);
+                continue;
+            }
+
+            result.push(RdpEvent::Order(self.read_primary(control_flags, &mut stream)?));
+        }
+        Ok(result)
+    }
+
+    /// Read a secondary order from its data
+    /// None if the order is not handled
+    fn read_secondary(&self, order_type: u8, extra_flags: u16, data: &[u8]) -> RdpResult<Option<SecondaryOrder>> {
+        let mut stream = Cursor::new(data);
+        let order = match SecondaryOrderType::try_from(order_type) {
+            Ok(SecondaryOrderType::TsCacheBitmapUncompressed) => SecondaryOrder::CacheBitmap(read_cache_bitmap(&mut stream, extra_flags, false)?),
+            Ok(SecondaryOrderType::TsCacheBitmapCompressed) => SecondaryOrder::CacheBitmap(read_cache_bitmap(&mut stream, extra_flags, true)?),
+            Ok(SecondaryOrderType::TsCacheBitmapUncompressedRev2) => SecondaryOrder::CacheBitmap(read_cache_bitmap_rev2(&mut stream, extra_flags, false)?),
+            Ok(SecondaryOrderType::TsCacheBitmapCompressedRev2) => SecondaryOrder::CacheBitmap(read_cache_bitmap_rev2(&mut stream, extra_flags, true)?),
+            Ok(SecondaryOrderType::TsCacheColorTable) => SecondaryOrder::CacheColorTable(read_cache_color_table(&mut stream)?),
+            Ok(SecondaryOrderType::TsCacheGlyph) => SecondaryOrder::CacheGlyph(read_cache_glyph(&mut stream, extra_flags, self.glyph_v2)?),
+            Ok(SecondaryOrderType::TsCacheBrush) => SecondaryOrder::CacheBrush(read_cache_brush(&mut stream)?),
+            Err(_) => return Ok(None)
+        };
+        Ok(Some(order))
+    }
+
+    /// Read the header of a primary order
+    /// then its fields
+    fn read_primary(&mut self, control_flags: u8, stream: &mut dyn Read) -> RdpResult<OrderEvent> {
+        if control_flags & ControlFlag::TsTypeChange as u8 != 0 {
+            let order_type = stream.read_u8()?;
+            self.order_type = PrimaryOrderType::try_from(order_type).map_err(|_| Error::RdpError(RdpError::new(RdpErrorKind::NotImplemented, &format!("ORDER: Primary order not supported {:x}", order_type))))?;
+        }
+
+        let mut field_bytes = self.order_type.field_bytes();
+        if control_flags & ControlFlag::TsZeroFieldByteBit0 as u8 != 0 {
+            field_bytes = field_bytes.saturating_sub(1);
+        }
+        if control_flags & ControlFlag::TsZeroFieldByteBit1 as u8 != 0 {
+            field_bytes = field_bytes.saturating_sub(2);
+        }
+        let mut flags = 0;
+        for i in 0..field_bytes {
+            flags |= (stream.read_u8()? as u32) << (i * 8);
+        }
+
+        let bounds = if control_flags & ControlFlag::TsBounds as u8 != 0 {
+            if control_flags & ControlFlag::TsZeroBoundsDeltas as u8 == 0 {
+                self.read_bounds(stream)?;
+            }
+            Some(self.bounds)
+        } else {
+            None
+        };
+
+        let mut fields = FieldReader {
+            stream,
+            flags,
+            delta: control_flags & ControlFlag::TsDeltaCoordinates as u8 != 0
+        };
+
+        let order = match self.order_type {
+            PrimaryOrderType::TsEncDstbltOrder => {
+                let order = &mut self.dst_blt;
+                fields.coord(1, &mut order.left)?;
+                fields.coord(2, &mut order.top)?;
+                fields.coord(3, &mut order.width)?;
+                fields.coord(4, &mut order.height)?;
+                fields.byte(5, &mut order.rop)?;
+                PrimaryOrder::DstBlt(order.clone())
+            },
+            PrimaryOrderType::TsEncPatbltOrder => {
+                let order = &mut self.pat_blt;
+                fields.coord(1, &mut order.left)?;
+                fields.coord(2, &mut order.top)?;
+                fields.coord(3, &mut order.width)?;
+                fields.coord(4, &mut order.height)?;
+                fields.byte(5, &mut order.rop)?;
+                fields.color(6, &mut order.back_color)?;
+                fields.color(7, &mut order.fore_color)?;
+                fields.brush(8, &mut order.brush)?;
+                PrimaryOrder::PatBlt(order.clone())
+            },
+            PrimaryOrderType::TsEncScrbltOrder => {
+                let order = &mut self.scr_blt;
+                fields.coord(1, &mut order.left)?;
+                fields.coord(2, &mut order.top)?;
+                fields.coord(3, &mut order.width)?;
+                fields.coord(4, &mut order.height)?;
+                fields.byte(5, &mut order.rop)?;
+                fields.coord(6, &mut order.src_x)?;
+                fields.coord(7, &mut order.src_y)?;
+                PrimaryOrder::ScrBlt(order.clone())
+            },
+            PrimaryOrderType::TsEncOpaquerectOrder => {
+                let order = &mut self.opaque_rect;
+                fields.coord(1, &mut order.left)?;
+                fields.coord(2, &mut order.top)?;
+                fields.coord(3, &mut order.width)?;
+                fields.coord(4, &mut order.height)?;
+                // each color component is a field
+                fields.byte(5, &mut order.color[0])?;
+                fields.byte(6, &mut order.color[1])?;
+                fields.byte(7, &mut order.color[2])?;
+                PrimaryOrder::OpaqueRect(order.clone())
+            },
+            PrimaryOrderType::TsEncMultiopaquerectOrder => {
+                let order = &mut self.multi_opaque_rect;
+                fields.coord(1, &mut order.left)?;
+                fields.coord(2, &mut order.top)?;
+                fields.coord(3, &mut order.width)?;
+                fields.coord(4, &mut order.height)?;
+                fields.byte(5, &mut order.color[0])?;
+                fields.byte(6, &mut order.color[1])?;
+                fields.byte(7, &mut order.color[2])?;
+                fields.byte(8, &mut self.number_rects)?;
+                if self.number_rects > MAX_DELTA_RECTS {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "ORDER: Too many rectangles")))
+                }
+                if fields.is_present(9) {
+                    let length = fields.stream.read_u16::<LittleEndian>()?;
+                    order.rects = read_delta_rects(&read_variable_data(fields.stream, length as usize)?, self.number_rects)?;
+                }
+                PrimaryOrder::MultiOpaqueRect(order.clone())
+            },
+            PrimaryOrderType::TsEncMembltOrder => {
+                let order = &mut self.mem_blt;
+                fields.word(1, &mut order.cache_id)?;
+                fields.coord(2, &mut order.left)?;
+                fields.coord(3, &mut order.top)?;
+                fields.coord(4, &mut order.width)?;
+                fields.coord(5, &mut order.height)?;
+                fields.byte(6, &mut order.rop)?;
+                fields.coord(7, &mut order.src_x)?;
+                fields.coord(8, &mut order.src_y)?;
+                fields.word(9, &mut order.cache_index)?;
+                PrimaryOrder::MemBlt(order.clone())
+            },
+            PrimaryOrderType::TsEncLinetoOrder => {
+                let order = &mut self.line_to;
+                fields.word(1, &mut order.back_mode)?;
+                fields.coord(2, &mut order.start_x)?;
+                fields.coord(3, &mut order.start_y)?;
+                fields.coord(4, &mut order.end_x)?;
+                fields.coord(5, &mut order.end_y)?;
+                fields.color(6, &mut order.back_color)?;
+                fields.byte(7, &mut order.rop2)?;
+                fields.byte(8, &mut order.pen_style)?;
+                fields.byte(9, &mut order.pen_width)?;
+                fields.color(10, &mut order.pen_color)?;
+                PrimaryOrder::LineTo(order.clone())
+            },
+            PrimaryOrderType::TsEncPolylineOrder => {
+                let order = &mut self.polyline;
+                fields.coord(1, &mut order.start_x)?;
+                fields.coord(2, &mut order.start_y)?;
+                fields.byte(3, &mut order.rop2)?;
+                fields.word(4, &mut order.brush_cache_entry)?;
+                fields.color(5, &mut order.pen_color)?;
+                fields.byte(6, &mut self.number_points)?;
+                if self.number_points > MAX_DELTA_POINTS {
+                    return Err(Error::RdpError(RdpError::new(RdpErrorKind::InvalidData, "ORDER: Too many points")))
+                }
+                if fields.is_present(7) {
+                    let length = fields.stream.read_u8()?;
+                    order.points = read_delta_points(&read_variable_data(fields.stream, length as usize)?, self.number_points, order.start_x, order.start_y)?;
+                }
+                PrimaryOrder::Polyline(order.clone())
+            },
+            PrimaryOrderType::TsEncIndexOrder => {
+                let order = &mut self.glyph_index;
+                fields.byte(1, &mut order.cache_id)?;
+                fields.byte(2, &mut order.fl_accel)?;
+                fields.byte(3, &mut order.ul_char_inc)?;
+                fields.byte(4, &mut order.op_redundant)?;
+                fields.color(5, &mut order.back_color)?;
+                fields.color(6, &mut order.fore_color)?;
+                fields.signed_word(7, &mut order.bk_left)?;
+                fields.signed_word(8, &mut order.bk_top)?;
+                fields.signed_word(9, &mut order.bk_right)?;
+                fields.signed_word(10, &mut order.bk_bottom)?;
+                fields.signed_word(11, &mut order.op_left)?;
+                fields.signed_word(12, &mut order.op_top)?;
+                fields.signed_word(13, &mut order.op_right)?;
+                fields.signed_word(14, &mut order.op_bottom)?;
+                fields.brush(15, &mut order.brush)?;
+                fields.signed_word(20, &mut order.x)?;
+                fields.signed_word(21, &mut order.y)?;
+                if fields.is_present(22) {
+                    let length = fields.stream.read_u8()?;
+                    order.data = read_variable_data(fields.stream, length as usize)?;
+                }
+                PrimaryOrder::GlyphIndex(order.clone())
+            },
+            PrimaryOrderType::TsEncFastGlyphOrder => {
+                let order = &mut self.fast_glyph;
+                fields.byte(1, &mut order.cache_id)?;
+                if fields.is_present(2) {
+                    order.ul_char_inc = fields.stream.read_u8()?;
+                    order.fl_accel = fields.stream.read_u8()?;
+                }
+                fields.color(3, &mut order.back_color)?;
+                fields.color(4, &mut order.fore_color)?;
+                fields.coord(5, &mut order.bk_left)?;
+                fields.coord(6, &mut order.bk_top)?;
+                fields.coord(7, &mut order.bk_right)?;
+                fields.coord(8, &mut order.bk_bottom)?;
+                fields.coord(9, &mut order.op_left)?;
+                fields.coord(10, &mut order.op_top)?;
+                fields.coord(11, &mut order.op_right)?;
+                fields.coord(12, &mut order.op_bottom)?;
+                fields.coord(13, &mut order.x)?;
+                fields.coord(14, &mut order.y)?;
+                if fields.is_present(15) {
+                    let length = fields.stream.read_u8()?;
+                    let (cache_index, glyph) = read_fast_glyph_data(&read_variable_data(fields.stream, length as usize)?)?;
+                    order.cache_index = cache_index;
+                    order.glyph = glyph;
+                }
+                PrimaryOrder::FastGlyph(order.clone())
+            }
+        };
+
+        Ok(OrderEvent {
+            order,
+            bounds
+        })
+    }
+
+    /// Update bounds from absolute values or one byte deltas
+    ///
+    /// See MS-RDPEGDI 2.2.2.2.1.1.1.1 Bounds (TS_BOUNDS)
+    fn read_bounds(&mut self, stream: &mut dyn Read) -> RdpResult<()> {
+        let flags = stream.read_u8()?;
+        read_bound(stream, flags, 0, &mut self.bounds.left)?;
+        read_bound(stream, flags, 1, &mut self.bounds.top)?;
+        read_bound(stream, flags, 2, &mut self.bounds.right)?;
+        read_bound(stream, flags, 3, &mut self.bounds.bottom)
+    }
+}
+
+impl Default for OrderDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Primary order of a decoded event
+    fn primary(event: &RdpEvent) -> &OrderEvent {
+        match event {
+            RdpEvent::Order(order) => order,
+            _ => panic!("Not a primary order")
+        }
+    }
+
+    /// Secondary order of a decoded event
+    fn secondary(event: &RdpEvent) -> &SecondaryOrder {
+        match event {
+            RdpEvent::CacheOrder(order) => order,
+            _ => panic!("Not a secondary order")
+        }
+    }
+
+    #[test]
+    fn test_read_delta() {
+        assert_eq!(read_delta(&mut Cursor::new(&[0x05])).unwrap(), 5);
+        assert_eq!(read_delta(&mut Cursor::new(&[0x7F])).unwrap(), -1);
+        assert_eq!(read_delta(&mut Cursor::new(&[0x81, 0x00])).unwrap(), 256);
+        assert_eq!(read_delta(&mut Cursor::new(&[0xFF, 0xFE])).unwrap(), -2);
+    }
+
+    /// Fields absent or delta encoded reuse the previous order
+    #[test]
+    fn test_delta_coordinates() {
+        let mut decoder = OrderDecoder::new();
+        // DstBlt with all fields
+        decoder.decode(1, &[0x09, 0x00, 0x1F, 10, 0, 20, 0, 30, 0, 40, 0, 0x55]).unwrap();
+        // same type, delta left and top only
+        let orders = decoder.decode(1, &[0x11, 0x03, 0xFE, 5]).unwrap();
+        match &primary(&orders[0]).order {
+            PrimaryOrder::DstBlt(order) => assert_eq!((order.left, order.top, order.width, order.height, order.rop), (8, 25, 30, 40, 0x55)),
+            _ => panic!("Invalid order")
+        }
+    }
+
+    /// Bounds are kept between orders
+    #[test]
+    fn test_bounds() {
+        let mut decoder = OrderDecoder::new();
+        // PatBlt with absolute left and right, zero field bytes
+        let orders = decoder.decode(1, &[0x05 | 0x80, 0x05, 1, 0, 100, 0]).unwrap();
+        assert_eq!(primary(&orders[0]).bounds, Some(OrderBounds { left: 1, top: 0, right: 100, bottom: 0 }));
+        // delta bottom then zero bounds deltas
+        let orders = decoder.decode(2, &[0x05 | 0x80, 0x80, 50, 0x25 | 0x80]).unwrap();
+        assert_eq!(primary(&orders[1]).bounds, Some(OrderBounds { left: 1, top: 0, right: 100, bottom: 50 }));
+        assert!(primary(&decoder.decode(1, &[0x01 | 0x80]).unwrap()[0]).bounds.is_none());
+    }
+
+    #[test]
+    fn test_multi_opaque_rect() {
+        let mut decoder = OrderDecoder::new();
+        let orders = decoder.decode(1, &[
+            0x09, 0x12, 0x80, 0x01,
+            // numRectangles
+            2,
+            // cbData, zero bits then rects
+            7, 0, 0x03,
+            10, 20, 5, 6,
+            1, 1
+        ]).unwrap();
+        match &primary(&orders[0]).order {
+            PrimaryOrder::MultiOpaqueRect(order) => assert_eq!(order.rects, [DeltaRect { left: 10, top: 20, width: 5, height: 6 }, DeltaRect { left: 11, top: 21, width: 5, height: 6 }]),
+            _ => panic!("Invalid order")
+        }
+    }
+
+    #[test]
+    fn test_polyline() {
+        let mut decoder = OrderDecoder::new();
+        let orders = decoder.decode(1, &[
+            0x09, 0x16, 0x63, 100, 0, 50, 0,
+            // numDeltaEntries
+            2,
+            // cbData, zero bits then points
+            4, 0x40, 10, 0x7E, 3
+        ]).unwrap();
+        match &primary(&orders[0]).order {
+            PrimaryOrder::Polyline(order) => assert_eq!(order.points, [(110, 50), (108, 53)]),
+            _ => panic!("Invalid order")
+        }
+    }
+
+    #[test]
+    fn test_fast_glyph() {
+        let mut decoder = OrderDecoder::new();
+        let orders = decoder.decode(1, &[
+            0x19, 0x18, 0x00, 0x40,
+            // cbData, cacheIndex, x, y, cx, cy then bitmap
+            9, 7, 0x41, 2, 8, 2, 0xFF, 0x81, 0, 0
+        ]).unwrap();
+        match &primary(&orders[0]).order {
+            PrimaryOrder::FastGlyph(order) => {
+                assert_eq!(order.cache_index, 7);
+                let glyph = order.glyph.as_ref().unwrap();
+                assert_eq!((glyph.x, glyph.y, glyph.cx, glyph.cy), (-1, 2, 8, 2));
+                assert_eq!(glyph.data, [0xFF, 0x81, 0, 0]);
+            },
+            _ => panic!("Invalid order")
+        }
+    }
+
+    /// Secondary orders not handled are skipped
+    #[test]
+    fn test_skip_secondary() {
+        let mut decoder = OrderDecoder::new();
+        // cache bitmap revision 3
+        let orders = decoder.decode(2, &[
+            0x03, 0xFB, 0xFF, 0, 0, 0x08, 0xAA, 0xAA,
+            0x09, 0x00, 0x10, 0x66
+        ]).unwrap();
+        assert_eq!(orders.len(), 1);
+        assert!(decoder.decode(1, &[0x02]).is_err());
+        assert!(decoder.decode(1, &[0x09, 0x04]).is_err());
+    }
+
+    #[test]
+    fn test_cache_bitmap() {
+        let mut decoder = OrderDecoder::new();
+        // revision 1 compressed with its compression header
+        let orders = decoder.decode(1, &[
+            0x03, 0x0C, 0x00, 0x00, 0x00, 0x02,
+            1, 0, 2, 2, 16, 10, 0, 5, 0,
+            0, 0, 2, 0, 4, 0, 8, 0,
+            0xAB, 0xCD
+        ]).unwrap();
+        match secondary(&orders[0]) {
+            SecondaryOrder::CacheBitmap(bitmap) => {
+                assert_eq!((bitmap.cache_id, bitmap.cache_index, bitmap.width, bitmap.height, bitmap.bpp, bitmap.is_compress), (1, 5, 2, 2, 16, true));
+                assert_eq!(bitmap.data, [0xAB, 0xCD]);
+                assert_eq!(bitmap.to_bitmap().dest_right, 1);
+            },
+            _ => panic!("Invalid order")
+        }
+
+        // revision 2 uncompressed, 32 bpp in cache 2 with same width and height
+        let orders = decoder.decode(1, &[
+            0x03, 0x01, 0x00, 0xB2, 0x00, 0x04,
+            1, 4, 0x80, 0x03,
+            1, 2, 3, 4
+        ]).unwrap();
+        match secondary(&orders[0]) {
+            SecondaryOrder::CacheBitmap(bitmap) => {
+                assert_eq!((bitmap.cache_id, bitmap.cache_index, bitmap.width, bitmap.height, bitmap.bpp, bitmap.key), (2, 3, 1, 1, 32, None));
+                assert_eq!(bitmap.data, [1, 2, 3, 4]);
+            },
+            _ => panic!("Invalid order")
+        }
+
+        // bitmap length overflow the order
+        assert!(decoder.decode(1, &[0x03, 0xFF, 0xFF, 0xB2, 0x00, 0x04, 1, 0xC0, 0xFF, 0xFF, 0xFF, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_cache_glyph() {
+        // revision 1 with unicode characters
+        let orders = OrderDecoder::new().decode(1, &[
+            0x03, 0x0B, 0x00, 0x10, 0x00, 0x03,
+            7, 1,
+            3, 0, 0xFF, 0xFF, 2, 0, 8, 0, 2, 0, 0xFF, 0x81, 0, 0,
+            0x41, 0
+        ]).unwrap();
+        match secondary(&orders[0]) {
+            SecondaryOrder::CacheGlyph(glyph) => {
+                assert_eq!(glyph.cache_id, 7);
+                let (cache_index, glyph_data) = &glyph.glyphs[0];
+                assert_eq!((*cache_index, glyph_data.x, glyph_data.y, glyph_data.cx, glyph_data.cy), (3, -1, 2, 8, 2));
+                assert_eq!(glyph_data.data, [0xFF, 0x81, 0, 0]);
+                assert_eq!(glyph.unicode, Some(vec![0x41]));
+            },
+            _ => panic!("Invalid order")
+        }
+
+        // revision 2, cache id and number of glyphs in extra flags
+        let orders = OrderDecoder::new().glyph_v2(true).decode(1, &[
+            0x03, 0x02, 0x00, 0x07, 0x01, 0x03,
+            3, 0x41, 2, 8, 2, 0xFF, 0x81, 0, 0
+        ]).unwrap();
+        match secondary(&orders[0]) {
+            SecondaryOrder::CacheGlyph(glyph) => {
+                assert_eq!((glyph.cache_id, glyph.glyphs[0].0, glyph.glyphs[0].1.x), (7, 3, -1));
+                assert!(glyph.unicode.is_none());
+            },
+            _ => panic!("Invalid order")
+        }
+    }
+
+    #[test]
+    fn test_cache_brush_and_color_table() {
+        let mut decoder = OrderDecoder::new();
+        let orders = decoder.decode(2, &[
+            0x03, 0x00, 0x00, 0x00, 0x00, 0x07,
+            4, 0x01, 8, 8, 0, 1, 0xAA,
+            0x03, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0, 1, 0, 0x10, 0x20, 0x30, 0
+        ]).unwrap();
+        match (secondary(&orders[0]), secondary(&orders[1])) {
+            (SecondaryOrder::CacheBrush(brush), SecondaryOrder::CacheColorTable(table)) => {
+                assert_eq!((brush.cache_index, brush.format, brush.width, brush.height), (4, 1, 8, 8));
+                assert_eq!(brush.data, [0xAA]);
+                assert_eq!(table.entries, [0x302010]);
+            },
+            _ => panic!("Invalid order")
+        }
+    }
+}
@@ -5,6 +5,7 @@ use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
 use model::data::{Message, Component, U16, U32, DynOption, MessageOption, Trame, DataType};
 use std::io::{Write, Read};
 use model::unicode::Unicode;
+use codec::mppc::CompressionType;
 
 /// Security flag send as header flage in core ptotocol
 /// https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-rdpbcgr/e13405c5-668b-4716-94b2-1c2654ca1ad4?redirectedfrom=MSDN
@@ -77,7 +78,7 @@ fn rdp_extended_infos() -> Component {
 /// When CSSP is not used
 /// interactive logon used credentials
 /// present in this payload
-fn rdp_infos(is_extended_info: bool, domain: &String, username: &String, password: &String, auto_logon: bool) -> Component {
+fn rdp_infos(is_extended_info: bool, domain: &String, username: &String, password: &String, auto_logon: bool, compression: Option<CompressionType>) -> Component {
     let mut domain_format = domain.to_unicode();
     domain_format.push(0);
     domain_format.push(0);
@@ -99,7 +100,8 @@ fn rdp_infos(is_extended_info: bool, domain: &String, username: &String, passwor
             InfoFlag::InfoLogonerrors as u32 |
             InfoFlag::InfoDisablectrlaltdel as u32 |
             InfoFlag::InfoEnablewindowskey as u32 |
-            if auto_logon { InfoFlag::InfoAutologon as u32 } else { 0 }
+            if auto_logon { InfoFlag::InfoAutologon as u32 } else { 0 } |
+            if let Some(compression_type) = compression { InfoFlag::InfoCompression as u32 | ((compression_type as u32) << 9) } else { 0 }
         ),
         "cbDomain" => U16::LE((domain_format.len() - 2) as u16),
         "cbUserName" => U16::LE((username_format.len() - 2) as u16),
@@ -136,7 +138,7 @@ fn security_header() -> Component {
 /// let mut mcs = mcs::Client(...).unwrap();
 /// sec::connect(&mut mcs).unwrap();
 /// ```
-pub fn connect<T: Read + Write>(mcs: &mut mcs::Client<T>, domain: &String, username: &String, password: &String, auto_logon: bool) -> RdpResult<()> {
+pub fn connect<T: Read + Write>(mcs: &mut mcs::Client<T>, domain: &String, username: &String, password: &String, auto_logon: bool, compression: Option<CompressionType>) -> RdpResult<()> {
     mcs.write(
         &"global".to_string(),
         trame![
@@ -147,7 +149,8 @@ pub fn connect<T: Read + Write>(mcs: &mut mcs::Client<T>, domain: &String, usern
                 domain,
                 username,
                 password,
-                auto_logon
+                auto_logon,
+                compression
             )
         ]
     )?;
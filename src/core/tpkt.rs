@@ -31,6 +31,34 @@ fn tpkt_header(size: u16) -> Component {
     ]
 }
 
+/// Fast path input header
+///
+/// The number of events is encoded in the header
+/// or in an extra byte if it doesn't fit in 4 bits
+/// Length include the header itself and use 1 or 2 bytes
+///
+/// See MS-RDPBCGR 2.2.8.1.2 Client Fast-Path Input Event PDU
+fn fast_path_header(num_events: u8, size: u16) -> Trame {
+    let (action, extra_num_events) = if num_events < 16 {
+        ((num_events << 2) | Action::FastPathActionFastPath as u8, None)
+    } else {
+        (Action::FastPathActionFastPath as u8, Some(num_events))
+    };
+
+    let size = size + if extra_num_events.is_some() { 1 } else { 0 };
+    let length = if size + 2 < 0x80 {
+        vec![(size + 2) as u8]
+    } else {
+        ((size + 3) | 0x8000).to_be_bytes().to_vec()
+    };
+
+    trame![
+        action,
+        length,
+        extra_num_events
+    ]
+}
+
 /// Client Context of TPKT layer
 ///
 /// # Example
@@ -88,6 +116,18 @@ impl<S: Read + Write> Client<S> {
         )
     }
 
+    /// Send a fast path input message
+    /// with appropriate header
+    pub fn write_fast_path<T>(&mut self, num_events: u8, message: T) -> RdpResult<()>
+    where T: 'static + Message {
+        self.transport.write(
+            &trame![
+                fast_path_header(num_events, message.length() as u16),
+                message
+            ]
+        )
+    }
+
     /// Read a payload from the underlying layer
     /// Check the tpkt header and provide a well
     /// formed payload
@@ -213,6 +253,11 @@ impl<S: Read + Write> Client<S> {
         self.transport.shutdown()
     }
 
+    /// Getter of the underlying link layer
+    pub fn get_link_ref(&self) -> &Link<S> {
+        &self.transport
+    }
+
     #[cfg(feature = "integration")]
     pub fn get_link(self) -> Link<S> {
         self.transport
@@ -223,7 +268,7 @@ impl<S: Read + Write> Client<S> {
 mod test {
     use super::*;
     use std::io::Cursor;
-    use model::data::{U32, DataType};
+    use model::data::{U32, DataType, to_vec};
     use model::link::Stream;
 
     /// Test the tpkt header type in write context
@@ -249,6 +294,13 @@ mod test {
         assert_eq!(cast!(DataType::U8, message["action"]).unwrap(), Action::FastPathActionX224 as u8);
     }
 
+    /// Test fast path header with short and long length
+    #[test]
+    fn test_fast_path_header() {
+        assert_eq!(to_vec(&fast_path_header(1, 2)), [4, 4]);
+        assert_eq!(to_vec(&fast_path_header(16, 200)), [0, 0x80, 204, 16]);
+    }
+
     fn process(data: &[u8]) {
         let cur = Cursor::new(data.to_vec());
         let link = Link::new(Stream::Raw(cur));
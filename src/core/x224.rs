@@ -1,4 +1,5 @@
 use core::tpkt;
+use model::link::Link;
 use model::data::{Message, Check, U16, U32, Component, DataType, Trame};
 use model::error::{Error, RdpError, RdpResult, RdpErrorKind};
 use std::io::{Read, Write};
@@ -145,6 +146,13 @@ impl<S: Read + Write> Client<S> {
         self.transport.write(trame![x224_header(), message])
     }
 
+    /// Send a fast path message
+    /// Fast path messages bypass the x224 layer
+    pub fn write_fast_path<T>(&mut self, num_events: u8, message: T) -> RdpResult<()>
+    where T: 'static + Message {
+        self.transport.write_fast_path(num_events, message)
+    }
+
     /// Start reading an entire X224 paylaod
     /// This function act to return a valid x224 payload
     /// or a fastpath payload coming from directly underlying layer
@@ -250,6 +258,11 @@ impl<S: Read + Write> Client<S> {
         self.selected_protocol
     }
 
+    /// Getter of the underlying link layer
+    pub fn get_link_ref(&self) -> &Link<S> {
+        self.transport.get_link_ref()
+    }
+
     pub fn shutdown(&mut self) -> RdpResult<()> {
         self.transport.shutdown()
     }
@@ -51,12 +51,192 @@ pub enum RdpErrorKind {
     UnexpectedType
 }
 
+/// Declare ErrorInfo with its raw codes
+/// Codes not listed are kept in the Unknown variant
+macro_rules! error_info {
+    ($($(#[$meta:meta])* $name:ident = $code:literal,)*) => {
+        /// Disconnect reason sent by the server
+        /// through the Set Error Info PDU
+        ///
+        /// See MS-RDPBCGR 2.2.5.1.1 Set Error Info PDU Data
+        #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+        pub enum ErrorInfo {
+            $($(#[$meta])* $name,)*
+            /// Code not referenced by the specification
+            Unknown(u32)
+        }
+
+        impl ErrorInfo {
+            /// Map a raw code sent by the server
+            ///
+            /// # Example
+            /// ```
+            /// use rdp::model::error::ErrorInfo;
+            /// assert_eq!(ErrorInfo::from_code(3), ErrorInfo::IdleTimeout);
+            /// assert_eq!(ErrorInfo::from_code(0x1196), ErrorInfo::Unknown(0x1196));
+            /// ```
+            pub fn from_code(code: u32) -> Self {
+                match code {
+                    $($code => ErrorInfo::$name,)*
+                    _ => ErrorInfo::Unknown(code)
+                }
+            }
+
+            /// Raw code as sent by the server
+            ///
+            /// # Example
+            /// ```
+            /// use rdp::model::error::ErrorInfo;
+            /// assert_eq!(ErrorInfo::IdleTimeout.code(), 3);
+            /// assert_eq!(ErrorInfo::Unknown(0x1196).code(), 0x1196);
+            /// ```
+            pub fn code(&self) -> u32 {
+                match self {
+                    $(ErrorInfo::$name => $code,)*
+                    ErrorInfo::Unknown(code) => *code
+                }
+            }
+        }
+    }
+}
+
+error_info! {
+    /// No error
+    None = 0x00000000,
+    // Protocol independent codes
+    RpcInitiatedDisconnect = 0x00000001,
+    RpcInitiatedLogoff = 0x00000002,
+    IdleTimeout = 0x00000003,
+    LogonTimeout = 0x00000004,
+    DisconnectedByOtherConnection = 0x00000005,
+    OutOfMemory = 0x00000006,
+    ServerDeniedConnection = 0x00000007,
+    ServerInsufficientPrivileges = 0x00000009,
+    ServerFreshCredentialsRequired = 0x0000000A,
+    RpcInitiatedDisconnectByUser = 0x0000000B,
+    LogoffByUser = 0x0000000C,
+    CloseStackOnDriverNotReady = 0x0000000F,
+    ServerDwmCrash = 0x00000010,
+    CloseStackOnDriverFailure = 0x00000011,
+    CloseStackOnDriverIfaceFailure = 0x00000012,
+    ServerWinlogonCrash = 0x00000017,
+    ServerCsrssCrash = 0x00000018,
+    ServerShutdown = 0x00000019,
+    ServerReboot = 0x0000001A,
+    // Protocol independent licensing codes
+    LicenseInternal = 0x00000100,
+    LicenseNoLicenseServer = 0x00000101,
+    LicenseNoLicense = 0x00000102,
+    LicenseBadClientMsg = 0x00000103,
+    LicenseHwidDoesntMatchLicense = 0x00000104,
+    LicenseBadClientLicense = 0x00000105,
+    LicenseCantFinishProtocol = 0x00000106,
+    LicenseClientEndedProtocol = 0x00000107,
+    LicenseBadClientEncryption = 0x00000108,
+    LicenseCantUpgradeLicense = 0x00000109,
+    LicenseNoRemoteConnections = 0x0000010A,
+    // Protocol independent codes generated by the Connection Broker
+    CbDestinationNotFound = 0x00000400,
+    CbLoadingDestination = 0x00000402,
+    CbRedirectingToDestination = 0x00000404,
+    CbSessionOnlineVmWake = 0x00000405,
+    CbSessionOnlineVmBoot = 0x00000406,
+    CbSessionOnlineVmNoDns = 0x00000407,
+    CbDestinationPoolNotFree = 0x00000408,
+    CbConnectionCancelled = 0x00000409,
+    CbConnectionErrorInvalidSettings = 0x00000410,
+    CbSessionOnlineVmBootTimeout = 0x00000411,
+    CbSessionOnlineVmSessmonFailed = 0x00000412,
+    // RDP specific codes
+    UnknownPduType2 = 0x000010C9,
+    UnknownPduType = 0x000010CA,
+    DataPduSequence = 0x000010CB,
+    ControlPduSequence = 0x000010CD,
+    InvalidControlPduAction = 0x000010CE,
+    InvalidInputPduType = 0x000010CF,
+    InvalidInputPduMouse = 0x000010D0,
+    InvalidRefreshRectPdu = 0x000010D1,
+    CreateUserDataFailed = 0x000010D2,
+    ConnectFailed = 0x000010D3,
+    ConfirmActiveWrongShareId = 0x000010D4,
+    ConfirmActiveWrongOriginator = 0x000010D5,
+    PersistentKeyPduBadLength = 0x000010DA,
+    PersistentKeyPduIllegalFirst = 0x000010DB,
+    PersistentKeyPduTooManyTotalKeys = 0x000010DC,
+    PersistentKeyPduTooManyCacheKeys = 0x000010DD,
+    InputPduBadLength = 0x000010DE,
+    BitmapCacheErrorPduBadLength = 0x000010DF,
+    SecurityDataTooShort = 0x000010E0,
+    VChannelDataTooShort = 0x000010E1,
+    ShareDataTooShort = 0x000010E2,
+    BadSuppressOutputPdu = 0x000010E3,
+    ConfirmActivePduTooShort = 0x000010E5,
+    CapabilitySetTooSmall = 0x000010E7,
+    CapabilitySetTooLarge = 0x000010E8,
+    NoCursorCache = 0x000010E9,
+    BadCapabilities = 0x000010EA,
+    VirtualChannelDecompressionErr = 0x000010EC,
+    InvalidVcCompressionType = 0x000010ED,
+    InvalidChannelId = 0x000010EF,
+    VChannelsTooMany = 0x000010F0,
+    RemoteAppsNotEnabled = 0x000010F3,
+    CacheCapNotSet = 0x000010F4,
+    BitmapCacheErrorPduBadLength2 = 0x000010F5,
+    OffscrCacheErrorPduBadLength = 0x000010F6,
+    DngCacheErrorPduBadLength = 0x000010F7,
+    GdiPlusPduBadLength = 0x000010F8,
+    SecurityDataTooShort2 = 0x00001111,
+    SecurityDataTooShort3 = 0x00001112,
+    SecurityDataTooShort4 = 0x00001113,
+    SecurityDataTooShort5 = 0x00001114,
+    SecurityDataTooShort6 = 0x00001115,
+    SecurityDataTooShort7 = 0x00001116,
+    SecurityDataTooShort8 = 0x00001117,
+    SecurityDataTooShort9 = 0x00001118,
+    SecurityDataTooShort10 = 0x00001119,
+    SecurityDataTooShort11 = 0x0000111A,
+    SecurityDataTooShort12 = 0x0000111B,
+    SecurityDataTooShort13 = 0x0000111C,
+    SecurityDataTooShort14 = 0x0000111D,
+    SecurityDataTooShort15 = 0x0000111E,
+    SecurityDataTooShort16 = 0x0000111F,
+    SecurityDataTooShort17 = 0x00001120,
+    SecurityDataTooShort18 = 0x00001121,
+    SecurityDataTooShort19 = 0x00001122,
+    SecurityDataTooShort20 = 0x00001123,
+    SecurityDataTooShort21 = 0x00001124,
+    SecurityDataTooShort22 = 0x00001125,
+    SecurityDataTooShort23 = 0x00001126,
+    BadMonitorData = 0x00001129,
+    VcDecompressedReassembleFailed = 0x0000112A,
+    VcDataTooLong = 0x0000112B,
+    BadFrameAckData = 0x0000112C,
+    GraphicsModeNotSupported = 0x0000112D,
+    GraphicsSubsystemResetFailed = 0x0000112E,
+    GraphicsSubsystemFailed = 0x0000112F,
+    TimezoneKeyNameLengthTooShort = 0x00001130,
+    TimezoneKeyNameLengthTooLong = 0x00001131,
+    DynamicDstDisabledFieldMissing = 0x00001132,
+    VcDecodingError = 0x00001133,
+    VirtualDesktopTooLarge = 0x00001134,
+    MonitorGeometryValidationFailed = 0x00001135,
+    InvalidMonitorCount = 0x00001136,
+    UpdateSessionKeyFailed = 0x00001191,
+    DecryptFailed = 0x00001192,
+    EncryptFailed = 0x00001193,
+    EncPkgMismatch = 0x00001194,
+    DecryptFailed2 = 0x00001195,
+}
+
 #[derive(Debug)]
 pub struct RdpError {
     /// Kind of error
     kind: RdpErrorKind,
     /// Associated message of the context
-    message: String
+    message: String,
+    /// Reason sent by the server
+    /// before a disconnection
+    error_info: Option<ErrorInfo>
 }
 
 impl RdpError {
@@ -69,7 +249,25 @@ impl RdpError {
     pub fn new (kind: RdpErrorKind, message: &str) -> Self {
         RdpError {
             kind,
-            message: String::from(message)
+            message: String::from(message),
+            error_info: None
+        }
+    }
+
+    /// create a disconnect error with the reason sent by the server
+    ///
+    /// # Example
+    /// ```
+    /// use rdp::model::error::{RdpError, RdpErrorKind, ErrorInfo};
+    /// let error = RdpError::disconnect(ErrorInfo::IdleTimeout);
+    /// assert_eq!(error.kind(), RdpErrorKind::Disconnect);
+    /// assert_eq!(error.error_info(), Some(ErrorInfo::IdleTimeout))
+    /// ```
+    pub fn disconnect(error_info: ErrorInfo) -> Self {
+        RdpError {
+            kind: RdpErrorKind::Disconnect,
+            message: format!("Server ask for disconnect {:?}", error_info),
+            error_info: Some(error_info)
         }
     }
 
@@ -84,6 +282,12 @@ impl RdpError {
     pub fn kind(&self) -> RdpErrorKind {
         self.kind
     }
+
+    /// Return the reason of the disconnection
+    /// if the server sent one
+    pub fn error_info(&self) -> Option<ErrorInfo> {
+        self.error_info
+    }
 }
 
 #[derive(Debug)]
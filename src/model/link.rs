@@ -1,7 +1,9 @@
 extern crate native_tls;
 
 use model::error::{RdpResult, Error, RdpError, RdpErrorKind};
-use std::io::{Cursor, Read, Write};
+use std::io::{self, Cursor, Read, Write, ErrorKind};
+use std::net::TcpStream;
+use std::time::Duration;
 use self::native_tls::{TlsConnector, TlsStream, Certificate};
 use model::data::{Message};
 
@@ -85,10 +87,32 @@ impl<S: Read + Write> Stream<S> {
     }
 }
 
+/// A stream which can bound the time spent
+/// in a blocking read
+pub trait ReadTimeout {
+    /// Current read timeout of the stream
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+    /// Set the read timeout of the stream
+    /// None means blocking forever
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl ReadTimeout for TcpStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
 /// Link layer is a wrapper around TCP or SSL stream
 /// It can swicth from TCP to SSL
 pub struct Link<S> {
-    stream: Stream<S>
+    stream: Stream<S>,
+    /// Number of bytes read since the link was created
+    bytes_read: u64
 }
 
 impl<S: Read + Write> Link<S> {
@@ -105,7 +129,8 @@ impl<S: Read + Write> Link<S> {
     /// ```
     pub fn new(stream: Stream<S>) -> Self {
         Link {
-            stream
+            stream,
+            bytes_read: 0
         }
     }
 
@@ -153,16 +178,44 @@ impl<S: Read + Write> Link<S> {
         if expected_size == 0 {
             let mut buffer = vec![0; 1500];
             let size = self.stream.read(&mut buffer)?;
+            self.bytes_read += size as u64;
             buffer.resize(size, 0);
             Ok(buffer)
         }
         else {
+            // Count each chunk so a caller can tell
+            // if an error happened in the middle of a message
             let mut buffer = vec![0; expected_size];
-            self.stream.read_exact(&mut buffer)?;
+            let mut offset = 0;
+            while offset < expected_size {
+                match self.stream.read(&mut buffer[offset..]) {
+                    Ok(0) => return Err(Error::Io(io::Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer"))),
+                    Ok(size) => {
+                        offset += size;
+                        self.bytes_read += size as u64;
+                    },
+                    Err(Error::Io(ref e)) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e)
+                }
+            }
             Ok(buffer)
         }
     }
 
+    /// Number of bytes read on the link since its creation
+    ///
+    /// # Example
+    /// ```
+    /// use rdp::model::link::{Link, Stream};
+    /// use std::io::Cursor;
+    /// let mut link = Link::new(Stream::Raw(Cursor::new(vec![0, 1, 2])));
+    /// link.read(2).unwrap();
+    /// assert_eq!(link.get_bytes_read(), 2)
+    /// ```
+    pub fn get_bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
     /// Start a ssl connection from a raw stream
     ///
     /// # Example
@@ -218,3 +271,23 @@ impl<S: Read + Write> Link<S> {
         self.stream
     }
 }
+
+impl<S: Read + Write + ReadTimeout> Link<S> {
+    /// Read timeout of the underlying socket
+    pub fn get_read_timeout(&self) -> RdpResult<Option<Duration>> {
+        Ok(match &self.stream {
+            Stream::Raw(e) => e.read_timeout()?,
+            Stream::Ssl(e) => e.get_ref().read_timeout()?
+        })
+    }
+
+    /// Set the read timeout of the underlying socket
+    /// A read which exceed it will fail with WouldBlock or TimedOut
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> RdpResult<()> {
+        match &self.stream {
+            Stream::Raw(e) => e.set_read_timeout(timeout)?,
+            Stream::Ssl(e) => e.get_ref().set_read_timeout(timeout)?
+        };
+        Ok(())
+    }
+}
@@ -23,4 +23,20 @@ impl Unicode for String {
         }
         return result.into_inner()
     }
-}
\ No newline at end of file
+}
+
+/// Convert an utf-16le buffer into a String
+/// Decoding stop at the first null character
+///
+/// # Example
+/// ```
+/// use rdp::model::unicode::from_unicode;
+/// assert_eq!(from_unicode(&[102, 0, 111, 0, 111, 0, 0, 0, 98, 0]), "foo")
+/// ```
+pub fn from_unicode(buffer: &[u8]) -> String {
+    let utf16: Vec<u16> = buffer.chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|c| *c != 0)
+        .collect();
+    String::from_utf16_lossy(&utf16)
+}